@@ -0,0 +1,51 @@
+//! Renders the default view of `UserSettings::new` with and without the closed-form
+//! cardioid and period-2 bulb test, each with the periodicity check on and off.
+//! Needs a GPU adapter, a software one works too: `cargo bench --bench cardioid_check`
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use mandelbrot_gpu::fractal_renderer::FractalRenderer;
+use mandelbrot_gpu::uniforms::PeriodicityFlags;
+use mandelbrot_gpu::user_settings::UserSettings;
+
+const SIZE: [u32; 2] = [1200, 800];
+
+fn cardioid_check(c: &mut Criterion) {
+    let mut renderer = match FractalRenderer::new() {
+        Ok(renderer) => renderer,
+        Err(error) => {
+            eprintln!("Skipping the benchmark: {error}");
+            return;
+        }
+    };
+
+    let mut group = c.benchmark_group("default view");
+    group.sample_size(10);
+    for periodicity in [false, true] {
+        for cardioid in [false, true] {
+            let mut settings = UserSettings::new();
+            settings
+                .periodicity_flags
+                .set(PeriodicityFlags::CHECK, periodicity);
+            settings.cardioid_check = cardioid;
+            let uniforms = settings.uniforms();
+
+            let name = format!(
+                "periodicity {}, cardioid {}",
+                if periodicity { "on" } else { "off" },
+                if cardioid { "on" } else { "off" },
+            );
+            // Includes the readback of the image, the same for every variant
+            group.bench_function(name, |b| {
+                b.iter(|| {
+                    renderer
+                        .render_to_image(&uniforms, SIZE[0], SIZE[1])
+                        .expect("Failed to render")
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cardioid_check);
+criterion_main!(benches);
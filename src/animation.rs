@@ -0,0 +1,245 @@
+//! Keyframe animation of the view, played back in the window and exported as numbered frames,
+//! and short seamless loops for animated GIF and APNG images
+
+use crate::user_settings::UserSettings;
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Highest frame rate of the exported frame sequences
+pub const MAX_ANIMATION_FPS: u32 = 120;
+
+/// Most frames of a loop, a GIF keeps every frame in the file
+pub const MAX_LOOP_FRAMES: u32 = 1000;
+
+bitflags! {
+    /// Pace of the transition from the previous keyframe
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Easing: u32 {
+        const LINEAR = 1;
+        const EASE_IN = 2;
+        const EASE_OUT = 4;
+        const EASE_IN_OUT = 8;
+    }
+}
+
+impl Display for Easing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if self.contains(Self::LINEAR) {
+            parts.push("Равномерно");
+        }
+        if self.contains(Self::EASE_IN) {
+            parts.push("Разгон");
+        }
+        if self.contains(Self::EASE_OUT) {
+            parts.push("Торможение");
+        }
+        if self.contains(Self::EASE_IN_OUT) {
+            parts.push("Разгон и торможение");
+        }
+
+        if parts.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+bitflags! {
+    /// What changes during a loop, several changes run at once
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct LoopKind: u32 {
+        /// The palette offset goes once around the palette
+        const PALETTE_CYCLE = 1;
+        /// The initial value goes once around a circle centered at its current value
+        const JULIA_ORBIT = 2;
+        /// The exponent goes from the lower to the upper bound of its animation and back
+        const POW_MORPH = 4;
+    }
+}
+
+impl Display for LoopKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if self.contains(Self::PALETTE_CYCLE) {
+            parts.push("Цикл палитры");
+        }
+        if self.contains(Self::JULIA_ORBIT) {
+            parts.push("Орбита начального значения");
+        }
+        if self.contains(Self::POW_MORPH) {
+            parts.push("Изменение степени");
+        }
+
+        if parts.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+impl Easing {
+    /// Maps the share of the elapsed transition time to the share of the way, both in [0..=1]
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        if self.contains(Self::EASE_IN_OUT) {
+            t * t * (3.0 - 2.0 * t)
+        } else if self.contains(Self::EASE_IN) {
+            t * t
+        } else if self.contains(Self::EASE_OUT) {
+            t * (2.0 - t)
+        } else {
+            t
+        }
+    }
+}
+
+/// The animated part of the settings at one moment
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keyframe {
+    pub center: [f32; 2],
+    pub zoom: f32,
+    pub initial_value: [f32; 2],
+    pub palette_offset: f32,
+    pub pow: f32,
+    /// Seconds of the transition from the previous keyframe, unused for the first one
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl Default for Keyframe {
+    fn default() -> Self {
+        Self::capture(&UserSettings::new())
+    }
+}
+
+impl Keyframe {
+    /// Keyframe of the current view
+    pub fn capture(settings: &UserSettings) -> Self {
+        Self {
+            center: [settings.center_x, settings.center_y],
+            zoom: settings.zoom,
+            initial_value: [settings.initial_value_x, settings.initial_value_y],
+            palette_offset: settings.palette_offset,
+            pow: settings.pow,
+            duration: 2.0,
+            easing: Easing::EASE_IN_OUT,
+        }
+    }
+
+    /// Shows the keyframe, the other settings stay as they are
+    pub fn apply(&self, settings: &mut UserSettings) {
+        [settings.center_x, settings.center_y] = self.center;
+        settings.zoom = self.zoom;
+        [settings.initial_value_x, settings.initial_value_y] = self.initial_value;
+        settings.palette_offset = self.palette_offset;
+        settings.pow = self.pow;
+    }
+
+    /// The view at `t` of the transition from `self` to `next`, `t` in [0..=1] after easing.
+    /// The zoom changes by the same factor in equal steps. The center moves at a constant speed
+    /// on the screen rather than in the plane, so deep zooms do not rush past the target
+    pub fn interpolate(&self, next: &Keyframe, t: f32) -> Keyframe {
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+
+        let ratio = next.zoom / self.zoom;
+        let zoom = self.zoom * ratio.powf(t);
+        // Speed in the plane is proportional to 1 / zoom, integrated over the transition
+        let way = if (ratio - 1.0).abs() < 1e-4 {
+            t
+        } else {
+            (1.0 - ratio.powf(-t)) / (1.0 - 1.0 / ratio)
+        };
+        let center = [0, 1].map(|i| self.center[i] + (next.center[i] - self.center[i]) * way);
+
+        Keyframe {
+            center,
+            zoom,
+            initial_value: [0, 1].map(|i| lerp(self.initial_value[i], next.initial_value[i])),
+            palette_offset: lerp(self.palette_offset, next.palette_offset),
+            pow: lerp(self.pow, next.pow),
+            ..*self
+        }
+    }
+}
+
+/// Seconds from the first keyframe to the last
+pub fn duration(keyframes: &[Keyframe]) -> f32 {
+    keyframes
+        .iter()
+        .skip(1)
+        .map(|keyframe| keyframe.duration.max(0.0))
+        .sum()
+}
+
+/// The view `time` seconds after the first keyframe, the last keyframe once the time is over
+pub fn view_at(keyframes: &[Keyframe], time: f32) -> Option<Keyframe> {
+    let mut start = 0.0;
+    for (from, to) in keyframes.iter().zip(keyframes.iter().skip(1)) {
+        let duration = to.duration.max(0.0);
+        if time < start + duration {
+            let t = to.easing.apply((time - start) / duration);
+            return Some(from.interpolate(to, t));
+        }
+        start += duration;
+    }
+    keyframes.last().copied()
+}
+
+/// Frames of the whole animation at `fps`, the first and the last keyframe included
+pub fn frame_count(keyframes: &[Keyframe], fps: u32) -> u32 {
+    if keyframes.is_empty() {
+        return 0;
+    }
+    (duration(keyframes) * fps as f32).round() as u32 + 1
+}
+
+/// Settings of frame `index` of the animation
+pub fn frame_settings(
+    settings: &UserSettings,
+    keyframes: &[Keyframe],
+    fps: u32,
+    index: u32,
+) -> UserSettings {
+    let mut settings = settings.clone();
+    if let Some(view) = view_at(keyframes, index as f32 / fps.max(1) as f32) {
+        view.apply(&mut settings);
+    }
+    settings
+}
+
+/// `frame_00042.png`, numbered from 0 so that video encoders pick up the sequence
+pub fn frame_path(folder: &Path, index: u32) -> PathBuf {
+    folder.join(format!("frame_{index:05}.png"))
+}
+
+/// Settings of frame `index` of a loop of `settings.loop_frames` frames. The frame after the
+/// last one would be the first again, so the loop plays without a jump
+pub fn loop_frame_settings(settings: &UserSettings, index: u32) -> UserSettings {
+    let mut frame = settings.clone();
+    let t = index as f32 / settings.loop_frames.max(1) as f32;
+    let kind = settings.loop_kind;
+
+    if kind.contains(LoopKind::PALETTE_CYCLE) {
+        frame.palette_offset = (settings.palette_offset + t).fract();
+    }
+    if kind.contains(LoopKind::JULIA_ORBIT) {
+        let angle = TAU * t;
+        frame.initial_value_x += settings.loop_orbit_radius * angle.cos();
+        frame.initial_value_y += settings.loop_orbit_radius * angle.sin();
+    }
+    if kind.contains(LoopKind::POW_MORPH) {
+        let low = settings.pow_animation_from;
+        let high = settings.pow_animation_to;
+        frame.pow = low + (high - low) * (1.0 - (TAU * t).cos()) / 2.0;
+    }
+    frame
+}
@@ -0,0 +1,101 @@
+//! Renders images without a window, one per job.
+//! Run with `--help` for the options and the exit codes.
+
+use anyhow::Context;
+use mandelbrot_gpu::cli::{
+    EXIT_JOB_FAILED, EXIT_NO_ADAPTER, EXIT_USAGE, HELP, Job, parse_command_line,
+};
+use mandelbrot_gpu::fractal_renderer::{FractalRenderer, RendererError};
+use mandelbrot_gpu::image_export::{save_raw_data, save_rendered_png};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Instant;
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let command_line = match parse_command_line(std::env::args().skip(1)) {
+        Ok(command_line) => command_line,
+        Err(error) => {
+            eprintln!("error: {error:#}\n\nRun with --help for the usage");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    if command_line.help {
+        print!("{HELP}");
+        return ExitCode::SUCCESS;
+    }
+
+    let jobs = match command_line.jobs() {
+        Ok(jobs) => jobs,
+        Err(error) => {
+            eprintln!("error: {error:#}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let mut renderer = match FractalRenderer::new() {
+        Ok(renderer) => renderer,
+        Err(error @ RendererError::NoAdapter(_)) => {
+            eprintln!("error: {error}");
+            return ExitCode::from(EXIT_NO_ADAPTER);
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::from(EXIT_JOB_FAILED);
+        }
+    };
+    let adapter = renderer.adapter_info();
+    log::info!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+
+    let mut failed = 0;
+    for job in &jobs {
+        let started = Instant::now();
+        match render_job(&mut renderer, job) {
+            Ok(paths) => {
+                let paths: Vec<_> = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                log::info!("Wrote {} in {:.2?}", paths.join(", "), started.elapsed());
+            }
+            Err(error) => {
+                failed += 1;
+                eprintln!("error: {error:#}");
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{failed} of {} jobs failed", jobs.len());
+        return ExitCode::from(EXIT_JOB_FAILED);
+    }
+    ExitCode::SUCCESS
+}
+
+fn render_job(renderer: &mut FractalRenderer, job: &Job) -> anyhow::Result<Vec<PathBuf>> {
+    let settings = job.settings()?;
+
+    if let Some(path) = &job.save_params {
+        let json = serde_json::to_string_pretty(&settings)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    let [width, height] = job.size.unwrap_or([1200, 800]);
+    let mut paths = vec![];
+    if let Some(output) = &job.output {
+        let supersampling = job.supersampling.unwrap_or(1);
+        save_rendered_png(renderer, &settings, width, height, supersampling, output)
+            .with_context(|| format!("failed to render {}", output.display()))?;
+        paths.push(output.clone());
+    }
+    if let Some(output) = &job.raw_output {
+        paths.extend(
+            save_raw_data(renderer, &settings, width, height, output)
+                .with_context(|| format!("failed to write {}", output.display()))?,
+        );
+    }
+
+    Ok(paths)
+}
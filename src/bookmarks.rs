@@ -0,0 +1,86 @@
+//! Named views kept in a JSON file, which can also be shared as is
+
+use crate::user_settings::UserSettings;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Version of the file format, files of newer versions are refused
+pub const BOOKMARKS_VERSION: u32 = 1;
+
+/// File name of the bookmarks in the storage folder of the app
+pub const BOOKMARKS_FILE_NAME: &str = "bookmarks.json";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    /// Fields of [`UserSettings::image_parameters`]
+    pub parameters: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookmarksFile {
+    version: u32,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Bookmark {
+    pub fn capture(name: String, settings: &UserSettings) -> Self {
+        Self {
+            name,
+            parameters: serde_json::from_str(&settings.image_parameters())
+                .expect("Image parameters are always valid JSON"),
+        }
+    }
+
+    /// Copy of `settings` showing the bookmarked view, see [`UserSettings::with_image_parameters`]
+    pub fn settings(&self, settings: &UserSettings) -> Result<UserSettings, serde_json::Error> {
+        settings.with_image_parameters(&self.parameters.to_string())
+    }
+}
+
+pub fn read_bookmarks(reader: impl Read) -> Result<Vec<Bookmark>, serde_json::Error> {
+    let file: BookmarksFile = serde_json::from_reader(reader)?;
+    if file.version > BOOKMARKS_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "bookmarks version {} is newer than the supported {BOOKMARKS_VERSION}",
+            file.version
+        )));
+    }
+    Ok(file.bookmarks)
+}
+
+/// Writes indented JSON, so that the file can be read and edited by hand
+pub fn write_bookmarks(
+    bookmarks: &[Bookmark],
+    writer: impl Write,
+) -> Result<(), serde_json::Error> {
+    serde_json::to_writer_pretty(
+        writer,
+        &BookmarksFile {
+            version: BOOKMARKS_VERSION,
+            bookmarks: bookmarks.to_vec(),
+        },
+    )
+}
+
+/// Bookmarks of the file, none if it does not exist yet
+pub fn load_bookmarks(path: &Path) -> std::io::Result<Vec<Bookmark>> {
+    match File::open(path) {
+        Ok(file) => Ok(read_bookmarks(BufReader::new(file))?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(error),
+    }
+}
+
+/// Writes the bookmarks, creating the folder of the file if needed
+pub fn save_bookmarks(bookmarks: &[Bookmark], path: &Path) -> std::io::Result<()> {
+    if let Some(folder) = path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_bookmarks(bookmarks, &mut writer)?;
+    writer.flush()
+}
@@ -0,0 +1,320 @@
+//! Command line of `mandelbrot_render`, which renders images without a window, one per job
+
+use crate::image_export::{MAX_SUPERSAMPLING, RAW_DATA_EXTENSIONS, read_parameters};
+use crate::uniforms::{FractalColorScheme, FractalType};
+use crate::user_settings::UserSettings;
+use anyhow::{Context, anyhow, bail};
+use std::path::{Path, PathBuf};
+
+/// Printed for `--help`, lists the options and the exit codes
+pub const HELP: &str = "\
+Renders the Mandelbrot set and its relatives into PNG images without a window
+
+Usage: mandelbrot_render [OPTIONS] --output FILE
+       mandelbrot_render [OPTIONS] --raw-output FILE
+       mandelbrot_render [OPTIONS] --jobs FILE
+
+Options:
+  -p, --params FILE          Start from a parameter file or a PNG rendered by this program
+                             instead of the default view
+  -c, --center X,Y           Center of the view
+  -z, --zoom ZOOM            Zoom, 1 shows 3 x 2 units of the plane
+  -i, --iterations N         Iteration limit
+  -f, --fractal TYPE         Fractal type, a variant and a mode joined by +, e.g. newton,
+                             julia or julia+burning-ship
+      --color-scheme SCHEME  hsv or rgb
+  -s, --size WxH             Image size in pixels [default: 1200x800], large images are
+                             rendered in tiles and streamed into the file
+      --supersampling N      Render N x N samples per pixel [default: 1]
+  -o, --output FILE          PNG file to write
+      --raw-output FILE      Also or instead write the raw escape time values: iterations,
+                             smooth iterations, distance estimate and the last z.
+                             FILE.npy is a float32 NumPy array of shape (height, width, 5),
+                             FILE.exr has a float channel each and FILE.png becomes one
+                             16-bit PNG per channel with its value range in tEXt chunks
+      --save-params FILE     Also write the parameters of the job as a parameter file
+  -j, --jobs FILE            Render one job per line, each line holds the options above.
+                             Options given on the command line apply to every job.
+                             Values with spaces go in double or single quotes
+  -h, --help                 Print this help
+
+Parameter files are JSON with the fields of the settings window, missing fields keep
+their defaults.
+
+Exit codes:
+  0  every image was written
+  1  some jobs failed, the others were written
+  2  invalid command line or jobs file
+  3  no graphics adapter available
+";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Some jobs failed, the others were written
+pub const EXIT_JOB_FAILED: u8 = 1;
+/// Invalid command line or jobs file
+pub const EXIT_USAGE: u8 = 2;
+/// No graphics adapter available
+pub const EXIT_NO_ADAPTER: u8 = 3;
+
+/// Options of one job, unset ones fall back to the parameter file or the defaults
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Job {
+    pub params: Option<PathBuf>,
+    pub center: Option<[f32; 2]>,
+    pub zoom: Option<f32>,
+    pub iterations: Option<u32>,
+    pub fractal_type: Option<FractalType>,
+    pub color_scheme: Option<FractalColorScheme>,
+    pub size: Option<[u32; 2]>,
+    pub supersampling: Option<u32>,
+    pub output: Option<PathBuf>,
+    pub raw_output: Option<PathBuf>,
+    pub save_params: Option<PathBuf>,
+}
+
+/// Options of the command line, the job holds the options shared by every job
+#[derive(Debug)]
+pub struct CommandLine {
+    pub job: Job,
+    pub jobs_file: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Job {
+    pub fn has_output(&self) -> bool {
+        self.output.is_some() || self.raw_output.is_some()
+    }
+
+    /// Settings of the parameter file or the defaults with the options of the job on top
+    pub fn settings(&self) -> anyhow::Result<UserSettings> {
+        let mut settings = match &self.params {
+            Some(path) => {
+                let bytes = std::fs::read(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let parameters = if bytes.starts_with(PNG_SIGNATURE) {
+                    read_parameters(bytes.as_slice())
+                        .with_context(|| format!("invalid PNG {}", path.display()))?
+                        .with_context(|| format!("{} has no embedded parameters", path.display()))?
+                } else {
+                    String::from_utf8(bytes)
+                        .with_context(|| format!("invalid parameter file {}", path.display()))?
+                };
+                UserSettings::new()
+                    .with_image_parameters(&parameters)
+                    .with_context(|| format!("invalid parameters in {}", path.display()))?
+            }
+            None => UserSettings::new(),
+        };
+
+        if let Some([x, y]) = self.center {
+            settings.center_x = x;
+            settings.center_y = y;
+        }
+        if let Some(zoom) = self.zoom {
+            settings.zoom = zoom;
+        }
+        if let Some(iterations) = self.iterations {
+            settings.max_iter = iterations;
+        }
+        if let Some(fractal_type) = self.fractal_type {
+            settings.fractal_type = fractal_type;
+        }
+        if let Some(color_scheme) = self.color_scheme {
+            settings.color_scheme = color_scheme;
+        }
+        // Automatic iterations need the statistics of earlier frames, a single image has none
+        settings.auto_iterations = false;
+
+        Ok(settings)
+    }
+}
+
+impl CommandLine {
+    /// The jobs of the jobs file or the single job of the command line
+    pub fn jobs(&self) -> anyhow::Result<Vec<Job>> {
+        match &self.jobs_file {
+            Some(path) => read_jobs(path, &self.job),
+            None if self.job.has_output() => Ok(vec![self.job.clone()]),
+            None => Err(anyhow!(
+                "either --output, --raw-output or --jobs is required"
+            )),
+        }
+    }
+}
+
+/// Each line of the file is one job on top of the options of the command line, split into
+/// arguments by [`split_arguments`]. Empty lines and lines starting with `#` are skipped
+pub fn read_jobs(path: &Path, base: &Job) -> anyhow::Result<Vec<Job>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let mut jobs = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut job = base.clone();
+        let mut args = split_arguments(line)
+            .with_context(|| format!("{}:{}", path.display(), index + 1))?
+            .into_iter();
+        while let Some(arg) = args.next() {
+            let known = parse_job_option(&mut job, &arg, &mut args)
+                .with_context(|| format!("{}:{}", path.display(), index + 1))?;
+            if !known {
+                bail!("{}:{}: unknown option {arg}", path.display(), index + 1);
+            }
+        }
+        if !job.has_output() {
+            bail!("{}:{}: missing --output", path.display(), index + 1);
+        }
+        jobs.push(job);
+    }
+
+    Ok(jobs)
+}
+
+/// Splits a line at whitespace outside of quotes, `"my file.png"` and `'my file.png'` are one
+/// argument without the quotes. There are no escapes, a quote of the other kind is kept as is
+pub fn split_arguments(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    for character in line.chars() {
+        match quote {
+            Some(open) if character == open => quote = None,
+            Some(_) => arg.get_or_insert_default().push(character),
+            None if character == '"' || character == '\'' => {
+                quote = Some(character);
+                arg.get_or_insert_default();
+            }
+            None if character.is_whitespace() => args.extend(arg.take()),
+            None => arg.get_or_insert_default().push(character),
+        }
+    }
+    if let Some(open) = quote {
+        bail!("missing closing {open}");
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+pub fn parse_command_line(mut args: impl Iterator<Item = String>) -> anyhow::Result<CommandLine> {
+    let mut command_line = CommandLine {
+        job: Job::default(),
+        jobs_file: None,
+        help: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => command_line.help = true,
+            "-j" | "--jobs" => command_line.jobs_file = Some(value(&arg, &mut args)?.into()),
+            _ => {
+                if !parse_job_option(&mut command_line.job, &arg, &mut args)? {
+                    bail!("unknown option {arg}");
+                }
+            }
+        }
+    }
+
+    Ok(command_line)
+}
+
+/// Returns `false` when `arg` is not an option of a job
+fn parse_job_option(
+    job: &mut Job,
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> anyhow::Result<bool> {
+    match arg {
+        "-p" | "--params" => job.params = Some(value(arg, args)?.into()),
+        "-c" | "--center" => {
+            let [x, y] = pair(&value(arg, args)?, ',')?;
+            job.center = Some([x, y]);
+        }
+        "-z" | "--zoom" => job.zoom = Some(number(arg, &value(arg, args)?)?),
+        "-i" | "--iterations" => job.iterations = Some(number(arg, &value(arg, args)?)?),
+        "-f" | "--fractal" => job.fractal_type = Some(fractal_type(&value(arg, args)?)?),
+        "--color-scheme" => {
+            let name = value(arg, args)?;
+            job.color_scheme = Some(
+                FractalColorScheme::from_name(&flag_name(&name))
+                    .with_context(|| format!("unknown color scheme {name}"))?,
+            );
+        }
+        "-s" | "--size" => {
+            let [width, height] = pair(&value(arg, args)?, 'x')?;
+            if width == 0 || height == 0 {
+                bail!("--size must not be empty");
+            }
+            job.size = Some([width, height]);
+        }
+        "--supersampling" => {
+            let supersampling = number(arg, &value(arg, args)?)?;
+            if !(1..=MAX_SUPERSAMPLING).contains(&supersampling) {
+                bail!("--supersampling must be between 1 and {MAX_SUPERSAMPLING}");
+            }
+            job.supersampling = Some(supersampling);
+        }
+        "-o" | "--output" => job.output = Some(value(arg, args)?.into()),
+        "--raw-output" => {
+            let path = PathBuf::from(value(arg, args)?);
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            if !RAW_DATA_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+                bail!(
+                    "--raw-output must end with .{}",
+                    RAW_DATA_EXTENSIONS.join(", .")
+                );
+            }
+            job.raw_output = Some(path);
+        }
+        "--save-params" => job.save_params = Some(value(arg, args)?.into()),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn value(arg: &str, args: &mut impl Iterator<Item = String>) -> anyhow::Result<String> {
+    args.next().with_context(|| format!("{arg} needs a value"))
+}
+
+fn number<T: std::str::FromStr>(arg: &str, text: &str) -> anyhow::Result<T> {
+    text.parse()
+        .map_err(|_| anyhow!("{arg}: {text} is not a valid number"))
+}
+
+fn pair<T: std::str::FromStr>(text: &str, separator: char) -> anyhow::Result<[T; 2]> {
+    let (first, second) = text
+        .split_once(separator)
+        .with_context(|| format!("expected two values separated by '{separator}', got {text}"))?;
+    Ok([number(text, first.trim())?, number(text, second.trim())?])
+}
+
+/// `burning-ship` names the flag `BURNING_SHIP`
+fn flag_name(name: &str) -> String {
+    name.trim().to_uppercase().replace('-', "_")
+}
+
+/// Parses a variant and a mode joined by `+`, the mode defaults to Mandelbrot.
+/// Like in the settings window at most one of each can be chosen
+pub fn fractal_type(text: &str) -> anyhow::Result<FractalType> {
+    let mut fractal_type = FractalType::empty();
+    for name in text.split('+') {
+        let flag = FractalType::from_name(&flag_name(name))
+            .with_context(|| format!("unknown fractal type {name}"))?;
+        if !fractal_type.mode().is_empty() && !flag.mode().is_empty() {
+            bail!("{text} has more than one of mandelbrot and julia");
+        }
+        if !fractal_type.variant().is_empty() && !flag.variant().is_empty() {
+            bail!("{text} has more than one variant");
+        }
+        fractal_type |= flag;
+    }
+    if fractal_type.mode().is_empty() {
+        fractal_type |= FractalType::MANDELBROT;
+    }
+    Ok(fractal_type)
+}
@@ -0,0 +1,189 @@
+//! A small expression language for iteration formulas, e.g. `z^2 + c` or `sin(z) * p1 + c`.
+//!
+//! Supports `+ - * / ^`, implicit multiplication (`2z`), the constants `i`, `pi` and `e`,
+//! the parameters `p1..p8` and the functions `pow exp log sqrt sin cos tan sinh cosh
+//! abs arg conj real imag`. Formulas are compiled to a WGSL expression that is spliced
+//! into the shader, either as the iteration step or as the function of the domain coloring.
+//! Formulas also arrive from saved sessions, images and shared locations, so their length and
+//! nesting are limited instead of overflowing the stack.
+
+mod codegen;
+mod lexer;
+mod parser;
+
+use std::fmt::{Display, Formatter};
+
+/// Number of complex parameters `p1..pN` available to formulas
+pub const MAX_FORMULA_PARAMS: usize = 8;
+
+pub const DEFAULT_FORMULA: &str = "z^2 + c";
+
+pub const DEFAULT_DOMAIN_FUNCTION: &str = "(z^2 - 1)(z - 2 - i)^2 / (z^2 + 2 + 2i)";
+
+/// Functions offered in the domain coloring mode, as formula sources
+pub const DOMAIN_FUNCTION_EXAMPLES: [&str; 8] = [
+    DEFAULT_DOMAIN_FUNCTION,
+    "z",
+    "z^3 - 1",
+    "1 / z",
+    "sin(z)",
+    "exp(1 / z)",
+    "log(z)",
+    "sqrt(z^2 - 1)",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaError {
+    /// Character offset in the source
+    pub position: usize,
+    pub message: String,
+}
+
+impl FormulaError {
+    fn new(position: usize, message: String) -> Self {
+        Self { position, message }
+    }
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "позиция {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledFormula {
+    /// WGSL expression of type `Complex`
+    pub wgsl: String,
+    /// Zero-based indices of the parameters referenced by the formula, in order of appearance
+    pub used_params: Vec<usize>,
+}
+
+/// Compiles an iteration formula over the variables `z` and `c`
+pub fn compile(source: &str) -> Result<CompiledFormula, FormulaError> {
+    compile_with_variables(source, &["z", "c"])
+}
+
+/// Compiles a function of `z` alone, plotted by the domain coloring mode
+pub fn compile_function(source: &str) -> Result<CompiledFormula, FormulaError> {
+    compile_with_variables(source, &["z"])
+}
+
+fn compile_with_variables(
+    source: &str,
+    variables: &[&str],
+) -> Result<CompiledFormula, FormulaError> {
+    let tokens = lexer::tokenize(source)?;
+    let expr = parser::parse(&tokens)?;
+
+    let mut generator = codegen::Generator {
+        variables,
+        used_params: vec![],
+        depth: 0,
+    };
+    let wgsl = generator.generate(&expr)?;
+
+    Ok(CompiledFormula {
+        wgsl,
+        used_params: generator.used_params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wgsl(source: &str) -> String {
+        compile(source).unwrap().wgsl
+    }
+
+    fn error(source: &str) -> FormulaError {
+        compile(source).unwrap_err()
+    }
+
+    #[test]
+    fn generates_wgsl() {
+        assert_eq!(wgsl("z^2 + c"), "sum(complex_pow(z, 2.0), c)");
+        assert_eq!(
+            wgsl("sin(z) * p1 + c"),
+            "sum(mul(c_sin(z), formula_param(0u)), c)"
+        );
+        assert_eq!(
+            wgsl("2z - pi"),
+            "sub(mul(Complex(2.0, 0.0), z), Complex(PI, 0.0))"
+        );
+        assert_eq!(wgsl("-z^-1"), "neg(complex_pow(z, (-1.0)))");
+        assert_eq!(wgsl("pow(z, c)"), "c_pow(z, c)");
+        assert_eq!(wgsl("z^2.5"), "complex_pow(z, 2.5)");
+    }
+
+    #[test]
+    fn reals_stay_real_until_needed() {
+        assert_eq!(wgsl("conj(2) * e"), "Complex((2.0 * E), 0.0)");
+        assert_eq!(
+            wgsl("abs(z) + real(c)"),
+            "Complex((norm_sqrt(z) + (c).re), 0.0)"
+        );
+        assert_eq!(wgsl("exp(1)"), "Complex(exp(1.0), 0.0)");
+        assert_eq!(wgsl("exp(z)"), "c_exp(z)");
+        // Roots and logarithms of negative reals are complex
+        assert_eq!(wgsl("sqrt(-1)"), "c_sqrt(Complex((-1.0), 0.0))");
+        assert_eq!(wgsl("imag(2)"), "Complex(0.0, 0.0)");
+    }
+
+    #[test]
+    fn lists_used_parameters_once() {
+        let compiled = compile("p3 + p1 + p3").unwrap();
+        assert_eq!(compiled.used_params, vec![2, 0]);
+        assert_eq!(
+            compiled.wgsl,
+            "sum(sum(formula_param(2u), formula_param(0u)), formula_param(2u))"
+        );
+        assert!(compile("p8").is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        let unknown = error("foo(z) + c");
+        assert_eq!(unknown.position, 0);
+        assert_eq!(unknown.message, "неизвестная функция «foo»");
+
+        let arity = error("z + sin(z, c)");
+        assert_eq!(arity.position, 4);
+        assert_eq!(
+            arity.message,
+            "функция «sin» принимает аргументов: 1, передано: 2"
+        );
+        assert_eq!(error("pow(z)").position, 0);
+
+        let out_of_range = error("z + p9");
+        assert_eq!(out_of_range.position, 4);
+        assert_eq!(out_of_range.message, "неизвестная переменная «p9»");
+        assert_eq!(error("p0").position, 0);
+
+        // A domain function has no c
+        let no_c = compile_function("z + c").unwrap_err();
+        assert_eq!(no_c.position, 4);
+        assert!(compile_function("z^2 - 1").is_ok());
+    }
+
+    #[test]
+    fn reports_positions_in_characters() {
+        // Non-ASCII characters take several bytes each
+        let unexpected = error("z·z");
+        assert_eq!(unexpected.position, 1);
+        assert_eq!(unexpected.to_string(), "позиция 2: неожиданный символ «·»");
+
+        assert_eq!(error("z + 1e39").position, 4);
+        assert_eq!(error("sin(я)").position, 4);
+    }
+
+    #[test]
+    fn limits_the_depth() {
+        let sum = vec!["z"; codegen::MAX_DEPTH].join("+");
+        assert!(compile(&sum).is_ok());
+        assert!(compile(&(sum + "+z")).is_err());
+    }
+}
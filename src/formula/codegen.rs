@@ -0,0 +1,226 @@
+use crate::formula::parser::{BinaryOperator, Expr, ExprKind};
+use crate::formula::{FormulaError, MAX_FORMULA_PARAMS};
+
+/// Deepest tree of operations. The WGSL expression nests as deep, and the shader compiler
+/// recurses through it with a large stack frame per level
+pub const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Real,
+    Complex,
+}
+
+/// WGSL expression together with its type
+struct Typed {
+    code: String,
+    ty: Type,
+}
+
+impl Typed {
+    fn real(code: String) -> Self {
+        Self {
+            code,
+            ty: Type::Real,
+        }
+    }
+
+    fn complex(code: String) -> Self {
+        Self {
+            code,
+            ty: Type::Complex,
+        }
+    }
+
+    /// Reals are implicitly promoted wherever a complex value is expected
+    fn into_complex(self) -> String {
+        match self.ty {
+            Type::Real => format!("Complex({}, 0.0)", self.code),
+            Type::Complex => self.code,
+        }
+    }
+}
+
+pub struct Generator<'a> {
+    /// Complex variables provided by the caller, e.g. `z` and `c`
+    pub variables: &'a [&'a str],
+    /// Indices of `p1..pN` referenced by the formula
+    pub used_params: Vec<usize>,
+    /// Depth of the node being generated
+    pub depth: usize,
+}
+
+impl Generator<'_> {
+    /// Type-checks the expression and returns WGSL code that evaluates to `Complex`
+    pub fn generate(&mut self, expr: &Expr) -> Result<String, FormulaError> {
+        Ok(self.expression(expr)?.into_complex())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<Typed, FormulaError> {
+        if self.depth == MAX_DEPTH {
+            return Err(FormulaError::new(
+                expr.position,
+                format!("больше {MAX_DEPTH} вложенных операций"),
+            ));
+        }
+        self.depth += 1;
+        let result = self.node(expr);
+        self.depth -= 1;
+        result
+    }
+
+    fn node(&mut self, expr: &Expr) -> Result<Typed, FormulaError> {
+        match &expr.kind {
+            ExprKind::Number(value) => Ok(Typed::real(float_literal(*value, expr.position)?)),
+            ExprKind::Variable(name) => self.variable(name, expr.position),
+            ExprKind::Negate(operand) => {
+                let operand = self.expression(operand)?;
+                Ok(match operand.ty {
+                    Type::Real => Typed::real(format!("(-{})", operand.code)),
+                    Type::Complex => Typed::complex(format!("neg({})", operand.code)),
+                })
+            }
+            ExprKind::Binary { operator, lhs, rhs } => {
+                let lhs = self.expression(lhs)?;
+                let rhs = self.expression(rhs)?;
+                Ok(binary(*operator, lhs, rhs))
+            }
+            ExprKind::Call {
+                function,
+                arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call(function, arguments, expr.position)
+            }
+        }
+    }
+
+    fn variable(&mut self, name: &str, position: usize) -> Result<Typed, FormulaError> {
+        if self.variables.contains(&name) {
+            return Ok(Typed::complex(name.to_string()));
+        }
+
+        match name {
+            "i" => return Ok(Typed::complex("Complex(0.0, 1.0)".to_string())),
+            "pi" => return Ok(Typed::real("PI".to_string())),
+            "e" => return Ok(Typed::real("E".to_string())),
+            _ => {}
+        }
+
+        if let Some(index) = name
+            .strip_prefix('p')
+            .and_then(|index| index.parse::<usize>().ok())
+            .filter(|index| (1..=MAX_FORMULA_PARAMS).contains(index))
+        {
+            let index = index - 1;
+            if !self.used_params.contains(&index) {
+                self.used_params.push(index);
+            }
+            return Ok(Typed::complex(format!("formula_param({index}u)")));
+        }
+
+        Err(FormulaError::new(
+            position,
+            format!("неизвестная переменная «{name}»"),
+        ))
+    }
+}
+
+fn float_literal(value: f64, position: usize) -> Result<String, FormulaError> {
+    let value = value as f32;
+    if !value.is_finite() {
+        return Err(FormulaError::new(
+            position,
+            "число не помещается в f32".to_string(),
+        ));
+    }
+    Ok(format!("{value:?}"))
+}
+
+fn binary(operator: BinaryOperator, lhs: Typed, rhs: Typed) -> Typed {
+    let both_real = lhs.ty == Type::Real && rhs.ty == Type::Real;
+    match operator {
+        BinaryOperator::Add if both_real => Typed::real(format!("({} + {})", lhs.code, rhs.code)),
+        BinaryOperator::Sub if both_real => Typed::real(format!("({} - {})", lhs.code, rhs.code)),
+        BinaryOperator::Mul if both_real => Typed::real(format!("({} * {})", lhs.code, rhs.code)),
+        BinaryOperator::Div if both_real => Typed::real(format!("({} / {})", lhs.code, rhs.code)),
+        BinaryOperator::Add => complex_call("sum", [lhs, rhs]),
+        BinaryOperator::Sub => complex_call("sub", [lhs, rhs]),
+        BinaryOperator::Mul => complex_call("mul", [lhs, rhs]),
+        BinaryOperator::Div => complex_call("div", [lhs, rhs]),
+        BinaryOperator::Pow => pow(lhs, rhs),
+    }
+}
+
+/// Real exponents go through `complex_pow`, which is exact for integers.
+/// Negative real bases are promoted since their non-integer powers are complex
+fn pow(base: Typed, exponent: Typed) -> Typed {
+    match exponent.ty {
+        Type::Real => Typed::complex(format!(
+            "complex_pow({}, {})",
+            base.into_complex(),
+            exponent.code
+        )),
+        Type::Complex => complex_call("c_pow", [base, exponent]),
+    }
+}
+
+fn complex_call<const N: usize>(function: &str, arguments: [Typed; N]) -> Typed {
+    let arguments = arguments.map(Typed::into_complex).join(", ");
+    Typed::complex(format!("{function}({arguments})"))
+}
+
+fn call(function: &str, arguments: Vec<Typed>, position: usize) -> Result<Typed, FormulaError> {
+    let expected_arity = match function {
+        "pow" => 2,
+        "exp" | "log" | "sqrt" | "sin" | "cos" | "tan" | "sinh" | "cosh" | "abs" | "arg"
+        | "conj" | "real" | "imag" => 1,
+        _ => {
+            return Err(FormulaError::new(
+                position,
+                format!("неизвестная функция «{function}»"),
+            ));
+        }
+    };
+    if arguments.len() != expected_arity {
+        return Err(FormulaError::new(
+            position,
+            format!(
+                "функция «{function}» принимает аргументов: {expected_arity}, передано: {}",
+                arguments.len()
+            ),
+        ));
+    }
+
+    let mut arguments = arguments.into_iter();
+    let argument = arguments.next().expect("Arity is checked above");
+    if function == "pow" {
+        let exponent = arguments.next().expect("Arity is checked above");
+        return Ok(pow(argument, exponent));
+    }
+
+    let code = &argument.code;
+    Ok(match (function, argument.ty) {
+        ("exp" | "sin" | "cos" | "tan" | "sinh" | "cosh", Type::Real) => {
+            Typed::real(format!("{function}({code})"))
+        }
+        ("exp" | "sin" | "cos" | "tan" | "sinh" | "cosh", Type::Complex) => {
+            Typed::complex(format!("c_{function}({code})"))
+        }
+        // Logarithms and roots of negative reals are complex
+        ("log" | "sqrt", _) => complex_call(&format!("c_{function}"), [argument]),
+        ("abs", Type::Real) => Typed::real(format!("abs({code})")),
+        ("abs", Type::Complex) => Typed::real(format!("norm_sqrt({code})")),
+        ("arg", Type::Real) => Typed::real(format!("atan2(0.0, {code})")),
+        ("arg", Type::Complex) => Typed::real(format!("c_arg({code})")),
+        ("conj" | "real", Type::Real) => argument,
+        ("conj", Type::Complex) => Typed::complex(format!("conj({code})")),
+        ("real", Type::Complex) => Typed::real(format!("({code}).re")),
+        ("imag", Type::Real) => Typed::real("0.0".to_string()),
+        ("imag", Type::Complex) => Typed::real(format!("({code}).im")),
+        _ => unreachable!("Function names are checked above"),
+    })
+}
@@ -0,0 +1,155 @@
+use crate::formula::FormulaError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    Identifier(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LeftParen,
+    RightParen,
+    Comma,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Character offset in the source, used for error messages
+    pub position: usize,
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, FormulaError> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    while position < chars.len() {
+        let ch = chars[position];
+        let start = position;
+
+        if ch.is_whitespace() {
+            position += 1;
+            continue;
+        }
+
+        if ch.is_ascii_digit() || ch == '.' {
+            while position < chars.len()
+                && (chars[position].is_ascii_digit() || chars[position] == '.')
+            {
+                position += 1;
+            }
+            // Exponent part, e.g. 1e-3. A bare `e` after a number is left for implicit multiplication
+            let has_exponent = matches!(chars.get(position), Some('e' | 'E'))
+                && match chars.get(position + 1) {
+                    Some(digit) if digit.is_ascii_digit() => true,
+                    Some('+' | '-') => chars
+                        .get(position + 2)
+                        .is_some_and(|digit| digit.is_ascii_digit()),
+                    _ => false,
+                };
+            if has_exponent {
+                position += 2;
+                while position < chars.len() && chars[position].is_ascii_digit() {
+                    position += 1;
+                }
+            }
+
+            let text = chars[start..position].iter().collect::<String>();
+            let value = text
+                .parse::<f64>()
+                .map_err(|_| FormulaError::new(start, format!("некорректное число «{text}»")))?;
+            tokens.push(Token {
+                kind: TokenKind::Number(value),
+                position: start,
+            });
+            continue;
+        }
+
+        if ch.is_alphabetic() || ch == '_' {
+            while position < chars.len()
+                && (chars[position].is_alphanumeric() || chars[position] == '_')
+            {
+                position += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Identifier(chars[start..position].iter().collect()),
+                position: start,
+            });
+            continue;
+        }
+
+        let kind = match ch {
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '^' => TokenKind::Caret,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            ',' => TokenKind::Comma,
+            _ => {
+                return Err(FormulaError::new(
+                    start,
+                    format!("неожиданный символ «{ch}»"),
+                ));
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+        position += 1;
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: chars.len(),
+    });
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn reads_numbers() {
+        assert_eq!(kinds("1.5e-3"), [TokenKind::Number(1.5e-3), TokenKind::End]);
+        // `2e` is 2 times e, not an exponent
+        assert_eq!(
+            kinds("2e"),
+            [
+                TokenKind::Number(2.0),
+                TokenKind::Identifier("e".to_string()),
+                TokenKind::End
+            ]
+        );
+
+        let error = tokenize("z + 1.2.3").unwrap_err();
+        assert_eq!(error.position, 4);
+        assert_eq!(error.message, "некорректное число «1.2.3»");
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        let tokens = tokenize("ж + z").unwrap();
+        let positions = tokens
+            .iter()
+            .map(|token| token.position)
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [0, 2, 4, 5]);
+        assert_eq!(tokenize("z # c").unwrap_err().position, 2);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::formula::FormulaError;
+use crate::formula::lexer::{Token, TokenKind};
+
+/// Longest formula in tokens. Long chains such as `z + z + …` are not nested while parsed,
+/// but the code generation walks their tree recursively before it checks the depth
+const MAX_TOKENS: usize = 1000;
+
+/// Deepest nesting of parentheses, signs and exponents, deeper formulas would overflow the stack
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expr>),
+    Binary {
+        operator: BinaryOperator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call {
+        function: String,
+        arguments: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub position: usize,
+}
+
+/// Grammar, from the lowest precedence:
+///
+/// ```text
+/// expression := term (('+' | '-') term)*
+/// term       := unary (('*' | '/') unary | primary)*
+/// unary      := '-' unary | power
+/// power      := primary ('^' unary)?
+/// primary    := number | identifier | identifier '(' arguments ')' | '(' expression ')'
+/// ```
+///
+/// A primary directly following a factor is an implicit multiplication, e.g. `2z` or `3(z + 1)`
+pub fn parse(tokens: &[Token]) -> Result<Expr, FormulaError> {
+    // The last token is always the end
+    if tokens.len() > MAX_TOKENS + 1 {
+        return Err(FormulaError::new(
+            tokens[MAX_TOKENS].position,
+            format!("формула длиннее {MAX_TOKENS} элементов"),
+        ));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        current: 0,
+        nesting: 0,
+    };
+    let expr = parser.expression()?;
+    let token = parser.peek();
+    if token.kind != TokenKind::End {
+        return Err(FormulaError::new(
+            token.position,
+            "ожидался конец формулы".to_string(),
+        ));
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    current: usize,
+    /// Current depth of the recursion through [`Parser::unary`]
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn advance(&mut self) -> &Token {
+        let token = &self.tokens[self.current];
+        if token.kind != TokenKind::End {
+            self.current += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind, description: &str) -> Result<(), FormulaError> {
+        let token = self.peek();
+        if token.kind != kind {
+            return Err(FormulaError::new(
+                token.position,
+                format!("ожидалась {description}"),
+            ));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<Expr, FormulaError> {
+        let mut lhs = self.term()?;
+        loop {
+            let operator = match self.peek().kind {
+                TokenKind::Plus => BinaryOperator::Add,
+                TokenKind::Minus => BinaryOperator::Sub,
+                _ => return Ok(lhs),
+            };
+            let position = self.advance().position;
+            let rhs = self.term()?;
+            lhs = binary(operator, lhs, rhs, position);
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, FormulaError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let position = self.peek().position;
+            let (operator, rhs) = match self.peek().kind {
+                TokenKind::Star => {
+                    self.advance();
+                    (BinaryOperator::Mul, self.unary()?)
+                }
+                TokenKind::Slash => {
+                    self.advance();
+                    (BinaryOperator::Div, self.unary()?)
+                }
+                // Cannot start with a minus, so the operand is a power as in 2z^2
+                TokenKind::Number(_) | TokenKind::Identifier(_) | TokenKind::LeftParen => {
+                    (BinaryOperator::Mul, self.unary()?)
+                }
+                _ => return Ok(lhs),
+            };
+            lhs = binary(operator, lhs, rhs, position);
+        }
+    }
+
+    /// Every recursive rule passes through here, so the nesting is limited in one place
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.nesting == MAX_NESTING {
+            return Err(FormulaError::new(
+                self.peek().position,
+                format!("вложенность глубже {MAX_NESTING} уровней"),
+            ));
+        }
+        self.nesting += 1;
+        let result = self.nested_unary();
+        self.nesting -= 1;
+        result
+    }
+
+    fn nested_unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.peek().kind == TokenKind::Minus {
+            let position = self.advance().position;
+            let operand = self.unary()?;
+            return Ok(Expr {
+                kind: ExprKind::Negate(Box::new(operand)),
+                position,
+            });
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr, FormulaError> {
+        let base = self.primary()?;
+        if self.peek().kind == TokenKind::Caret {
+            let position = self.advance().position;
+            // Right associative: z^2^3 is z^(2^3), and z^-1 is allowed
+            let exponent = self.unary()?;
+            return Ok(binary(BinaryOperator::Pow, base, exponent, position));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        let token = self.advance().clone();
+        match token.kind {
+            TokenKind::Number(value) => Ok(Expr {
+                kind: ExprKind::Number(value),
+                position: token.position,
+            }),
+            TokenKind::Identifier(name) => {
+                if self.peek().kind != TokenKind::LeftParen {
+                    return Ok(Expr {
+                        kind: ExprKind::Variable(name),
+                        position: token.position,
+                    });
+                }
+
+                self.advance();
+                let mut arguments = vec![];
+                if self.peek().kind != TokenKind::RightParen {
+                    loop {
+                        arguments.push(self.expression()?);
+                        if self.peek().kind != TokenKind::Comma {
+                            break;
+                        }
+                        self.advance();
+                    }
+                }
+                self.expect(TokenKind::RightParen, "закрывающая скобка")?;
+                Ok(Expr {
+                    kind: ExprKind::Call {
+                        function: name,
+                        arguments,
+                    },
+                    position: token.position,
+                })
+            }
+            TokenKind::LeftParen => {
+                let expr = self.expression()?;
+                self.expect(TokenKind::RightParen, "закрывающая скобка")?;
+                Ok(expr)
+            }
+            TokenKind::End => Err(FormulaError::new(
+                token.position,
+                "неожиданный конец формулы".to_string(),
+            )),
+            _ => Err(FormulaError::new(
+                token.position,
+                "ожидалось число, переменная или скобка".to_string(),
+            )),
+        }
+    }
+}
+
+fn binary(operator: BinaryOperator, lhs: Expr, rhs: Expr, position: usize) -> Expr {
+    Expr {
+        kind: ExprKind::Binary {
+            operator,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::lexer::tokenize;
+
+    /// Prefix notation of the tree, e.g. `(+ z (* 2 c))`
+    fn show(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Number(value) => value.to_string(),
+            ExprKind::Variable(name) => name.clone(),
+            ExprKind::Negate(operand) => format!("(neg {})", show(operand)),
+            ExprKind::Binary { operator, lhs, rhs } => {
+                let operator = match operator {
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Sub => "-",
+                    BinaryOperator::Mul => "*",
+                    BinaryOperator::Div => "/",
+                    BinaryOperator::Pow => "^",
+                };
+                format!("({operator} {} {})", show(lhs), show(rhs))
+            }
+            ExprKind::Call {
+                function,
+                arguments,
+            } => {
+                let arguments = arguments.iter().map(show).collect::<Vec<_>>();
+                format!("({function} {})", arguments.join(" "))
+            }
+        }
+    }
+
+    fn parse_source(source: &str) -> Result<String, FormulaError> {
+        parse(&tokenize(source)?).map(|expr| show(&expr))
+    }
+
+    #[test]
+    fn operators_have_precedence() {
+        assert_eq!(parse_source("z + 2 * c").unwrap(), "(+ z (* 2 c))");
+        assert_eq!(parse_source("z - c - 1").unwrap(), "(- (- z c) 1)");
+        assert_eq!(parse_source("z / c * 2").unwrap(), "(* (/ z c) 2)");
+        assert_eq!(parse_source("2 * z^3").unwrap(), "(* 2 (^ z 3))");
+        assert_eq!(parse_source("(z + c) * 2").unwrap(), "(* (+ z c) 2)");
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(parse_source("z^2^3").unwrap(), "(^ z (^ 2 3))");
+        assert_eq!(parse_source("z^-1").unwrap(), "(^ z (neg 1))");
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_power() {
+        assert_eq!(parse_source("-z^2").unwrap(), "(neg (^ z 2))");
+        assert_eq!(parse_source("--z").unwrap(), "(neg (neg z))");
+        assert_eq!(parse_source("c * -z").unwrap(), "(* c (neg z))");
+    }
+
+    #[test]
+    fn juxtaposition_multiplies() {
+        assert_eq!(parse_source("2z").unwrap(), "(* 2 z)");
+        assert_eq!(parse_source("3(z + 1)").unwrap(), "(* 3 (+ z 1))");
+        assert_eq!(parse_source("2z^2").unwrap(), "(* 2 (^ z 2))");
+        assert_eq!(parse_source("pow(z, 2) c").unwrap(), "(* (pow z 2) c)");
+    }
+
+    #[test]
+    fn reports_positions_of_syntax_errors() {
+        let error = parse_source("z + ").unwrap_err();
+        assert_eq!(error.position, 4);
+        assert_eq!(error.message, "неожиданный конец формулы");
+
+        let error = parse_source("sin(z").unwrap_err();
+        assert_eq!(error.position, 5);
+        assert_eq!(error.message, "ожидалась закрывающая скобка");
+
+        assert_eq!(parse_source("z )").unwrap_err().position, 2);
+        assert_eq!(parse_source("z * * c").unwrap_err().position, 4);
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        // Short enough for the length limit, so only the nesting stops them
+        for source in [
+            "-".repeat(300) + "z",
+            "(".repeat(300) + "z" + &")".repeat(300),
+            "z^".repeat(300) + "z",
+            "sin(".repeat(300) + "z" + &")".repeat(300),
+            "z(".repeat(300) + "z" + &")".repeat(300),
+        ] {
+            let error = parse_source(&source).unwrap_err();
+            assert!(error.message.starts_with("вложенность"), "{error}");
+        }
+
+        let nested = "(".repeat(MAX_NESTING - 1) + "z" + &")".repeat(MAX_NESTING - 1);
+        assert!(parse_source(&nested).is_ok());
+        let error = parse_source(&format!("({nested})")).unwrap_err();
+        assert_eq!(error.position, MAX_NESTING);
+    }
+
+    #[test]
+    fn long_formulas_are_an_error() {
+        // A sum of n terms has 2n - 1 tokens, and the `2` of `2z` is one more
+        let longest = "2".to_string() + &vec!["z"; MAX_TOKENS / 2].join("+");
+        assert!(parse_source(&longest).is_ok());
+        let error = parse_source(&(longest + "+1")).unwrap_err();
+        assert_eq!(error.position, MAX_TOKENS);
+
+        // Would overflow the stack in the later passes if it were accepted
+        assert!(parse_source(&vec!["z"; 100_000].join("+")).is_err());
+    }
+}
@@ -1,13 +1,52 @@
+use crate::animation::Keyframe;
+use crate::bookmarks::{BOOKMARKS_FILE_NAME, load_bookmarks};
+use crate::formula;
+use crate::fractal_renderer::FractalRenderer;
 use crate::fv_render_callback::FvRenderCallback;
-use crate::fv_renderer_resource::FvRendererResource;
-use crate::uniforms::{FractalColorScheme, FractalType, Uniforms};
-use crate::user_settings::UserSettings;
+use crate::fv_renderer_resource::{
+    FvRendererResource, MAX_BUDDHABROT_SAMPLES_PER_FRAME, RenderFrame,
+};
+use crate::image_export::read_parameters;
+use crate::location::{decode_location, encode_location};
+use crate::polynomial::{coefficients_from_roots, roots_from_coefficients};
+use crate::uniforms::{
+    BailoutTest, BuddhabrotFlags, DomainColoringFlags, Fractal3dType, FractalColorScheme,
+    FractalType, HybridStep, MAX_HYBRID_STEPS, MAX_LYAPUNOV_SEQUENCE, MAX_NEWTON_ROOTS,
+    PeriodicityFlags, RaymarchFlags, RenderMode,
+};
+use crate::user_settings::{LYAPUNOV_RANGES, MAX_CAMERA_PITCH, MAX_ITERATIONS, UserSettings};
 use eframe::{CreationContext, Frame};
-use egui::{Context, DragValue, Grid, Key, PointerButton, Slider, Ui, ViewportCommand, Widget};
-use log::info;
+use egui::{
+    Align2, Button, Color32, ComboBox, Context, DragValue, Event, FontId, Grid, Key, PointerButton,
+    Pos2, Rect, Response, Sense, Slider, Stroke, TextEdit, Ui, Vec2, ViewportCommand, Widget,
+};
+use egui_wgpu::RenderState;
+use log::{info, warn};
 use measure_time::debug_time;
+use std::path::PathBuf;
 use std::time::Instant;
 
+mod animation_ui;
+mod bookmarks_ui;
+mod export_ui;
+mod toast;
+
+use animation_ui::AnimationExport;
+use bookmarks_ui::BookmarkEntry;
+use export_ui::{LoopFileExport, RawDataFileExport, ScreenshotExport};
+
+/// Automatic iterations at zoom 1, growing with the square of the decimal zoom level
+const AUTO_ITERATIONS_BASE: f32 = 100.0;
+/// Share of pixels at the iteration limit that the automatic mode aims to stay under
+const AUTO_ITERATIONS_TARGET: f32 = 0.01;
+const MAX_AUTO_ITERATIONS_BOOST: f32 = 100.0;
+
+/// Key of the settings in the storage of eframe
+const SETTINGS_KEY: &str = "settings";
+
+/// Name of the window, also names the storage folder of the app
+pub const APP_NAME: &str = "Mandelbrot wgpu";
+
 pub struct FractalApp {
     settings: UserSettings,
     adapter_name: String,
@@ -16,6 +55,41 @@ pub struct FractalApp {
     driver_name: String,
     last_frame: Instant,
     frame_delta_time_sec: f32,
+    pow_animation_direction: f32,
+    newton_edit_coefficients: bool,
+    newton_coefficients: Vec<[f32; 2]>,
+    render_state: RenderState,
+    formula_error: Option<String>,
+    formula_used_params: Vec<usize>,
+    buddhabrot_samples: u64,
+    buddhabrot_paused: bool,
+    buddhabrot_reset_requested: bool,
+    domain_function_error: Option<String>,
+    domain_function_used_params: Vec<usize>,
+    /// Multiplier of the zoom-based iteration count, raised while too many pixels hit the limit
+    auto_iterations_boost: f32,
+    last_limit_statistics_id: Option<u64>,
+    limit_fraction: Option<f32>,
+    /// Size of the fractal view in pixels
+    view_size: [u32; 2],
+    /// Offscreen renderer of the screenshots, created on the first one
+    screenshot_renderer: Option<FractalRenderer>,
+    screenshot_export: Option<ScreenshotExport>,
+    raw_data_export: Option<RawDataFileExport>,
+    toast: Option<(String, Instant)>,
+    /// Start of the preview of the keyframe animation
+    animation_playback: Option<Instant>,
+    animation_export: Option<AnimationExport>,
+    loop_export: Option<LoopFileExport>,
+    bookmarks: Vec<BookmarkEntry>,
+    /// File of the bookmarks, `None` when it has no place or could not be read,
+    /// so that it is not overwritten
+    bookmarks_path: Option<PathBuf>,
+    bookmark_name: String,
+    /// Transition to a bookmark: the view it started from, the bookmarked view and the start
+    bookmark_transition: Option<(Keyframe, Keyframe, Instant)>,
+    /// The paste button asked the window for the clipboard, its text arrives as a paste event
+    paste_requested: bool,
 }
 
 impl FractalApp {
@@ -25,7 +99,8 @@ impl FractalApp {
             .as_ref()
             .expect("Missing Wgpu render state");
 
-        let renderer_resource = FvRendererResource::new(wgpu_render_state);
+        let renderer_resource =
+            FvRendererResource::new(&wgpu_render_state.device, wgpu_render_state.target_format);
 
         wgpu_render_state
             .renderer
@@ -35,9 +110,31 @@ impl FractalApp {
 
         let adapter_info = wgpu_render_state.adapter.get_info();
         let backend_name = format!("{}", adapter_info.backend);
-        let user_settings = UserSettings::new();
+        let mut bookmarks_path =
+            eframe::storage_dir(APP_NAME).map(|folder| folder.join(BOOKMARKS_FILE_NAME));
+        let mut toast = None;
+        let bookmarks = match bookmarks_path.as_deref().map(load_bookmarks) {
+            Some(Ok(bookmarks)) => bookmarks,
+            Some(Err(error)) => {
+                let path = bookmarks_path.take().unwrap_or_default();
+                warn!("Failed to read bookmarks from {}: {error}", path.display());
+                toast = Some((
+                    format!(
+                        "Закладки из {} не прочитаны и не будут сохраняться: {error}",
+                        path.display()
+                    ),
+                    Instant::now(),
+                ));
+                vec![]
+            }
+            None => vec![],
+        };
+        let user_settings = cc
+            .storage
+            .and_then(|storage| storage.get_string(SETTINGS_KEY))
+            .map_or_else(UserSettings::new, |saved| UserSettings::restore(&saved));
         info!("{:?}", &adapter_info);
-        Self {
+        let mut app = Self {
             settings: user_settings,
             adapter_name: adapter_info.name.clone(),
             backend_name,
@@ -45,6 +142,93 @@ impl FractalApp {
             driver_name: adapter_info.driver.clone(),
             last_frame: Instant::now(),
             frame_delta_time_sec: 0.0,
+            pow_animation_direction: 1.0,
+            newton_edit_coefficients: false,
+            newton_coefficients: vec![],
+            render_state: wgpu_render_state.clone(),
+            formula_error: None,
+            formula_used_params: vec![],
+            buddhabrot_samples: 0,
+            buddhabrot_paused: false,
+            buddhabrot_reset_requested: false,
+            domain_function_error: None,
+            domain_function_used_params: vec![],
+            auto_iterations_boost: 1.0,
+            last_limit_statistics_id: None,
+            limit_fraction: None,
+            view_size: [1, 1],
+            screenshot_renderer: None,
+            screenshot_export: None,
+            raw_data_export: None,
+            toast,
+            animation_playback: None,
+            animation_export: None,
+            loop_export: None,
+            bookmarks: bookmarks.into_iter().map(BookmarkEntry::new).collect(),
+            bookmarks_path,
+            bookmark_name: String::new(),
+            bookmark_transition: None,
+            paste_requested: false,
+        };
+        app.apply_formula();
+        app.apply_domain_function();
+        app
+    }
+
+    /// Compiles the custom formula and rebuilds the pipeline with it.
+    /// On failure the error is shown in the settings and the previous pipeline stays active
+    fn apply_formula(&mut self) {
+        let compiled = match formula::compile(&self.settings.formula) {
+            Ok(compiled) => compiled,
+            Err(error) => {
+                self.formula_error = Some(error.to_string());
+                return;
+            }
+        };
+
+        let mut renderer = self.render_state.renderer.write();
+        let resource = renderer
+            .callback_resources
+            .get_mut::<FvRendererResource>()
+            .expect("Missing FvRendererResource");
+
+        match resource.set_formula(&self.render_state.device, &compiled.wgsl) {
+            Ok(()) => {
+                self.formula_error = None;
+                self.formula_used_params = compiled.used_params;
+            }
+            Err(error) => {
+                warn!("Failed to build custom formula pipeline: {error}");
+                self.formula_error = Some(format!("ошибка компиляции шейдера: {error}"));
+            }
+        }
+    }
+
+    /// Same as [`Self::apply_formula`] for the function of the domain coloring
+    fn apply_domain_function(&mut self) {
+        let compiled = match formula::compile_function(&self.settings.domain_function) {
+            Ok(compiled) => compiled,
+            Err(error) => {
+                self.domain_function_error = Some(error.to_string());
+                return;
+            }
+        };
+
+        let mut renderer = self.render_state.renderer.write();
+        let resource = renderer
+            .callback_resources
+            .get_mut::<FvRendererResource>()
+            .expect("Missing FvRendererResource");
+
+        match resource.set_domain_function(&self.render_state.device, &compiled.wgsl) {
+            Ok(()) => {
+                self.domain_function_error = None;
+                self.domain_function_used_params = compiled.used_params;
+            }
+            Err(error) => {
+                warn!("Failed to build domain function pipeline: {error}");
+                self.domain_function_error = Some(format!("ошибка компиляции шейдера: {error}"));
+            }
         }
     }
 }
@@ -59,15 +243,70 @@ impl eframe::App for FractalApp {
         if ctx.input(|state| state.key_pressed(Key::F1)) {
             self.settings.show_settings = !self.settings.show_settings;
         }
+        if ctx.input(|state| state.key_pressed(Key::F2)) {
+            self.settings.show_bookmarks = !self.settings.show_bookmarks;
+        }
         if ctx.input(|state| state.key_pressed(Key::F11))
             && let Some(current_is_fullscreen) = ctx.input(|i| i.viewport().fullscreen)
         {
             ctx.send_viewport_cmd(ViewportCommand::Fullscreen(!current_is_fullscreen))
         }
+        if ctx.input(|state| state.key_pressed(Key::F12)) {
+            self.save_screenshot(false);
+        }
+        self.load_dropped_images(ctx);
+        self.paste_location(ctx);
+        if self.animation_playback.is_some() {
+            self.play_animation();
+            ctx.request_repaint();
+        }
+        if self.screenshot_export.is_some() {
+            self.export_screenshot_strip();
+            ctx.request_repaint();
+        }
+        if self.raw_data_export.is_some() {
+            self.export_raw_data_strip();
+            ctx.request_repaint();
+        }
+        if self.animation_export.is_some() {
+            self.export_animation_frame();
+            ctx.request_repaint();
+        }
+        if self.loop_export.is_some() {
+            self.export_loop_frame();
+            ctx.request_repaint();
+        }
+        if self.bookmark_transition.is_some() {
+            self.play_bookmark_transition();
+            ctx.request_repaint();
+        }
+        if self.settings.animate_pow {
+            self.animate_pow();
+            ctx.request_repaint();
+        }
+        if self.settings.render_mode.contains(RenderMode::BUDDHABROT) {
+            self.buddhabrot_samples = self
+                .render_state
+                .renderer
+                .read()
+                .callback_resources
+                .get::<FvRendererResource>()
+                .map_or(0, FvRendererResource::buddhabrot_samples);
+            if !self.buddhabrot_paused {
+                ctx.request_repaint();
+            }
+        }
+        if self.settings.show_bookmarks {
+            egui::SidePanel::right("bookmarks")
+                .resizable(false)
+                .show(ctx, |ui| self.bookmarks_ui(ui));
+            self.render_next_thumbnail(ctx);
+        }
         egui::CentralPanel::default().show(ctx, |ui| self.paint_fractal(ui, ctx, frame));
 
+        let mut show_settings = self.settings.show_settings;
         egui::Window::new("Информация и настройки")
-            .open(&mut self.settings.show_settings)
+            .open(&mut show_settings)
             .movable(true)
             .default_pos([0.0, 0.0])
             .resizable(false)
@@ -116,46 +355,288 @@ impl eframe::App for FractalApp {
                         .striped(true)
                         .spacing([10.0, 4.0])
                         .show(ui, |ui| {
-                            ui.heading("Тип фрактала");
-
+                            ui.heading("Режим");
                             ui.horizontal(|ui| {
-                                ui.selectable_value(
-                                    &mut self.settings.fractal_type,
-                                    FractalType::MANDELBROT,
-                                    FractalType::MANDELBROT.to_string(),
-                                );
-                                ui.selectable_value(
-                                    &mut self.settings.fractal_type,
-                                    FractalType::JULIA,
-                                    FractalType::JULIA.to_string(),
-                                );
+                                for mode in [
+                                    RenderMode::ESCAPE_TIME,
+                                    RenderMode::LYAPUNOV,
+                                    RenderMode::BUDDHABROT,
+                                    RenderMode::RAYMARCH_3D,
+                                    RenderMode::DOMAIN_COLORING,
+                                ] {
+                                    let selected = self.settings.render_mode == mode;
+                                    if ui.selectable_label(selected, mode.to_string()).clicked() {
+                                        self.settings.switch_render_mode(mode);
+                                    }
+                                }
                             });
 
                             ui.end_row();
 
-                            ui.heading("Степень");
-                            DragValue::new(&mut self.settings.pow)
-                                .speed(1)
-                                .range(2..=255)
-                                .ui(ui);
+                            if self
+                                .settings
+                                .render_mode
+                                .intersects(RenderMode::ESCAPE_TIME | RenderMode::BUDDHABROT)
+                            {
+                                ui.heading("Тип фрактала");
 
-                            ui.end_row();
+                                ui.vertical(|ui| {
+                                    ui.horizontal(|ui| {
+                                        for mode in [FractalType::MANDELBROT, FractalType::JULIA] {
+                                            let selected =
+                                                self.settings.fractal_type.mode() == mode;
+                                            if ui
+                                                .selectable_label(selected, mode.to_string())
+                                                .clicked()
+                                            {
+                                                self.settings.fractal_type =
+                                                    self.settings.fractal_type.with_mode(mode);
+                                            }
+                                        }
+                                    });
 
-                            ui.heading("Количество итераций");
-                            DragValue::new(&mut self.settings.max_iter)
-                                .speed(1)
-                                .range(0..=1_000)
-                                .ui(ui);
+                                    let current_variant = self.settings.fractal_type.variant();
+                                    ComboBox::from_id_salt("fractal_variant")
+                                        .selected_text(variant_name(current_variant))
+                                        .show_ui(ui, |ui| {
+                                            let variants = std::iter::once(FractalType::empty())
+                                                .chain(FractalType::VARIANTS);
+                                            for variant in variants {
+                                                if ui
+                                                    .selectable_label(
+                                                        current_variant == variant,
+                                                        variant_name(variant),
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    self.settings.fractal_type = self
+                                                        .settings
+                                                        .fractal_type
+                                                        .with_variant(variant);
+                                                }
+                                            }
+                                        });
 
-                            ui.end_row();
+                                    if self.settings.fractal_type.contains(FractalType::NEWTON) {
+                                        self.newton_settings_ui(ui);
+                                    }
 
-                            ui.heading("Граница проверки");
-                            DragValue::new(&mut self.settings.escape_threshold)
-                                .speed(1.0)
-                                .range(1.0..=255.0)
-                                .ui(ui);
+                                    if self
+                                        .settings
+                                        .fractal_type
+                                        .intersects(FractalType::CUSTOM | FractalType::HYBRID)
+                                    {
+                                        self.formula_settings_ui(ui);
+                                    }
 
-                            ui.end_row();
+                                    if self.settings.fractal_type.contains(FractalType::HYBRID) {
+                                        self.hybrid_settings_ui(ui);
+                                    }
+
+                                    if self.settings.fractal_type.contains(FractalType::PHOENIX) {
+                                        Grid::new("phoenix_settings")
+                                            .num_columns(2)
+                                            .spacing([10.0, 4.0])
+                                            .show(ui, |ui| {
+                                                ui.label("Параметр p");
+                                                ui.horizontal(|ui| {
+                                                    DragValue::new(&mut self.settings.phoenix_p_x)
+                                                        .speed(0.01)
+                                                        .range(-3.0..=3.0)
+                                                        .ui(ui);
+                                                    DragValue::new(&mut self.settings.phoenix_p_y)
+                                                        .speed(0.01)
+                                                        .range(-3.0..=3.0)
+                                                        .suffix("i")
+                                                        .ui(ui);
+                                                });
+                                                ui.end_row();
+                                            });
+                                    }
+
+                                    if self
+                                        .settings
+                                        .fractal_type
+                                        .intersects(FractalType::MAGNET_1 | FractalType::MAGNET_2)
+                                    {
+                                        Grid::new("magnet_settings")
+                                            .num_columns(2)
+                                            .spacing([10.0, 4.0])
+                                            .show(ui, |ui| {
+                                                ui.label("Точность сходимости к 1");
+                                                DragValue::new(&mut self.settings.magnet_tolerance)
+                                                    .speed(0.0001)
+                                                    .range(0.000_001..=0.5)
+                                                    .ui(ui);
+                                                ui.end_row();
+                                            });
+                                    }
+                                });
+
+                                ui.end_row();
+                            }
+
+                            if self.settings.render_mode.contains(RenderMode::RAYMARCH_3D) {
+                                self.raymarch_settings_rows(ui);
+                            }
+
+                            if self
+                                .settings
+                                .render_mode
+                                .contains(RenderMode::DOMAIN_COLORING)
+                            {
+                                self.domain_coloring_settings_rows(ui);
+                            }
+
+                            let render_mode = self.settings.render_mode;
+                            let uses_pow = render_mode
+                                .intersects(RenderMode::ESCAPE_TIME | RenderMode::BUDDHABROT)
+                                || (render_mode.contains(RenderMode::RAYMARCH_3D)
+                                    && self
+                                        .settings
+                                        .fractal_3d
+                                        .contains(Fractal3dType::MANDELBULB));
+                            if uses_pow {
+                                ui.heading("Степень");
+                                ui.vertical(|ui| {
+                                    ui.horizontal(|ui| {
+                                        DragValue::new(&mut self.settings.pow)
+                                            .speed(0.01)
+                                            .range(-255.0..=255.0)
+                                            .ui(ui);
+                                        ui.checkbox(&mut self.settings.animate_pow, "Анимация");
+                                    });
+
+                                    if self.settings.animate_pow {
+                                        Grid::new("pow_animation_settings")
+                                            .num_columns(2)
+                                            .spacing([10.0, 4.0])
+                                            .show(ui, |ui| {
+                                                ui.label("От");
+                                                DragValue::new(
+                                                    &mut self.settings.pow_animation_from,
+                                                )
+                                                .speed(0.01)
+                                                .range(-255.0..=255.0)
+                                                .ui(ui);
+                                                ui.end_row();
+
+                                                ui.label("До");
+                                                DragValue::new(&mut self.settings.pow_animation_to)
+                                                    .speed(0.01)
+                                                    .range(-255.0..=255.0)
+                                                    .ui(ui);
+                                                ui.end_row();
+
+                                                ui.label("Скорость");
+                                                Slider::new(
+                                                    &mut self.settings.pow_animation_speed,
+                                                    0.01..=5.0,
+                                                )
+                                                .logarithmic(true)
+                                                .suffix("/с")
+                                                .ui(ui);
+                                                ui.end_row();
+                                            });
+                                    }
+                                });
+
+                                ui.end_row();
+                            }
+
+                            if self
+                                .settings
+                                .render_mode
+                                .intersects(RenderMode::ESCAPE_TIME | RenderMode::BUDDHABROT)
+                            {
+                                ui.heading("Количество итераций");
+                                ui.horizontal(|ui| {
+                                    let auto = self.settings.auto_iterations
+                                        && self
+                                            .settings
+                                            .render_mode
+                                            .contains(RenderMode::ESCAPE_TIME);
+                                    let speed = (self.settings.max_iter as f32 / 100.0).max(1.0);
+                                    ui.add_enabled(
+                                        !auto,
+                                        DragValue::new(&mut self.settings.max_iter)
+                                            .speed(speed)
+                                            .range(0..=MAX_ITERATIONS),
+                                    );
+
+                                    if self.settings.render_mode.contains(RenderMode::ESCAPE_TIME) {
+                                        ui.checkbox(&mut self.settings.auto_iterations, "Авто")
+                                            .on_hover_text(
+                                                "По масштабу и доле пикселей, достигших предела. \
+                                                Лучше работает с проверкой периодичности",
+                                            );
+                                        if auto && let Some(fraction) = self.limit_fraction {
+                                            ui.label(format!(
+                                                "на пределе {:.1}%",
+                                                fraction * 100.0
+                                            ));
+                                        }
+                                    }
+                                });
+
+                                ui.end_row();
+
+                                ui.heading("Граница проверки (R²)");
+                                Slider::new(&mut self.settings.escape_threshold, 1.0..=1e30)
+                                    .logarithmic(true)
+                                    .ui(ui);
+
+                                ui.end_row();
+
+                                ui.heading("Условие остановки");
+                                ui.horizontal(|ui| {
+                                    ComboBox::from_id_salt("bailout_test")
+                                        .selected_text(self.settings.bailout_test.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for test in [
+                                                BailoutTest::NORM,
+                                                BailoutTest::REAL,
+                                                BailoutTest::IMAGINARY,
+                                                BailoutTest::MANHATTAN,
+                                                BailoutTest::MAX_NORM,
+                                                BailoutTest::PRODUCT,
+                                                BailoutTest::CONVERGENCE,
+                                            ] {
+                                                ui.selectable_value(
+                                                    &mut self.settings.bailout_test,
+                                                    test,
+                                                    test.to_string(),
+                                                );
+                                            }
+                                        });
+
+                                    if self
+                                        .settings
+                                        .bailout_test
+                                        .contains(BailoutTest::CONVERGENCE)
+                                    {
+                                        DragValue::new(&mut self.settings.convergence_epsilon)
+                                            .speed(0.00001)
+                                            .range(1e-9..=0.5)
+                                            .prefix("ε = ")
+                                            .ui(ui);
+                                    }
+                                });
+
+                                ui.end_row();
+                            }
+
+                            if self.settings.render_mode.contains(RenderMode::ESCAPE_TIME) {
+                                self.interior_settings_rows(ui);
+                            }
+
+                            if self.settings.render_mode.contains(RenderMode::LYAPUNOV) {
+                                self.lyapunov_settings_rows(ui);
+                            }
+
+                            if self.settings.render_mode.contains(RenderMode::BUDDHABROT) {
+                                self.buddhabrot_settings_rows(ui);
+                            }
 
                             ui.heading("Центр");
                             ui.horizontal(|ui| {
@@ -207,6 +688,12 @@ impl eframe::App for FractalApp {
                                     });
                                 });
 
+                                ui.horizontal(|ui| {
+                                    ui.label("Сдвиг палитры");
+                                    Slider::new(&mut self.settings.palette_offset, 0.0..=1.0)
+                                        .ui(ui);
+                                });
+
                                 if self.settings.color_scheme.contains(FractalColorScheme::RGB) {
                                     Grid::new("rgb_settings")
                                         .num_columns(2)
@@ -278,22 +765,874 @@ impl eframe::App for FractalApp {
                         });
                 });
 
+                ui.group(|ui| self.screenshot_settings_ui(ui));
+
+                ui.group(|ui| self.animation_settings_ui(ui));
+
+                ui.group(|ui| self.loop_settings_ui(ui));
+
                 ui.group(|ui| {
                     ui.label("F1 - скрыть настройки");
+                    ui.label("F2 - закладки");
                     ui.label("F11 - полноэкранный режим");
+                    ui.label("F12 - сохранить изображение");
+                    ui.label("Перетащите сохранённый PNG в окно, чтобы восстановить вид");
                     ui.label("Колесо мыши - изменить масштаб");
                     ui.label("ЛКМ + движение мыши - изменить координаты");
                     ui.label("ПКМ + движение мыши - изменить начальное значение");
+                    ui.label("ЛКМ на корне многочлена - переместить корень (Ньютон)");
+                    ui.label("3D: ЛКМ - вращать камеру, ПКМ - изменить c, колесо - приблизить");
                 });
+
+                if ui
+                    .button("Сбросить все настройки")
+                    .on_hover_text("Настройки сохраняются между запусками")
+                    .clicked()
+                {
+                    self.replace_settings(UserSettings::new());
+                    self.show_message("Настройки сброшены".to_string());
+                }
             });
+        self.settings.show_settings = show_settings;
+
+        self.show_toast(ctx);
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        match serde_json::to_string(&self.settings) {
+            Ok(settings) => storage.set_string(SETTINGS_KEY, settings),
+            Err(error) => warn!("Failed to save the settings: {error}"),
+        }
     }
 }
 
 impl FractalApp {
-    fn paint_fractal(&mut self, ui: &mut Ui, _ctx: &Context, _frame: &mut Frame) {
-        let size = ui.available_size().max(egui::vec2(400.0, 400.0));
-        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+    /// Moves the exponent back and forth between the animation bounds,
+    /// passing through non-integer values
+    fn animate_pow(&mut self) {
+        let settings = &mut self.settings;
+        let low = settings.pow_animation_from.min(settings.pow_animation_to);
+        let high = settings.pow_animation_from.max(settings.pow_animation_to);
+
+        settings.pow +=
+            self.pow_animation_direction * settings.pow_animation_speed * self.frame_delta_time_sec;
+
+        if settings.pow >= high {
+            settings.pow = high;
+            self.pow_animation_direction = -1.0;
+        } else if settings.pow <= low {
+            settings.pow = low;
+            self.pow_animation_direction = 1.0;
+        }
+    }
+
+    /// Rows of the main settings grid that only apply to the Buddhabrot mode
+    fn buddhabrot_settings_rows(&mut self, ui: &mut Ui) {
+        let flags = &mut self.settings.buddhabrot_flags;
+
+        ui.heading("Накопление");
+        ui.horizontal(|ui| {
+            ui.label(format!("{} орбит", self.buddhabrot_samples));
+            ui.checkbox(&mut self.buddhabrot_paused, "Пауза");
+            if ui.button("Сбросить").clicked() {
+                self.buddhabrot_reset_requested = true;
+            }
+        });
+
+        ui.end_row();
+
+        ui.heading("Орбит за кадр");
+        DragValue::new(&mut self.settings.buddhabrot_samples_per_frame)
+            .speed(1_000)
+            .range(64..=MAX_BUDDHABROT_SAMPLES_PER_FRAME)
+            .ui(ui);
+
+        ui.end_row();
+
+        ui.heading("Вариант");
+        ui.horizontal(|ui| {
+            let mut anti = flags.contains(BuddhabrotFlags::ANTI);
+            if ui.checkbox(&mut anti, "Анти-Буддаброт").changed() {
+                flags.set(BuddhabrotFlags::ANTI, anti);
+            }
+            let mut nebula = flags.contains(BuddhabrotFlags::NEBULA);
+            if ui.checkbox(&mut nebula, "Небулаброт").changed() {
+                flags.set(BuddhabrotFlags::NEBULA, nebula);
+            }
+        });
+
+        ui.end_row();
+
+        if flags.contains(BuddhabrotFlags::NEBULA) {
+            ui.heading("Итерации R/G/B");
+            ui.horizontal(|ui| {
+                for limit in &mut self.settings.buddhabrot_limits {
+                    DragValue::new(limit).speed(1).range(1..=100_000).ui(ui);
+                }
+            });
+
+            ui.end_row();
+        }
+
+        ui.heading("Выборка по значимости");
+        ui.horizontal(|ui| {
+            let mut importance = flags.contains(BuddhabrotFlags::IMPORTANCE);
+            if ui.checkbox(&mut importance, "").changed() {
+                flags.set(BuddhabrotFlags::IMPORTANCE, importance);
+            }
+            ui.add_enabled(
+                importance,
+                Slider::new(&mut self.settings.buddhabrot_importance, 0.0..=1.0)
+                    .text("доля у границы"),
+            );
+        });
+
+        ui.end_row();
+
+        ui.heading("Гамма");
+        Slider::new(&mut self.settings.buddhabrot_gamma, 0.1..=2.0).ui(ui);
+
+        ui.end_row();
+    }
+
+    /// Rows of the main settings grid that only apply to the domain coloring mode
+    fn domain_coloring_settings_rows(&mut self, ui: &mut Ui) {
+        ui.heading("f(z)");
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                let response = TextEdit::singleline(&mut self.settings.domain_function)
+                    .code_editor()
+                    .hint_text(formula::DEFAULT_DOMAIN_FUNCTION)
+                    .ui(ui);
+                let mut changed = response.changed();
+
+                ComboBox::from_id_salt("domain_function_examples")
+                    .selected_text("Примеры")
+                    .show_ui(ui, |ui| {
+                        for example in formula::DOMAIN_FUNCTION_EXAMPLES {
+                            if ui.selectable_label(false, example).clicked() {
+                                self.settings.domain_function = example.to_string();
+                                changed = true;
+                            }
+                        }
+                    });
+
+                if changed {
+                    self.apply_domain_function();
+                }
+            });
+
+            if let Some(error) = &self.domain_function_error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            Grid::new("domain_function_params")
+                .num_columns(2)
+                .spacing([10.0, 4.0])
+                .show(ui, |ui| {
+                    for &index in &self.domain_function_used_params {
+                        let param = &mut self.settings.formula_params[index];
+                        ui.label(format!("p{}", index + 1));
+                        ui.horizontal(|ui| {
+                            DragValue::new(&mut param[0]).speed(0.01).ui(ui);
+                            DragValue::new(&mut param[1]).speed(0.01).suffix("i").ui(ui);
+                        });
+                        ui.end_row();
+                    }
+                });
+        });
+
+        ui.end_row();
+
+        let flags = &mut self.settings.domain_flags;
+
+        ui.heading("Линии уровня |f|");
+        ui.horizontal(|ui| {
+            let mut contours = flags.contains(DomainColoringFlags::MODULUS_CONTOURS);
+            if ui.checkbox(&mut contours, "").changed() {
+                flags.set(DomainColoringFlags::MODULUS_CONTOURS, contours);
+            }
+            ui.add_enabled(
+                contours,
+                Slider::new(&mut self.settings.domain_contour_base, 1.1..=10.0)
+                    .logarithmic(true)
+                    .text("основание"),
+            );
+        });
+
+        ui.end_row();
+
+        ui.heading("Сетка Re f, Im f");
+        ui.horizontal(|ui| {
+            let mut grid = flags.contains(DomainColoringFlags::GRID);
+            if ui.checkbox(&mut grid, "").changed() {
+                flags.set(DomainColoringFlags::GRID, grid);
+            }
+            ui.add_enabled(
+                grid,
+                DragValue::new(&mut self.settings.domain_grid_spacing)
+                    .speed(0.01)
+                    .range(0.01..=100.0)
+                    .prefix("шаг "),
+            );
+        });
+
+        ui.end_row();
+    }
+
+    /// Rows of the main settings grid that only apply to the 3D mode
+    fn raymarch_settings_rows(&mut self, ui: &mut Ui) {
+        ui.heading("Фрактал");
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                for fractal in [Fractal3dType::MANDELBULB, Fractal3dType::QUATERNION_JULIA] {
+                    ui.selectable_value(
+                        &mut self.settings.fractal_3d,
+                        fractal,
+                        fractal.to_string(),
+                    );
+                }
+            });
+
+            if self
+                .settings
+                .fractal_3d
+                .contains(Fractal3dType::QUATERNION_JULIA)
+            {
+                ui.horizontal(|ui| {
+                    ui.label("c");
+                    for (component, suffix) in self
+                        .settings
+                        .quaternion_c
+                        .iter_mut()
+                        .zip(["", "i", "j", "k"])
+                    {
+                        DragValue::new(component)
+                            .speed(0.01)
+                            .range(-2.0..=2.0)
+                            .suffix(suffix)
+                            .ui(ui);
+                    }
+                });
+            }
+        });
+
+        ui.end_row();
+
+        ui.heading("Итерации формулы");
+        DragValue::new(&mut self.settings.raymarch_iterations)
+            .speed(0.1)
+            .range(1..=100)
+            .ui(ui);
+
+        ui.end_row();
+
+        ui.heading("Шаги луча");
+        DragValue::new(&mut self.settings.raymarch_steps)
+            .speed(1)
+            .range(16..=2_000)
+            .ui(ui);
+
+        ui.end_row();
+
+        let flags = &mut self.settings.raymarch_flags;
+
+        ui.heading("Освещение");
+        ui.horizontal(|ui| {
+            let mut shadows = flags.contains(RaymarchFlags::SOFT_SHADOWS);
+            if ui.checkbox(&mut shadows, "Мягкие тени").changed() {
+                flags.set(RaymarchFlags::SOFT_SHADOWS, shadows);
+            }
+            let mut occlusion = flags.contains(RaymarchFlags::AMBIENT_OCCLUSION);
+            if ui.checkbox(&mut occlusion, "Фоновое затенение").changed() {
+                flags.set(RaymarchFlags::AMBIENT_OCCLUSION, occlusion);
+            }
+        });
 
+        ui.end_row();
+
+        ui.heading("Окраска");
+        ui.horizontal(|ui| {
+            let orbit_trap = flags.contains(RaymarchFlags::ORBIT_TRAP);
+            if ui.selectable_label(!orbit_trap, "По итерациям").clicked() {
+                flags.remove(RaymarchFlags::ORBIT_TRAP);
+            }
+            if ui
+                .selectable_label(orbit_trap, "Орбитальная ловушка")
+                .clicked()
+            {
+                flags.insert(RaymarchFlags::ORBIT_TRAP);
+            }
+        });
+
+        ui.end_row();
+
+        ui.heading("Камера");
+        ui.horizontal(|ui| {
+            DragValue::new(&mut self.settings.camera_distance)
+                .speed(0.01)
+                .range(0.1..=20.0)
+                .prefix("расстояние ")
+                .ui(ui);
+            DragValue::new(&mut self.settings.camera_fov)
+                .speed(0.1)
+                .range(10.0..=120.0)
+                .prefix("угол обзора ")
+                .suffix("°")
+                .ui(ui);
+            if ui.button("Сбросить").clicked() {
+                let defaults = UserSettings::new();
+                self.settings.camera_yaw = defaults.camera_yaw;
+                self.settings.camera_pitch = defaults.camera_pitch;
+                self.settings.camera_distance = defaults.camera_distance;
+                self.settings.camera_fov = defaults.camera_fov;
+            }
+        });
+
+        ui.end_row();
+    }
+
+    /// Rows of the main settings grid for the shortcuts that stop interior points early
+    fn interior_settings_rows(&mut self, ui: &mut Ui) {
+        let flags = &mut self.settings.periodicity_flags;
+
+        ui.heading("Проверка периодичности");
+        ui.horizontal(|ui| {
+            let mut check = flags.contains(PeriodicityFlags::CHECK);
+            if ui
+                .checkbox(&mut check, "")
+                .on_hover_text(
+                    "Быстрее внутри множества, но может принять медленно убегающие точки \
+                     у границы за внутренние",
+                )
+                .changed()
+            {
+                flags.set(PeriodicityFlags::CHECK, check);
+            }
+            ui.add_enabled(
+                check,
+                DragValue::new(&mut self.settings.periodicity_epsilon)
+                    .speed(0.000_000_1)
+                    .range(1e-12..=0.01)
+                    .prefix("ε = "),
+            );
+        });
+
+        ui.end_row();
+
+        ui.heading("Окраска по периоду");
+        ui.add_enabled_ui(flags.contains(PeriodicityFlags::CHECK), |ui| {
+            let mut coloring = flags.contains(PeriodicityFlags::INTERIOR_COLORING);
+            if ui.checkbox(&mut coloring, "").changed() {
+                flags.set(PeriodicityFlags::INTERIOR_COLORING, coloring);
+            }
+        });
+
+        ui.end_row();
+
+        ui.heading("Кардиоида и круг периода 2");
+        ui.checkbox(&mut self.settings.cardioid_check, "")
+            .on_hover_text("Только для классического Мандельброта со степенью 2 и z0 = 0");
+
+        ui.end_row();
+    }
+
+    /// Rows of the main settings grid that only apply to the Lyapunov mode
+    fn lyapunov_settings_rows(&mut self, ui: &mut Ui) {
+        ui.heading("Последовательность");
+        ui.horizontal(|ui| {
+            let response = TextEdit::singleline(&mut self.settings.lyapunov_sequence)
+                .char_limit(MAX_LYAPUNOV_SEQUENCE)
+                .hint_text("AB")
+                .ui(ui);
+            if response.changed() {
+                self.settings.lyapunov_sequence = self
+                    .settings
+                    .lyapunov_sequence
+                    .to_uppercase()
+                    .chars()
+                    .filter(|letter| matches!(letter, 'A' | 'B'))
+                    .collect();
+            }
+        });
+
+        ui.end_row();
+
+        ui.heading("Прогрев");
+        DragValue::new(&mut self.settings.lyapunov_warmup)
+            .speed(1)
+            .range(0..=10_000)
+            .ui(ui);
+
+        ui.end_row();
+
+        ui.heading("Количество итераций");
+        DragValue::new(&mut self.settings.lyapunov_iterations)
+            .speed(1)
+            .range(1..=10_000)
+            .ui(ui);
+
+        ui.end_row();
+
+        ui.heading("Диапазон r");
+        ui.vertical(|ui| {
+            let mut ranges = self.settings.lyapunov_ranges();
+            let mut changed = false;
+            for (name, range) in ["A", "B"].iter().zip(&mut ranges) {
+                ui.horizontal(|ui| {
+                    ui.label(*name);
+                    let [from, to] = range;
+                    changed |= DragValue::new(from)
+                        .speed(0.01)
+                        .max_decimals(4)
+                        .ui(ui)
+                        .changed();
+                    ui.label("..");
+                    changed |= DragValue::new(to)
+                        .speed(0.01)
+                        .max_decimals(4)
+                        .ui(ui)
+                        .changed();
+                });
+            }
+            if changed {
+                self.settings.show_lyapunov_ranges(ranges);
+            }
+            if ui
+                .button("Сбросить")
+                .on_hover_text("r_A и r_B от 2 до 4")
+                .clicked()
+            {
+                self.settings.show_lyapunov_ranges(LYAPUNOV_RANGES);
+            }
+        });
+
+        ui.end_row();
+    }
+
+    fn hybrid_settings_ui(&mut self, ui: &mut Ui) {
+        let steps = &mut self.settings.hybrid_steps;
+        let mut removed = None;
+        let mut moved_up = None;
+        Grid::new("hybrid_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                for (i, step) in steps.iter_mut().enumerate() {
+                    ui.label(format!("Шаг {}", i + 1));
+                    ui.horizontal(|ui| {
+                        ComboBox::from_id_salt(("hybrid_step", i))
+                            .selected_text(variant_name(step.fractal_type))
+                            .show_ui(ui, |ui| {
+                                for variant in FractalType::HYBRID_STEPS {
+                                    ui.selectable_value(
+                                        &mut step.fractal_type,
+                                        variant,
+                                        variant_name(variant),
+                                    );
+                                }
+                            });
+                        DragValue::new(&mut step.repeats)
+                            .speed(0.1)
+                            .range(1..=HybridStep::MAX_REPEATS)
+                            .prefix("×")
+                            .ui(ui);
+                        if ui.add_enabled(i > 0, Button::new("⏶")).clicked() {
+                            moved_up = Some(i);
+                        }
+                        if ui.button("✖").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+
+                ui.label("");
+                ui.add_enabled_ui(steps.len() < MAX_HYBRID_STEPS, |ui| {
+                    if ui.button("Добавить шаг").clicked() {
+                        steps.push(HybridStep {
+                            fractal_type: FractalType::empty(),
+                            repeats: 1,
+                        });
+                    }
+                });
+                ui.end_row();
+            });
+
+        if let Some(i) = moved_up {
+            steps.swap(i - 1, i);
+        }
+        if let Some(i) = removed
+            && steps.len() > 1
+        {
+            steps.remove(i);
+        }
+    }
+
+    fn formula_settings_ui(&mut self, ui: &mut Ui) {
+        Grid::new("formula_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("z ←");
+                let response = TextEdit::singleline(&mut self.settings.formula)
+                    .code_editor()
+                    .hint_text(formula::DEFAULT_FORMULA)
+                    .ui(ui);
+                if response.changed() {
+                    self.apply_formula();
+                }
+                ui.end_row();
+
+                if let Some(error) = &self.formula_error {
+                    ui.label("");
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                    ui.end_row();
+                }
+
+                for &index in &self.formula_used_params {
+                    let param = &mut self.settings.formula_params[index];
+                    ui.label(format!("p{}", index + 1));
+                    ui.horizontal(|ui| {
+                        DragValue::new(&mut param[0]).speed(0.01).ui(ui);
+                        DragValue::new(&mut param[1]).speed(0.01).suffix("i").ui(ui);
+                    });
+                    ui.end_row();
+                }
+            });
+        ui.label("Функции: pow exp log sqrt sin cos tan sinh cosh abs arg conj real imag");
+    }
+
+    fn newton_settings_ui(&mut self, ui: &mut Ui) {
+        Grid::new("newton_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Многочлен");
+                ui.horizontal(|ui| {
+                    if ui
+                        .selectable_label(!self.newton_edit_coefficients, "Корни")
+                        .clicked()
+                    {
+                        self.newton_edit_coefficients = false;
+                    }
+                    if ui
+                        .selectable_label(self.newton_edit_coefficients, "Коэффициенты")
+                        .clicked()
+                    {
+                        self.newton_edit_coefficients = true;
+                        self.newton_coefficients =
+                            coefficients_from_roots(&self.settings.newton_roots);
+                    }
+                });
+                ui.end_row();
+
+                if self.newton_edit_coefficients {
+                    self.newton_coefficients_ui(ui);
+                } else {
+                    self.newton_roots_ui(ui);
+                }
+
+                ui.label("Релаксация");
+                ui.horizontal(|ui| {
+                    DragValue::new(&mut self.settings.newton_relaxation_x)
+                        .speed(0.01)
+                        .range(-3.0..=3.0)
+                        .ui(ui);
+                    DragValue::new(&mut self.settings.newton_relaxation_y)
+                        .speed(0.01)
+                        .range(-3.0..=3.0)
+                        .suffix("i")
+                        .ui(ui);
+                });
+                ui.end_row();
+
+                ui.label("Nova (+c)");
+                ui.checkbox(&mut self.settings.newton_nova, "")
+                    .on_hover_text(
+                        "В режиме Мандельброта начинается с z0 = 1 + начальное значение",
+                    );
+                ui.end_row();
+
+                ui.label("Точность");
+                DragValue::new(&mut self.settings.newton_tolerance)
+                    .speed(0.0001)
+                    .range(0.000_001..=0.5)
+                    .ui(ui);
+                ui.end_row();
+
+                ui.label("Показывать корни");
+                ui.checkbox(&mut self.settings.newton_show_roots, "");
+                ui.end_row();
+            });
+    }
+
+    fn newton_roots_ui(&mut self, ui: &mut Ui) {
+        let roots = &mut self.settings.newton_roots;
+        let mut removed = None;
+        for (i, root) in roots.iter_mut().enumerate() {
+            ui.label(format!("z{}", i + 1));
+            ui.horizontal(|ui| {
+                DragValue::new(&mut root[0]).speed(0.01).ui(ui);
+                DragValue::new(&mut root[1]).speed(0.01).suffix("i").ui(ui);
+                if ui.button("✖").clicked() {
+                    removed = Some(i);
+                }
+            });
+            ui.end_row();
+        }
+
+        if let Some(i) = removed
+            && roots.len() > 1
+        {
+            roots.remove(i);
+        }
+
+        ui.label("");
+        ui.add_enabled_ui(roots.len() < MAX_NEWTON_ROOTS, |ui| {
+            if ui.button("Добавить корень").clicked() {
+                roots.push([0.0, 0.0]);
+            }
+        });
+        ui.end_row();
+    }
+
+    /// Coefficients are listed from the highest degree; every edit recomputes the roots
+    fn newton_coefficients_ui(&mut self, ui: &mut Ui) {
+        let coefficients = &mut self.newton_coefficients;
+        let degree = coefficients.len().saturating_sub(1);
+        let mut changed = false;
+        for (i, coefficient) in coefficients.iter_mut().enumerate() {
+            ui.label(format!("z^{}", degree - i));
+            ui.horizontal(|ui| {
+                changed |= DragValue::new(&mut coefficient[0])
+                    .speed(0.01)
+                    .ui(ui)
+                    .changed();
+                changed |= DragValue::new(&mut coefficient[1])
+                    .speed(0.01)
+                    .suffix("i")
+                    .ui(ui)
+                    .changed();
+            });
+            ui.end_row();
+        }
+
+        ui.label("");
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(degree < MAX_NEWTON_ROOTS, |ui| {
+                if ui.button("Повысить степень").clicked() {
+                    coefficients.insert(0, [1.0, 0.0]);
+                    changed = true;
+                }
+            });
+            ui.add_enabled_ui(degree > 1, |ui| {
+                if ui.button("Понизить степень").clicked() {
+                    coefficients.remove(0);
+                    changed = true;
+                }
+            });
+        });
+        ui.end_row();
+
+        if changed {
+            let roots = roots_from_coefficients(coefficients);
+            if !roots.is_empty() {
+                self.settings.newton_roots = roots;
+            }
+        }
+    }
+
+    /// Maps a point of the complex plane to the screen the same way `fs_main` does
+    fn complex_to_screen(&self, rect: Rect, value: [f32; 2]) -> Pos2 {
+        let x = (value[0] - self.settings.center_x) * self.settings.zoom / 3.0 + 0.5;
+        let y = 0.5 - (value[1] - self.settings.center_y) * self.settings.zoom / 2.0;
+        rect.lerp_inside(egui::vec2(x, y))
+    }
+
+    /// Draws the Newton roots on top of the fractal and lets them be dragged
+    fn newton_root_markers(&mut self, ui: &mut Ui, rect: Rect) {
+        let marker_size = egui::vec2(14.0, 14.0);
+        let root_count = self.settings.newton_roots.len();
+        for i in 0..root_count {
+            let position = self.complex_to_screen(rect, self.settings.newton_roots[i]);
+            let response = ui.interact(
+                Rect::from_center_size(position, marker_size),
+                ui.id().with(("newton_root", i)),
+                Sense::drag(),
+            );
+
+            if response.dragged() {
+                let delta = response.drag_delta();
+                let root = &mut self.settings.newton_roots[i];
+                root[0] += delta.x / rect.width() * 3.0 / self.settings.zoom;
+                root[1] -= delta.y / rect.height() * 2.0 / self.settings.zoom;
+                self.newton_edit_coefficients = false;
+            }
+
+            let stroke_color = if response.hovered() || response.dragged() {
+                Color32::YELLOW
+            } else {
+                Color32::WHITE
+            };
+            let painter = ui.painter();
+            painter.circle(
+                position,
+                marker_size.x / 2.0,
+                Color32::from_black_alpha(120),
+                Stroke::new(2.0, stroke_color),
+            );
+            painter.text(
+                position + egui::vec2(marker_size.x, -marker_size.y),
+                Align2::LEFT_BOTTOM,
+                format!("z{}", i + 1),
+                FontId::proportional(14.0),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    /// Derives `max_iter` from the zoom, then raises it while the last measured frame had too many
+    /// pixels at the limit and lowers it back when there are few
+    fn update_auto_iterations(&mut self, ctx: &Context) {
+        let statistics = self
+            .render_state
+            .renderer
+            .read()
+            .callback_resources
+            .get::<FvRendererResource>()
+            .and_then(FvRendererResource::limit_statistics);
+
+        let is_current = statistics.is_some_and(|statistics| {
+            bytemuck::bytes_of(&statistics.uniforms)
+                == bytemuck::bytes_of(&self.settings.uniforms())
+        });
+        if let Some(statistics) = statistics
+            && is_current
+            && self.last_limit_statistics_id != Some(statistics.id)
+        {
+            self.last_limit_statistics_id = Some(statistics.id);
+            self.limit_fraction = Some(statistics.limit_fraction);
+            if statistics.limit_fraction > AUTO_ITERATIONS_TARGET {
+                self.auto_iterations_boost *= 1.5;
+            } else if statistics.limit_fraction < AUTO_ITERATIONS_TARGET / 4.0 {
+                self.auto_iterations_boost /= 1.1;
+            }
+            self.auto_iterations_boost = self
+                .auto_iterations_boost
+                .clamp(1.0, MAX_AUTO_ITERATIONS_BOOST);
+        }
+
+        let zoom_level = self.settings.zoom.max(1.0).log10();
+        let iterations =
+            AUTO_ITERATIONS_BASE * (1.0 + zoom_level).powi(2) * self.auto_iterations_boost;
+        self.settings.max_iter = (iterations.round() as u32).clamp(1, MAX_ITERATIONS);
+
+        // The measurement of the frame being rendered arrives a few frames later
+        if !is_current {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Restores the view from images saved by this program that are dropped onto the window
+    fn load_dropped_images(&mut self, ctx: &Context) {
+        if ctx.input(|input| !input.raw.hovered_files.is_empty()) {
+            egui::Area::new(egui::Id::new("drop_hint"))
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .interactable(false)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.heading("Отпустите PNG-изображение, чтобы восстановить его вид")
+                    });
+                });
+        }
+
+        let dropped_files = ctx.input(|input| input.raw.dropped_files.clone());
+        for file in dropped_files {
+            let name = file
+                .path
+                .as_ref()
+                .and_then(|path| path.file_name())
+                .map_or(file.name.clone(), |name| {
+                    name.to_string_lossy().into_owned()
+                });
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => Ok(bytes.to_vec()),
+                (None, Some(path)) => std::fs::read(path),
+                (None, None) => continue,
+            };
+
+            let message = match bytes.map(|bytes| read_parameters(bytes.as_slice())) {
+                Err(error) => format!("Не удалось открыть «{name}»: {error}"),
+                Ok(Err(error)) => {
+                    info!("Dropped file {name} is not a PNG image: {error}");
+                    format!("«{name}» — не PNG-изображение")
+                }
+                Ok(Ok(None)) => {
+                    format!("В «{name}» нет параметров фрактала, оно сохранено не этой программой")
+                }
+                Ok(Ok(Some(parameters))) => {
+                    match self.settings.with_image_parameters(&parameters) {
+                        Ok(settings) => {
+                            self.replace_settings(settings);
+                            format!("Вид восстановлен из «{name}»")
+                        }
+                        Err(error) => format!("Параметры в «{name}» повреждены: {error}"),
+                    }
+                }
+            };
+            self.show_message(message);
+        }
+    }
+
+    fn copy_location(&mut self, ctx: &Context) {
+        ctx.copy_text(encode_location(&self.settings));
+        self.show_message("Место скопировано".to_string());
+    }
+
+    /// Goes to a location pasted with the paste button, or with Ctrl+V while no text field
+    /// has the focus and takes the paste itself
+    fn paste_location(&mut self, ctx: &Context) {
+        let Some(text) = ctx.input(|input| {
+            input.events.iter().rev().find_map(|event| match event {
+                Event::Paste(text) => Some(text.clone()),
+                _ => None,
+            })
+        }) else {
+            return;
+        };
+        if !std::mem::take(&mut self.paste_requested)
+            && ctx.memory(|memory| memory.focused().is_some())
+        {
+            return;
+        }
+
+        let message = match decode_location(&text, &self.settings) {
+            Ok(settings) => {
+                self.replace_settings(settings);
+                "Место восстановлено".to_string()
+            }
+            Err(error) => {
+                info!("Pasted text is not a location: {error}");
+                format!("Строка не подходит: {error}")
+            }
+        };
+        self.show_message(message);
+    }
+
+    /// Switches to other settings, e.g. restored from an image or reset to the defaults
+    fn replace_settings(&mut self, settings: UserSettings) {
+        self.settings = settings;
+        self.apply_formula();
+        self.apply_domain_function();
+        self.newton_edit_coefficients = false;
+        self.buddhabrot_reset_requested = true;
+        self.auto_iterations_boost = 1.0;
+        self.animation_playback = None;
+        self.bookmark_transition = None;
+    }
+
+    fn pan_and_zoom(&mut self, ui: &Ui, response: &Response, size: Vec2) {
         let scale = 4.0 / self.settings.zoom / size.min_elem();
         if response.dragged_by(PointerButton::Primary) {
             let drag_motion = response.drag_delta();
@@ -310,31 +1649,80 @@ impl FractalApp {
         let scroll = ui.input(|i| i.raw_scroll_delta);
 
         self.settings.zoom += self.settings.zoom * (scroll.y / 380.0).max(-0.9);
-        let user_settings = &self.settings;
-        let uniforms = Uniforms {
-            max_iter: user_settings.max_iter,
-            zoom: user_settings.zoom,
-            center: [user_settings.center_x, user_settings.center_y, 0.0, 0.0],
-            rgb_green: user_settings.rgb_green,
-            rgb_blue: user_settings.rgb_blue,
-            color_scheme: self.settings.color_scheme.bits(),
-            hsv_saturation: self.settings.hsv_saturation,
-            hsv_brightness: self.settings.hsv_brightness,
-            show_axis: self.settings.show_axis as u8 as u32,
-            escape_threshold: self.settings.escape_threshold,
-            initial_value: [
-                self.settings.initial_value_x,
-                self.settings.initial_value_y,
-                0.0,
-                0.0,
-            ],
-            fractal_type: self.settings.fractal_type.bits(),
-            pow: self.settings.pow,
-            pad: [0; 4],
+    }
+
+    /// Same gestures as on the plane: the primary button rotates the camera around the fractal,
+    /// the secondary one moves the quaternion constant and the wheel moves the camera closer
+    fn orbit_camera(&mut self, ui: &Ui, response: &Response) {
+        if response.dragged_by(PointerButton::Primary) {
+            let drag_motion = response.drag_delta();
+            self.settings.camera_yaw -= drag_motion.x * 0.01;
+            self.settings.camera_pitch = (self.settings.camera_pitch + drag_motion.y * 0.01)
+                .clamp(-MAX_CAMERA_PITCH, MAX_CAMERA_PITCH);
+        }
+
+        if response.dragged_by(PointerButton::Secondary) {
+            let drag_motion = response.drag_delta();
+            self.settings.quaternion_c[0] -= drag_motion.x * 0.002;
+            self.settings.quaternion_c[1] += drag_motion.y * 0.002;
+        }
+
+        let scroll = ui.input(|i| i.raw_scroll_delta);
+
+        self.settings.camera_distance =
+            (self.settings.camera_distance / (1.0 + (scroll.y / 380.0).max(-0.9))).clamp(0.1, 20.0);
+    }
+
+    fn paint_fractal(&mut self, ui: &mut Ui, _ctx: &Context, _frame: &mut Frame) {
+        let size = ui.available_size().max(egui::vec2(400.0, 400.0));
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+
+        if self.settings.render_mode.contains(RenderMode::RAYMARCH_3D) {
+            self.orbit_camera(ui, &response);
+        } else {
+            self.pan_and_zoom(ui, &response, size);
+        }
+
+        if self.settings.auto_iterations
+            && self.settings.render_mode.contains(RenderMode::ESCAPE_TIME)
+        {
+            self.update_auto_iterations(ui.ctx());
+        }
+
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let buddhabrot_samples = if self.buddhabrot_paused {
+            0
+        } else {
+            self.settings.buddhabrot_samples_per_frame
+        };
+        self.view_size = [
+            (rect.width() * pixels_per_point).round() as u32,
+            (rect.height() * pixels_per_point).round() as u32,
+        ];
+        let frame = RenderFrame {
+            uniforms: self.settings.uniforms(),
+            size: self.view_size,
+            buddhabrot_samples,
+            buddhabrot_reset: std::mem::take(&mut self.buddhabrot_reset_requested),
         };
-        let callback = FvRenderCallback { uniforms };
+        let callback = FvRenderCallback { frame };
 
         ui.painter()
             .add(egui_wgpu::Callback::new_paint_callback(rect, callback));
+
+        if self.settings.render_mode.contains(RenderMode::ESCAPE_TIME)
+            && self.settings.fractal_type.contains(FractalType::NEWTON)
+            && self.settings.newton_show_roots
+        {
+            self.newton_root_markers(ui, rect);
+        }
+    }
+}
+
+fn variant_name(variant: FractalType) -> String {
+    if variant.is_empty() {
+        "Классический".to_string()
+    } else {
+        variant.to_string()
     }
 }
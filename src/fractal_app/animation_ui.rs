@@ -0,0 +1,279 @@
+//! Keyframe table of the animation, its preview and the export of its frames
+
+use super::FractalApp;
+use crate::animation::{self, Easing, Keyframe, MAX_ANIMATION_FPS};
+use crate::fv_renderer_resource::MAX_BUDDHABROT_SAMPLES_PER_FRAME;
+use crate::image_export::save_rendered_png;
+use crate::user_settings::UserSettings;
+use egui::{Button, ComboBox, DragValue, Grid, Ui, Widget};
+use log::{info, warn};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Frame sequence being written, one frame per update so that the window stays responsive
+pub(super) struct AnimationExport {
+    folder: PathBuf,
+    /// Settings and keyframes as they were when the export started
+    settings: UserSettings,
+    keyframes: Vec<Keyframe>,
+    next_frame: u32,
+    frame_count: u32,
+    started: Instant,
+}
+
+impl FractalApp {
+    pub(super) fn animation_settings_ui(&mut self, ui: &mut Ui) {
+        Grid::new("animation_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.heading("Анимация");
+                ui.horizontal(|ui| {
+                    if ui.button("Добавить кадр").clicked() {
+                        let mut keyframe = Keyframe::capture(&self.settings);
+                        if let Some(last) = self.settings.keyframes.last() {
+                            keyframe.duration = last.duration;
+                            keyframe.easing = last.easing;
+                        }
+                        self.settings.keyframes.push(keyframe);
+                    }
+
+                    let can_play = self.settings.keyframes.len() >= 2;
+                    if self.animation_playback.is_some() {
+                        if ui.button("■ Стоп").clicked() {
+                            self.animation_playback = None;
+                        }
+                    } else if ui
+                        .add_enabled(can_play, Button::new("▶ Просмотр"))
+                        .clicked()
+                    {
+                        self.animation_playback = Some(Instant::now());
+                    }
+
+                    if let Some(export) = &self.animation_export {
+                        ui.label(format!(
+                            "кадр {} из {}",
+                            export.next_frame, export.frame_count
+                        ));
+                        if ui.button("Отменить").clicked() {
+                            self.animation_export = None;
+                        }
+                    } else if ui
+                        .add_enabled(can_play, Button::new("Экспорт кадров…"))
+                        .on_hover_text("PNG-файлы frame_00000.png, … в выбранной папке")
+                        .clicked()
+                    {
+                        self.start_animation_export();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Длительность");
+                ui.label(format!(
+                    "{:.1} с, {} кадров",
+                    animation::duration(&self.settings.keyframes),
+                    animation::frame_count(&self.settings.keyframes, self.settings.animation_fps)
+                ));
+                ui.end_row();
+
+                ui.label("Кадров в секунду");
+                DragValue::new(&mut self.settings.animation_fps)
+                    .range(1..=MAX_ANIMATION_FPS)
+                    .ui(ui);
+                ui.end_row();
+
+                ui.label("Размер кадра");
+                ui.horizontal(|ui| {
+                    let [width, height] = &mut self.settings.animation_size;
+                    DragValue::new(width).range(1..=100_000).ui(ui);
+                    ui.label("×");
+                    DragValue::new(height).range(1..=100_000).ui(ui);
+                    ui.label(format!(
+                        "сглаживание ×{}",
+                        self.settings.screenshot_supersampling
+                    ))
+                    .on_hover_text("Как у снимков");
+                });
+                ui.end_row();
+            });
+
+        if !self.settings.keyframes.is_empty() {
+            self.keyframes_ui(ui);
+        }
+    }
+
+    /// Table of the keyframes with the transition into each of them
+    fn keyframes_ui(&mut self, ui: &mut Ui) {
+        enum Action {
+            Show(usize),
+            Update(usize),
+            Remove(usize),
+        }
+        let mut action = None;
+
+        Grid::new("keyframes")
+            .num_columns(4)
+            .striped(true)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                for (index, keyframe) in self.settings.keyframes.iter_mut().enumerate() {
+                    ui.label(format!("{}", index + 1))
+                        .on_hover_text(format!("масштаб {:.3e}", keyframe.zoom));
+
+                    if index == 0 {
+                        ui.label("начало");
+                        ui.label("");
+                    } else {
+                        DragValue::new(&mut keyframe.duration)
+                            .speed(0.05)
+                            .range(0.0..=600.0)
+                            .suffix(" с")
+                            .ui(ui);
+                        ComboBox::from_id_salt(("keyframe_easing", index))
+                            .selected_text(keyframe.easing.to_string())
+                            .show_ui(ui, |ui| {
+                                for easing in [
+                                    Easing::LINEAR,
+                                    Easing::EASE_IN,
+                                    Easing::EASE_OUT,
+                                    Easing::EASE_IN_OUT,
+                                ] {
+                                    ui.selectable_value(
+                                        &mut keyframe.easing,
+                                        easing,
+                                        easing.to_string(),
+                                    );
+                                }
+                            });
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Показать").clicked() {
+                            action = Some(Action::Show(index));
+                        }
+                        if ui
+                            .button("Обновить")
+                            .on_hover_text("Заменить текущим видом")
+                            .clicked()
+                        {
+                            action = Some(Action::Update(index));
+                        }
+                        if ui.button("✖").clicked() {
+                            action = Some(Action::Remove(index));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+
+        match action {
+            Some(Action::Show(index)) => {
+                self.animation_playback = None;
+                let keyframe = self.settings.keyframes[index];
+                keyframe.apply(&mut self.settings);
+            }
+            Some(Action::Update(index)) => {
+                let view = Keyframe::capture(&self.settings);
+                let keyframe = &mut self.settings.keyframes[index];
+                *keyframe = Keyframe {
+                    duration: keyframe.duration,
+                    easing: keyframe.easing,
+                    ..view
+                };
+            }
+            Some(Action::Remove(index)) => {
+                self.settings.keyframes.remove(index);
+            }
+            None => {}
+        }
+    }
+
+    /// Shows the view of the animation at the time since the playback started
+    pub(super) fn play_animation(&mut self) {
+        let Some(started) = self.animation_playback else {
+            return;
+        };
+        let time = started.elapsed().as_secs_f32();
+        if let Some(view) = animation::view_at(&self.settings.keyframes, time) {
+            view.apply(&mut self.settings);
+        }
+        if time >= animation::duration(&self.settings.keyframes) {
+            self.animation_playback = None;
+        }
+    }
+
+    fn start_animation_export(&mut self) {
+        let mut dialog = rfd::FileDialog::new();
+        if !self.settings.screenshot_folder.is_empty() {
+            dialog = dialog.set_directory(&self.settings.screenshot_folder);
+        }
+        let Some(folder) = dialog.pick_folder() else {
+            return;
+        };
+
+        let keyframes = self.settings.keyframes.clone();
+        self.animation_playback = None;
+        self.animation_export = Some(AnimationExport {
+            folder,
+            frame_count: animation::frame_count(&keyframes, self.settings.animation_fps),
+            settings: self.settings.clone(),
+            keyframes,
+            next_frame: 0,
+            started: Instant::now(),
+        });
+    }
+
+    /// Renders the next frame of the running export offscreen
+    pub(super) fn export_animation_frame(&mut self) {
+        let Some(mut export) = self.animation_export.take() else {
+            return;
+        };
+
+        let settings = &export.settings;
+        let frame_settings = animation::frame_settings(
+            settings,
+            &export.keyframes,
+            settings.animation_fps,
+            export.next_frame,
+        );
+        let path = animation::frame_path(&export.folder, export.next_frame);
+        let [width, height] = settings.animation_size;
+        let buddhabrot_samples = self
+            .buddhabrot_samples
+            .max(MAX_BUDDHABROT_SAMPLES_PER_FRAME as u64);
+        let renderer = self.screenshot_renderer();
+        renderer.buddhabrot_samples = buddhabrot_samples;
+        let result = save_rendered_png(
+            renderer,
+            &frame_settings,
+            width,
+            height,
+            settings.screenshot_supersampling,
+            &path,
+        );
+
+        if let Err(error) = result {
+            warn!("Failed to save {}: {error}", path.display());
+            self.show_message(format!("Не удалось сохранить {}: {error}", path.display()));
+            return;
+        }
+
+        export.next_frame += 1;
+        if export.next_frame < export.frame_count {
+            self.animation_export = Some(export);
+            return;
+        }
+
+        info!(
+            "Saved {} frames into {} in {:.2?}",
+            export.frame_count,
+            export.folder.display(),
+            export.started.elapsed()
+        );
+        self.show_message(format!(
+            "Сохранено кадров: {} в {}",
+            export.frame_count,
+            export.folder.display()
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+//! Side panel of the bookmarks with their thumbnails and the transitions to them
+
+use super::FractalApp;
+use crate::animation::{Easing, Keyframe};
+use crate::bookmarks::{BOOKMARKS_FILE_NAME, Bookmark, load_bookmarks, save_bookmarks};
+use crate::fv_renderer_resource::MAX_BUDDHABROT_SAMPLES_PER_FRAME;
+use crate::image_export::render_image;
+use crate::user_settings::UserSettings;
+use egui::{Button, Context, TextEdit, TextureHandle, Ui, Vec2, Widget};
+use log::warn;
+use std::time::Instant;
+
+const THUMBNAIL_SIZE: [u32; 2] = [150, 100];
+const BOOKMARK_TRANSITION_SECONDS: f32 = 1.5;
+
+pub(super) struct BookmarkEntry {
+    bookmark: Bookmark,
+    /// `None` until rendered, `Some(None)` when the view cannot be rendered
+    thumbnail: Option<Option<TextureHandle>>,
+}
+
+impl BookmarkEntry {
+    pub(super) fn new(bookmark: Bookmark) -> Self {
+        Self {
+            bookmark,
+            thumbnail: None,
+        }
+    }
+}
+
+impl FractalApp {
+    pub(super) fn bookmarks_ui(&mut self, ui: &mut Ui) {
+        ui.heading("Закладки");
+        ui.horizontal(|ui| {
+            TextEdit::singleline(&mut self.bookmark_name)
+                .hint_text("название")
+                .desired_width(THUMBNAIL_SIZE[0] as f32)
+                .ui(ui);
+            if ui
+                .button("Добавить")
+                .on_hover_text("Сохранить текущий вид")
+                .clicked()
+            {
+                let name = match self.bookmark_name.trim() {
+                    "" => format!("Закладка {}", self.bookmarks.len() + 1),
+                    name => name.to_string(),
+                };
+                self.bookmarks
+                    .push(BookmarkEntry::new(Bookmark::capture(name, &self.settings)));
+                self.bookmark_name.clear();
+                self.save_bookmarks();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Импорт…").clicked() {
+                self.import_bookmarks();
+            }
+            if ui.button("Экспорт…").clicked() {
+                self.export_bookmarks();
+            }
+        });
+        ui.separator();
+
+        let mut go_to = None;
+        let mut remove = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            let size = Vec2::new(THUMBNAIL_SIZE[0] as f32, THUMBNAIL_SIZE[1] as f32);
+            for (index, entry) in self.bookmarks.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let response = match &entry.thumbnail {
+                        Some(Some(texture)) => ui.add(Button::image(
+                            egui::Image::from_texture(egui::load::SizedTexture::from_handle(
+                                texture,
+                            ))
+                            .fit_to_exact_size(size),
+                        )),
+                        Some(None) => ui.add_sized(size, Button::new("нет превью")),
+                        None => ui.add_sized(size, Button::new("…")),
+                    };
+                    if response.on_hover_text("Перейти").clicked() {
+                        go_to = Some(index);
+                    }
+                    ui.vertical(|ui| {
+                        ui.label(&entry.bookmark.name);
+                        if ui.small_button("✖").on_hover_text("Удалить").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                });
+            }
+        });
+
+        if let Some(index) = go_to {
+            self.go_to_bookmark(index);
+        }
+        if let Some(index) = remove {
+            self.bookmarks.remove(index);
+            self.save_bookmarks();
+        }
+    }
+
+    /// Renders the thumbnail of one bookmark per frame, so that a long list does not stall the window
+    pub(super) fn render_next_thumbnail(&mut self, ctx: &Context) {
+        let Some(index) = self
+            .bookmarks
+            .iter()
+            .position(|entry| entry.thumbnail.is_none())
+        else {
+            return;
+        };
+
+        let bookmark = self.bookmarks[index].bookmark.clone();
+        let [width, height] = THUMBNAIL_SIZE;
+        let image = bookmark
+            .settings(&UserSettings::new())
+            .map_err(|error| error.to_string())
+            .and_then(|settings| {
+                let renderer = self.screenshot_renderer();
+                renderer.buddhabrot_samples = MAX_BUDDHABROT_SAMPLES_PER_FRAME as u64;
+                render_image(renderer, &settings, width, height, 1)
+                    .map_err(|error| error.to_string())
+            });
+
+        self.bookmarks[index].thumbnail = Some(match image {
+            Ok(image) => Some(ctx.load_texture(
+                format!("bookmark_{index}"),
+                egui::ColorImage::from_rgba_unmultiplied(
+                    [width as usize, height as usize],
+                    &image.pixels,
+                ),
+                Default::default(),
+            )),
+            Err(error) => {
+                warn!(
+                    "Failed to render the thumbnail of {}: {error}",
+                    bookmark.name
+                );
+                None
+            }
+        });
+        ctx.request_repaint();
+    }
+
+    /// Switches to the bookmarked settings and moves the view there
+    fn go_to_bookmark(&mut self, index: usize) {
+        let bookmark = &self.bookmarks[index].bookmark;
+        let settings = match bookmark.settings(&self.settings) {
+            Ok(settings) => settings,
+            Err(error) => {
+                let message = format!("Закладка «{}» повреждена: {error}", bookmark.name);
+                self.show_message(message);
+                return;
+            }
+        };
+
+        let from = Keyframe::capture(&self.settings);
+        let to = Keyframe::capture(&settings);
+        self.replace_settings(settings);
+        from.apply(&mut self.settings);
+        self.bookmark_transition = Some((from, to, Instant::now()));
+    }
+
+    pub(super) fn play_bookmark_transition(&mut self) {
+        let Some((from, to, started)) = self.bookmark_transition else {
+            return;
+        };
+        let t = started.elapsed().as_secs_f32() / BOOKMARK_TRANSITION_SECONDS;
+        from.interpolate(&to, Easing::EASE_IN_OUT.apply(t))
+            .apply(&mut self.settings);
+        if t >= 1.0 {
+            self.bookmark_transition = None;
+        }
+    }
+
+    fn save_bookmarks(&mut self) {
+        let Some(path) = &self.bookmarks_path else {
+            return;
+        };
+        let bookmarks: Vec<_> = self
+            .bookmarks
+            .iter()
+            .map(|entry| entry.bookmark.clone())
+            .collect();
+        if let Err(error) = save_bookmarks(&bookmarks, path) {
+            warn!("Failed to save bookmarks to {}: {error}", path.display());
+            let message = format!("Не удалось сохранить закладки: {error}");
+            self.show_message(message);
+        }
+    }
+
+    /// Adds the bookmarks of a shared file to the list
+    fn import_bookmarks(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+
+        let message = match load_bookmarks(&path) {
+            Ok(bookmarks) => {
+                let count = bookmarks.len();
+                self.bookmarks
+                    .extend(bookmarks.into_iter().map(BookmarkEntry::new));
+                self.save_bookmarks();
+                format!("Импортировано закладок: {count}")
+            }
+            Err(error) => {
+                warn!(
+                    "Failed to import bookmarks from {}: {error}",
+                    path.display()
+                );
+                format!("Не удалось прочитать {}: {error}", path.display())
+            }
+        };
+        self.show_message(message);
+    }
+
+    fn export_bookmarks(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name(BOOKMARKS_FILE_NAME)
+            .save_file()
+        else {
+            return;
+        };
+
+        let bookmarks: Vec<_> = self
+            .bookmarks
+            .iter()
+            .map(|entry| entry.bookmark.clone())
+            .collect();
+        let message = match save_bookmarks(&bookmarks, &path) {
+            Ok(()) => format!("Закладки сохранены: {}", path.display()),
+            Err(error) => {
+                warn!("Failed to export bookmarks to {}: {error}", path.display());
+                format!("Не удалось сохранить {}: {error}", path.display())
+            }
+        };
+        self.show_message(message);
+    }
+}
@@ -0,0 +1,520 @@
+//! Screenshot, raw data and loop exports: their settings and the exports running
+//! one strip or frame per update
+
+use super::FractalApp;
+use crate::animation::{LoopKind, MAX_LOOP_FRAMES};
+use crate::fractal_renderer::FractalRenderer;
+use crate::fv_renderer_resource::MAX_BUDDHABROT_SAMPLES_PER_FRAME;
+use crate::image_export::{LoopExport, MAX_SUPERSAMPLING, PngExport, RawDataExport};
+use crate::uniforms::RenderMode;
+use egui::{Button, DragValue, Grid, TextEdit, Ui, ViewportCommand, Widget};
+use log::{info, warn};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Screenshot being written strip by strip, one strip per update
+pub(super) struct ScreenshotExport {
+    png: PngExport,
+    path: PathBuf,
+    started: Instant,
+}
+
+/// Raw data being computed and written, one strip per update
+pub(super) struct RawDataFileExport {
+    file: RawDataExport,
+    path: PathBuf,
+    started: Instant,
+}
+
+/// GIF or APNG loop being written, one frame per update
+pub(super) struct LoopFileExport {
+    file: LoopExport<BufWriter<File>>,
+    path: PathBuf,
+    started: Instant,
+}
+
+impl FractalApp {
+    pub(super) fn screenshot_settings_ui(&mut self, ui: &mut Ui) {
+        Grid::new("screenshot_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.heading("Снимок");
+                ui.horizontal(|ui| {
+                    if let Some(export) = &self.screenshot_export {
+                        ui.label(format!("{:.0}%", export.png.progress() * 100.0));
+                        if ui.button("Отменить").clicked() {
+                            self.cancel_screenshot();
+                        }
+                    } else {
+                        if ui.button("Сохранить").on_hover_text("F12").clicked() {
+                            self.save_screenshot(false);
+                        }
+                        if ui.button("Сохранить как…").clicked() {
+                            self.save_screenshot(true);
+                        }
+                    }
+                    let escape_time = self.settings.render_mode == RenderMode::ESCAPE_TIME;
+                    if let Some(export) = &self.raw_data_export {
+                        ui.label(format!(
+                            "сырые данные {:.0}%",
+                            export.file.progress() * 100.0
+                        ));
+                        if ui.button("Отменить").clicked() {
+                            self.cancel_raw_data_export();
+                        }
+                    } else if ui
+                        .add_enabled(escape_time, Button::new("Сырые данные…"))
+                        .on_hover_text(
+                            "Итерации, сглаженные итерации, оценка расстояния и последнее z \
+                             каждого пикселя в NPY, EXR или 16-битных PNG",
+                        )
+                        .on_disabled_hover_text("Только в режиме времени убегания")
+                        .clicked()
+                    {
+                        self.save_raw_data();
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Место");
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Копировать")
+                        .on_hover_text("Скопировать вид строкой, чтобы отправить его в чат")
+                        .clicked()
+                    {
+                        self.copy_location(ui.ctx());
+                    }
+                    if ui
+                        .button("Вставить")
+                        .on_hover_text("Перейти к виду из скопированной строки, или Ctrl+V")
+                        .clicked()
+                    {
+                        self.paste_requested = true;
+                        ui.ctx().send_viewport_cmd(ViewportCommand::RequestPaste);
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Размер");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.settings.screenshot_custom_size, "Свой");
+                    if self.settings.screenshot_custom_size {
+                        let [width, height] = &mut self.settings.screenshot_size;
+                        DragValue::new(width).range(1..=100_000).ui(ui);
+                        ui.label("×");
+                        DragValue::new(height).range(1..=100_000).ui(ui);
+                    } else {
+                        let [width, height] = self.view_size;
+                        ui.label(format!("{width}×{height}, как окно"));
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Сглаживание");
+                DragValue::new(&mut self.settings.screenshot_supersampling)
+                    .range(1..=MAX_SUPERSAMPLING)
+                    .prefix("×")
+                    .ui(ui)
+                    .on_hover_text("Число отсчётов на сторону пикселя");
+                ui.end_row();
+
+                ui.label("Папка");
+                ui.horizontal(|ui| {
+                    TextEdit::singleline(&mut self.settings.screenshot_folder)
+                        .hint_text("рабочая папка")
+                        .desired_width(200.0)
+                        .ui(ui);
+                    if ui.button("Выбрать…").clicked()
+                        && let Some(folder) = rfd::FileDialog::new().pick_folder()
+                    {
+                        self.settings.screenshot_folder = folder.display().to_string();
+                    }
+                });
+                ui.end_row();
+            });
+    }
+
+    /// Starts rendering the current view offscreen, without the settings window, into a PNG.
+    /// Quick screenshots go to the screenshot folder under a timestamped name
+    pub(super) fn save_screenshot(&mut self, ask_path: bool) {
+        if self.screenshot_export.is_some() {
+            self.show_message("Предыдущий снимок ещё сохраняется".to_string());
+            return;
+        }
+
+        let folder = PathBuf::from(&self.settings.screenshot_folder);
+        let file_name = format!(
+            "mandelbrot-{}.png",
+            jiff::Zoned::now().strftime("%Y-%m-%d-%H%M%S")
+        );
+        let path = if ask_path {
+            let mut dialog = rfd::FileDialog::new()
+                .add_filter("PNG", &["png"])
+                .set_file_name(&file_name);
+            if !self.settings.screenshot_folder.is_empty() {
+                dialog = dialog.set_directory(&folder);
+            }
+            match dialog.save_file() {
+                Some(path) => path,
+                None => return,
+            }
+        } else {
+            folder.join(file_name)
+        };
+
+        let [width, height] = self.screenshot_size();
+        let buddhabrot_samples = self
+            .buddhabrot_samples
+            .max(MAX_BUDDHABROT_SAMPLES_PER_FRAME as u64);
+        let settings = self.settings.clone();
+        let renderer = self.screenshot_renderer();
+        renderer.buddhabrot_samples = buddhabrot_samples;
+
+        match PngExport::create(
+            renderer,
+            &settings,
+            width,
+            height,
+            settings.screenshot_supersampling,
+            &path,
+        ) {
+            Ok(png) => {
+                self.screenshot_export = Some(ScreenshotExport {
+                    png,
+                    path,
+                    started: Instant::now(),
+                })
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                self.show_message(format!("Не удалось сохранить {}: {error}", path.display()));
+            }
+        }
+    }
+
+    /// Renders the next strip of the running screenshot, the window stays responsive in between
+    pub(super) fn export_screenshot_strip(&mut self) {
+        let Some(mut export) = self.screenshot_export.take() else {
+            return;
+        };
+
+        let result = export.png.render_strip(self.screenshot_renderer());
+        if result.is_ok() && !export.png.is_done() {
+            self.show_message(format!(
+                "Сохраняется снимок: {:.0}%",
+                export.png.progress() * 100.0
+            ));
+            self.screenshot_export = Some(export);
+            return;
+        }
+
+        let ScreenshotExport { png, path, started } = export;
+        let message = match result.and_then(|()| png.finish()) {
+            Ok(()) => {
+                info!("Saved {} in {:.2?}", path.display(), started.elapsed());
+                format!("Сохранено: {}", path.display())
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                // The cut off file is not a valid PNG
+                let _ = std::fs::remove_file(&path);
+                format!("Не удалось сохранить {}: {error}", path.display())
+            }
+        };
+        self.show_message(message);
+    }
+
+    fn cancel_screenshot(&mut self) {
+        let Some(export) = self.screenshot_export.take() else {
+            return;
+        };
+        let path = export.path.clone();
+        drop(export);
+        let _ = std::fs::remove_file(&path);
+        self.show_message("Снимок отменён".to_string());
+    }
+
+    /// Computes the raw escape time values of the current view at the screenshot size and saves
+    /// them in the format picked in the save dialog
+    fn save_raw_data(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("NumPy", &["npy"])
+            .add_filter("OpenEXR", &["exr"])
+            .add_filter("16-битные PNG", &["png"])
+            .set_file_name(format!(
+                "mandelbrot-{}.npy",
+                jiff::Zoned::now().strftime("%Y-%m-%d-%H%M%S")
+            ));
+        if !self.settings.screenshot_folder.is_empty() {
+            dialog = dialog.set_directory(&self.settings.screenshot_folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+
+        let [width, height] = self.screenshot_size();
+        let settings = self.settings.clone();
+        match RawDataExport::create(self.screenshot_renderer(), &settings, width, height, &path) {
+            Ok(file) => {
+                self.raw_data_export = Some(RawDataFileExport {
+                    file,
+                    path,
+                    started: Instant::now(),
+                })
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                self.show_message(format!("Не удалось сохранить {}: {error}", path.display()));
+            }
+        }
+    }
+
+    /// Computes and writes the next strip of the running raw data export
+    pub(super) fn export_raw_data_strip(&mut self) {
+        let Some(mut export) = self.raw_data_export.take() else {
+            return;
+        };
+
+        let result = export.file.render_strip(self.screenshot_renderer());
+        if result.is_ok() && !export.file.is_done() {
+            self.show_message(format!(
+                "Сохраняются сырые данные: {:.0}%",
+                export.file.progress() * 100.0
+            ));
+            self.raw_data_export = Some(export);
+            return;
+        }
+
+        let RawDataFileExport {
+            file,
+            path,
+            started,
+        } = export;
+        let written = file.paths().to_vec();
+        let message = match result.and_then(|()| file.finish()) {
+            Ok(paths) => {
+                info!("Saved {} in {:.2?}", path.display(), started.elapsed());
+                match paths.as_slice() {
+                    [path] => format!("Сохранено: {}", path.display()),
+                    _ => format!(
+                        "Сохранено файлов: {} рядом с {}",
+                        paths.len(),
+                        path.display()
+                    ),
+                }
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                remove_files(&written);
+                format!("Не удалось сохранить {}: {error}", path.display())
+            }
+        };
+        self.show_message(message);
+    }
+
+    fn cancel_raw_data_export(&mut self) {
+        let Some(export) = self.raw_data_export.take() else {
+            return;
+        };
+        let written = export.file.paths().to_vec();
+        drop(export);
+        remove_files(&written);
+        self.show_message("Сохранение сырых данных отменено".to_string());
+    }
+
+    /// Offscreen renderer sharing the device of the window, created on first use
+    pub(super) fn screenshot_renderer(&mut self) -> &mut FractalRenderer {
+        self.screenshot_renderer.get_or_insert_with(|| {
+            FractalRenderer::from_device(
+                self.render_state.device.clone(),
+                self.render_state.queue.clone(),
+                self.render_state.adapter.get_info(),
+            )
+        })
+    }
+
+    fn screenshot_size(&self) -> [u32; 2] {
+        if self.settings.screenshot_custom_size {
+            self.settings.screenshot_size
+        } else {
+            self.view_size
+        }
+    }
+
+    pub(super) fn loop_settings_ui(&mut self, ui: &mut Ui) {
+        Grid::new("loop_settings")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.heading("Петля");
+                if let Some(export) = &self.loop_export {
+                    let progress = format!(
+                        "кадр {} из {}",
+                        export.file.next_frame(),
+                        export.file.frame_count()
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label(progress);
+                        if ui.button("Отменить").clicked() {
+                            self.cancel_loop_export();
+                        }
+                    });
+                } else if ui
+                    .button("Сохранить GIF / APNG…")
+                    .on_hover_text("Бесконечно повторяющаяся анимация без склейки")
+                    .clicked()
+                {
+                    self.save_loop();
+                }
+                ui.end_row();
+
+                ui.label("Что меняется");
+                ui.vertical(|ui| {
+                    let kind = &mut self.settings.loop_kind;
+                    for flag in [
+                        LoopKind::PALETTE_CYCLE,
+                        LoopKind::JULIA_ORBIT,
+                        LoopKind::POW_MORPH,
+                    ] {
+                        let mut checked = kind.contains(flag);
+                        if ui.checkbox(&mut checked, flag.to_string()).changed() {
+                            kind.set(flag, checked);
+                        }
+                    }
+                });
+                ui.end_row();
+
+                if self.settings.loop_kind.contains(LoopKind::JULIA_ORBIT) {
+                    ui.label("Радиус орбиты");
+                    DragValue::new(&mut self.settings.loop_orbit_radius)
+                        .speed(0.001)
+                        .range(0.0..=3.0)
+                        .ui(ui)
+                        .on_hover_text("Вокруг текущего начального значения");
+                    ui.end_row();
+                }
+
+                if self.settings.loop_kind.contains(LoopKind::POW_MORPH) {
+                    ui.label("Степень");
+                    ui.horizontal(|ui| {
+                        DragValue::new(&mut self.settings.pow_animation_from)
+                            .speed(0.01)
+                            .range(-255.0..=255.0)
+                            .ui(ui);
+                        ui.label("…");
+                        DragValue::new(&mut self.settings.pow_animation_to)
+                            .speed(0.01)
+                            .range(-255.0..=255.0)
+                            .ui(ui);
+                    });
+                    ui.end_row();
+                }
+
+                ui.label("Длительность");
+                DragValue::new(&mut self.settings.loop_duration)
+                    .speed(0.05)
+                    .range(0.1..=60.0)
+                    .suffix(" с")
+                    .ui(ui);
+                ui.end_row();
+
+                ui.label("Кадров");
+                DragValue::new(&mut self.settings.loop_frames)
+                    .range(1..=MAX_LOOP_FRAMES)
+                    .ui(ui);
+                ui.end_row();
+
+                ui.label("Размер");
+                ui.horizontal(|ui| {
+                    let [width, height] = &mut self.settings.loop_size;
+                    DragValue::new(width).range(1..=u16::MAX as u32).ui(ui);
+                    ui.label("×");
+                    DragValue::new(height).range(1..=u16::MAX as u32).ui(ui);
+                });
+                ui.end_row();
+            });
+    }
+
+    fn save_loop(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("GIF", &["gif"])
+            .add_filter("APNG", &["png", "apng"])
+            .set_file_name(format!(
+                "mandelbrot-{}.gif",
+                jiff::Zoned::now().strftime("%Y-%m-%d-%H%M%S")
+            ));
+        if !self.settings.screenshot_folder.is_empty() {
+            dialog = dialog.set_directory(&self.settings.screenshot_folder);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+
+        match LoopExport::create(&self.settings, &path) {
+            Ok(file) => {
+                self.loop_export = Some(LoopFileExport {
+                    file,
+                    path,
+                    started: Instant::now(),
+                })
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                self.show_message(format!("Не удалось сохранить {}: {error}", path.display()));
+            }
+        }
+    }
+
+    /// Renders and quantizes the next frame of the running loop export
+    pub(super) fn export_loop_frame(&mut self) {
+        let Some(mut export) = self.loop_export.take() else {
+            return;
+        };
+
+        let result = export.file.render_frame(self.screenshot_renderer());
+        if result.is_ok() && !export.file.is_done() {
+            self.loop_export = Some(export);
+            return;
+        }
+
+        let LoopFileExport {
+            file,
+            path,
+            started,
+        } = export;
+        let message = match result.and_then(|()| file.finish()) {
+            Ok(()) => {
+                info!("Saved {} in {:.2?}", path.display(), started.elapsed());
+                format!("Сохранено: {}", path.display())
+            }
+            Err(error) => {
+                warn!("Failed to save {}: {error}", path.display());
+                // The cut off file is not a valid animation
+                let _ = std::fs::remove_file(&path);
+                format!("Не удалось сохранить {}: {error}", path.display())
+            }
+        };
+        self.show_message(message);
+    }
+
+    fn cancel_loop_export(&mut self) {
+        let Some(export) = self.loop_export.take() else {
+            return;
+        };
+        let path = export.path.clone();
+        drop(export);
+        let _ = std::fs::remove_file(&path);
+        self.show_message("Сохранение петли отменено".to_string());
+    }
+}
+
+/// Removes the files of a failed or cancelled export, they are incomplete
+fn remove_files(paths: &[PathBuf]) {
+    for path in paths {
+        let _ = std::fs::remove_file(path);
+    }
+}
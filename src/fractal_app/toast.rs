@@ -0,0 +1,33 @@
+//! Short messages at the bottom of the window, e.g. about saved files
+
+use super::FractalApp;
+use egui::{Align2, Context};
+use std::time::{Duration, Instant};
+
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+impl FractalApp {
+    pub(super) fn show_message(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+
+    /// Shows the last message for a few seconds at the bottom of the window
+    pub(super) fn show_toast(&mut self, ctx: &Context) {
+        let Some((message, shown)) = &self.toast else {
+            return;
+        };
+        let remaining = TOAST_DURATION.saturating_sub(shown.elapsed());
+        if remaining.is_zero() {
+            self.toast = None;
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("toast"))
+            .anchor(Align2::CENTER_BOTTOM, [0.0, -24.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(message));
+            });
+        ctx.request_repaint_after(remaining);
+    }
+}
@@ -0,0 +1,551 @@
+//! Headless rendering into an offscreen texture, for tools and tests that run without a window.
+//! Falls back to a software adapter (llvmpipe, lavapipe, WARP) when there is no GPU.
+
+use crate::formula;
+use crate::fv_renderer_resource::{
+    FvRendererResource, MAX_BUDDHABROT_SAMPLES_PER_FRAME, RAW_DATA_CHANNELS, RenderFrame,
+    raw_data_size,
+};
+use crate::uniforms::{RenderMode, Uniforms};
+use crate::user_settings::UserSettings;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use wgpu::{
+    AdapterInfo, Buffer, BufferAsyncError, BufferDescriptor, BufferUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT, Color, CommandEncoderDescriptor, Device, DeviceDescriptor,
+    Extent3d, Instance, InstanceDescriptor, LoadOp, MapMode, Operations, PollType, PowerPreference,
+    Queue, RenderPassColorAttachment, RenderPassDescriptor, RequestAdapterOptions, StoreOp,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
+
+/// Format of the offscreen texture, the pixels are read back exactly as the shader writes them
+const FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+/// Side of the tiles of large images, small enough for the memory of most devices
+pub const DEFAULT_TILE_SIZE: u32 = 2048;
+
+/// Orbits traced for a Buddhabrot image unless [`FractalRenderer::buddhabrot_samples`] says otherwise
+pub const DEFAULT_BUDDHABROT_SAMPLES: u64 = 16 * MAX_BUDDHABROT_SAMPLES_PER_FRAME as u64;
+
+#[derive(Debug)]
+pub enum RendererError {
+    NoAdapter(wgpu::RequestAdapterError),
+    RequestDevice(wgpu::RequestDeviceError),
+    InvalidSize {
+        width: u32,
+        height: u32,
+    },
+    InvalidRows {
+        rows: Range<u32>,
+        height: u32,
+    },
+    /// Buddhabrot images must fit into a single tile
+    TiledBuddhabrot {
+        max: u32,
+    },
+    Poll(wgpu::PollError),
+    Readback(BufferAsyncError),
+    /// The custom formula or the domain function does not compile
+    Formula(String),
+    /// Raw data exists only for [`RenderMode::ESCAPE_TIME`]
+    RawDataMode,
+    /// The result does not fit into the memory
+    OutOfMemory {
+        bytes: u64,
+    },
+}
+
+impl Display for RendererError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoAdapter(error) => write!(f, "no graphics adapter found: {error}"),
+            Self::RequestDevice(error) => write!(f, "failed to create a device: {error}"),
+            Self::InvalidSize { width, height } => {
+                write!(f, "invalid image size {width}x{height}")
+            }
+            Self::InvalidRows { rows, height } => write!(
+                f,
+                "rows {}..{} do not fit into an image of height {height}",
+                rows.start, rows.end
+            ),
+            Self::TiledBuddhabrot { max } => {
+                write!(f, "Buddhabrot images are limited to {max}x{max} pixels")
+            }
+            Self::Poll(error) => write!(f, "failed to wait for the GPU: {error}"),
+            Self::Readback(error) => write!(f, "failed to read back the image: {error}"),
+            Self::Formula(error) => write!(f, "invalid formula: {error}"),
+            Self::RawDataMode => write!(f, "raw data is only available in escape time mode"),
+            Self::OutOfMemory { bytes } => {
+                write!(f, "{bytes} bytes for the result do not fit into the memory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+/// Tightly packed RGBA pixels, row by row from the top
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Averages blocks of `factor` x `factor` pixels, the size must be divisible by the factor
+    pub fn downsample(&self, factor: u32) -> Image {
+        if factor <= 1 {
+            return self.clone();
+        }
+
+        let width = self.width / factor;
+        let height = self.height / factor;
+        let samples = factor * factor;
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 4];
+                for sample_y in y * factor..(y + 1) * factor {
+                    let row = (sample_y * self.width + x * factor) as usize * 4;
+                    for pixel in self.pixels[row..row + factor as usize * 4].chunks_exact(4) {
+                        for (sum, &channel) in sum.iter_mut().zip(pixel) {
+                            *sum += channel as u32;
+                        }
+                    }
+                }
+                pixels.extend(sum.map(|sum| ((sum + samples / 2) / samples) as u8));
+            }
+        }
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Raw escape time values instead of colors, row by row from the top.
+/// Every pixel holds one value of each of [`RawData::CHANNELS`]
+#[derive(Clone)]
+pub struct RawData {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f32>,
+}
+
+impl RawData {
+    /// Iterations until escape, -1 inside the set; smooth iterations with the fraction of the last
+    /// step; distance estimate, 0 inside and -1 where none exists; real and imaginary part of z
+    /// at the last iteration
+    pub const CHANNELS: [&str; RAW_DATA_CHANNELS] = [
+        "iterations",
+        "smooth_iterations",
+        "distance",
+        "z_re",
+        "z_im",
+    ];
+
+    /// Values of one channel of every pixel
+    pub fn channel(&self, index: usize) -> impl Iterator<Item = f32> + '_ {
+        self.values
+            .iter()
+            .skip(index)
+            .step_by(RAW_DATA_CHANNELS)
+            .copied()
+    }
+}
+
+/// Owns a `wgpu` device and renders fractals without a window
+pub struct FractalRenderer {
+    device: Device,
+    queue: Queue,
+    adapter_info: AdapterInfo,
+    resource: FvRendererResource,
+    /// Sources of the custom formula and the domain function the pipelines were built with
+    formula: Option<String>,
+    domain_function: Option<String>,
+    /// Orbits traced for images rendered in [`RenderMode::BUDDHABROT`]
+    pub buddhabrot_samples: u64,
+    /// Largest side of a tile, capped by [`FractalRenderer::max_image_size`]
+    pub tile_size: u32,
+}
+
+impl FractalRenderer {
+    /// Picks a hardware adapter if there is one and a software adapter otherwise.
+    /// `WGPU_BACKEND` and the other `wgpu` environment variables are respected.
+    pub fn new() -> Result<Self, RendererError> {
+        let instance = Instance::new(&InstanceDescriptor::from_env_or_default());
+        let adapter = pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .or_else(|_| {
+            pollster::block_on(instance.request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::None,
+                force_fallback_adapter: true,
+                compatible_surface: None,
+            }))
+        })
+        .map_err(RendererError::NoAdapter)?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&DeviceDescriptor {
+            label: Some("fractal renderer"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .map_err(RendererError::RequestDevice)?;
+
+        Ok(Self::from_device(device, queue, adapter.get_info()))
+    }
+
+    /// Shares a device with a window, the renderer keeps its own pipelines and buffers
+    pub fn from_device(device: Device, queue: Queue, adapter_info: AdapterInfo) -> Self {
+        let resource = FvRendererResource::new(&device, FORMAT);
+
+        Self {
+            device,
+            queue,
+            adapter_info,
+            resource,
+            formula: None,
+            domain_function: None,
+            buddhabrot_samples: DEFAULT_BUDDHABROT_SAMPLES,
+            tile_size: DEFAULT_TILE_SIZE,
+        }
+    }
+
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Largest width and height of a single tile
+    pub fn max_image_size(&self) -> u32 {
+        self.device.limits().max_texture_dimension_2d
+    }
+
+    /// Rebuilds the pipelines when the custom formula or the domain function of the settings changed
+    pub fn apply_settings(&mut self, settings: &UserSettings) -> Result<(), RendererError> {
+        if self.formula.as_ref() != Some(&settings.formula) {
+            let compiled = formula::compile(&settings.formula)
+                .map_err(|error| RendererError::Formula(error.to_string()))?;
+            self.resource
+                .set_formula(&self.device, &compiled.wgsl)
+                .map_err(RendererError::Formula)?;
+            self.formula = Some(settings.formula.clone());
+        }
+
+        if self.domain_function.as_ref() != Some(&settings.domain_function) {
+            let compiled = formula::compile_function(&settings.domain_function)
+                .map_err(|error| RendererError::Formula(error.to_string()))?;
+            self.resource
+                .set_domain_function(&self.device, &compiled.wgsl)
+                .map_err(RendererError::Formula)?;
+            self.domain_function = Some(settings.domain_function.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Renders one image and waits for its pixels, images larger than a tile are rendered tile by tile
+    pub fn render_to_image(
+        &mut self,
+        uniforms: &Uniforms,
+        width: u32,
+        height: u32,
+    ) -> Result<Image, RendererError> {
+        self.render_rows(uniforms, width, height, 0..height)
+    }
+
+    /// Renders the rows `rows` of a `width` x `height` image. Each tile sees its own pixels of the
+    /// same view, so the result matches the same rows of a single-pass render exactly
+    pub fn render_rows(
+        &mut self,
+        uniforms: &Uniforms,
+        width: u32,
+        height: u32,
+        rows: Range<u32>,
+    ) -> Result<Image, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::InvalidSize { width, height });
+        }
+        if rows.is_empty() || rows.end > height {
+            return Err(RendererError::InvalidRows { rows, height });
+        }
+
+        // The Buddhabrot density covers the whole image and cannot be split
+        let is_buddhabrot =
+            RenderMode::from_bits_truncate(uniforms.render_mode).contains(RenderMode::BUDDHABROT);
+        let tile_size = if is_buddhabrot {
+            self.max_image_size()
+        } else {
+            self.tile_size.clamp(1, self.max_image_size())
+        };
+        if is_buddhabrot && (width > tile_size || rows.len() as u32 != height || height > tile_size)
+        {
+            return Err(RendererError::TiledBuddhabrot { max: tile_size });
+        }
+
+        let row_size = width as usize * 4;
+        let mut pixels = zeroed((row_size as u64).saturating_mul(rows.len() as u64))?;
+        for tile_y in rows.clone().step_by(tile_size as usize) {
+            for tile_x in (0..width).step_by(tile_size as usize) {
+                let tile_width = tile_size.min(width - tile_x);
+                let tile_height = tile_size.min(rows.end - tile_y);
+                let mut tile_uniforms = *uniforms;
+                tile_uniforms.image_rect = [tile_x, tile_y, width, height];
+                let tile = self.render_tile(&tile_uniforms, tile_width, tile_height)?;
+
+                let tile_row_size = tile_width as usize * 4;
+                for (y, tile_row) in tile.pixels.chunks_exact(tile_row_size).enumerate() {
+                    let start =
+                        ((tile_y - rows.start) as usize + y) * row_size + tile_x as usize * 4;
+                    pixels[start..start + tile_row_size].copy_from_slice(tile_row);
+                }
+            }
+        }
+
+        Ok(Image {
+            width,
+            height: rows.len() as u32,
+            pixels,
+        })
+    }
+
+    /// Computes the raw values of a whole image instead of its colors
+    pub fn render_raw_data(
+        &mut self,
+        uniforms: &Uniforms,
+        width: u32,
+        height: u32,
+    ) -> Result<RawData, RendererError> {
+        self.render_raw_rows(uniforms, width, height, 0..height)
+    }
+
+    /// Computes the raw values of the rows `rows` of a `width` x `height` image, tile by tile.
+    /// Only [`RenderMode::ESCAPE_TIME`] has raw values
+    pub fn render_raw_rows(
+        &mut self,
+        uniforms: &Uniforms,
+        width: u32,
+        height: u32,
+        rows: Range<u32>,
+    ) -> Result<RawData, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::InvalidSize { width, height });
+        }
+        if rows.is_empty() || rows.end > height {
+            return Err(RendererError::InvalidRows { rows, height });
+        }
+        if RenderMode::from_bits_truncate(uniforms.render_mode) != RenderMode::ESCAPE_TIME {
+            return Err(RendererError::RawDataMode);
+        }
+
+        // A tile of raw data must also fit into a single storage buffer binding
+        let limits = self.device.limits();
+        let max_pixels = (limits.max_storage_buffer_binding_size as u64)
+            .min(limits.max_buffer_size)
+            / raw_data_size(1);
+        let tile_width = self.tile_size.clamp(1, self.max_image_size()).min(width);
+        let tile_height = (self.tile_size as u64)
+            .min(max_pixels / tile_width as u64)
+            .max(1) as u32;
+
+        let row_size = width as usize * RAW_DATA_CHANNELS;
+        let mut values = zeroed((row_size as u64).saturating_mul(rows.len() as u64))?;
+        for tile_y in rows.clone().step_by(tile_height as usize) {
+            for tile_x in (0..width).step_by(tile_width as usize) {
+                let mut tile_uniforms = *uniforms;
+                tile_uniforms.image_rect = [tile_x, tile_y, width, height];
+                tile_uniforms.raw_data_width = tile_width.min(width - tile_x);
+                tile_uniforms.raw_data_height = tile_height.min(rows.end - tile_y);
+                let tile = self.compute_raw_tile(&tile_uniforms)?;
+
+                let tile_row_size = tile_uniforms.raw_data_width as usize * RAW_DATA_CHANNELS;
+                for (y, tile_row) in tile.chunks_exact(tile_row_size).enumerate() {
+                    let start = ((tile_y - rows.start) as usize + y) * row_size
+                        + tile_x as usize * RAW_DATA_CHANNELS;
+                    values[start..start + tile_row_size].copy_from_slice(tile_row);
+                }
+            }
+        }
+
+        Ok(RawData {
+            width,
+            height: rows.len() as u32,
+            values,
+        })
+    }
+
+    /// Runs `cs_raw_data` for the tile described by the uniforms
+    fn compute_raw_tile(&mut self, uniforms: &Uniforms) -> Result<Vec<f32>, RendererError> {
+        let size = raw_data_size(uniforms.raw_data_width as u64 * uniforms.raw_data_height as u64);
+        let readback_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("raw data readback buffer"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        let raw_data_buffer =
+            self.resource
+                .compute_raw_data(&self.device, &self.queue, &mut encoder, uniforms);
+        encoder.copy_buffer_to_buffer(raw_data_buffer, 0, &readback_buffer, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        self.map_readback(&readback_buffer)?;
+        let values = bytemuck::cast_slice(&readback_buffer.slice(..).get_mapped_range()).to_vec();
+        readback_buffer.unmap();
+
+        Ok(values)
+    }
+
+    /// Renders into a single texture, which must fit into the limits of the device
+    fn render_tile(
+        &mut self,
+        uniforms: &Uniforms,
+        width: u32,
+        height: u32,
+    ) -> Result<Image, RendererError> {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("offscreen target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+
+        let row_size = width * 4;
+        let padded_row_size = row_size.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("offscreen readback buffer"),
+            size: padded_row_size as u64 * height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // The Buddhabrot density is accumulated over several batches before the image is drawn
+        let is_buddhabrot =
+            RenderMode::from_bits_truncate(uniforms.render_mode).contains(RenderMode::BUDDHABROT);
+        let mut remaining_samples = if is_buddhabrot {
+            self.buddhabrot_samples
+        } else {
+            0
+        };
+        let mut buddhabrot_reset = true;
+        let mut encoder = loop {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&CommandEncoderDescriptor::default());
+            let samples = remaining_samples.min(MAX_BUDDHABROT_SAMPLES_PER_FRAME as u64) as u32;
+            remaining_samples -= samples as u64;
+            self.resource.prepare(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &RenderFrame {
+                    uniforms: *uniforms,
+                    size: [width, height],
+                    buddhabrot_samples: samples,
+                    buddhabrot_reset: std::mem::take(&mut buddhabrot_reset),
+                },
+            );
+            if remaining_samples == 0 {
+                break encoder;
+            }
+            self.queue.submit([encoder.finish()]);
+            self.device
+                .poll(PollType::wait_indefinitely())
+                .map_err(RendererError::Poll)?;
+        };
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("offscreen pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            self.resource.paint(&mut pass);
+        }
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            TexelCopyBufferInfo {
+                buffer: &readback_buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+        self.queue.submit([encoder.finish()]);
+
+        self.map_readback(&readback_buffer)?;
+        let mut pixels = Vec::with_capacity(row_size as usize * height as usize);
+        {
+            let data = readback_buffer.slice(..).get_mapped_range();
+            for row in data.chunks_exact(padded_row_size as usize) {
+                pixels.extend_from_slice(&row[..row_size as usize]);
+            }
+        }
+        readback_buffer.unmap();
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Maps a buffer for reading and waits until the GPU work writing to it is done
+    fn map_readback(&self, buffer: &Buffer) -> Result<(), RendererError> {
+        let mapped = Arc::new(OnceLock::new());
+        let result = mapped.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |status| {
+            let _ = result.set(status);
+        });
+        self.device
+            .poll(PollType::wait_indefinitely())
+            .map_err(RendererError::Poll)?;
+        if let Some(Err(error)) = mapped.get() {
+            return Err(RendererError::Readback(error.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// `len` zeros, or an error instead of the abort of `vec!` when they do not fit into the memory
+fn zeroed<T: Clone + Default>(len: u64) -> Result<Vec<T>, RendererError> {
+    let out_of_memory = || RendererError::OutOfMemory {
+        bytes: len.saturating_mul(size_of::<T>() as u64),
+    };
+    let len = usize::try_from(len).map_err(|_| out_of_memory())?;
+    let mut values = Vec::new();
+    values.try_reserve_exact(len).map_err(|_| out_of_memory())?;
+    values.resize(len, T::default());
+    Ok(values)
+}
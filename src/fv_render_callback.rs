@@ -1,32 +1,27 @@
-use crate::fv_renderer_resource::FvRendererResource;
-use crate::uniforms::Uniforms;
+use crate::fv_renderer_resource::{FvRendererResource, RenderFrame};
 use eframe::epaint::PaintCallbackInfo;
 use egui_wgpu::wgpu::RenderPass;
 use egui_wgpu::{CallbackResources, CallbackTrait, ScreenDescriptor};
 use wgpu::{CommandBuffer, CommandEncoder, Device, Queue};
 
 pub struct FvRenderCallback {
-    pub uniforms: Uniforms,
+    pub frame: RenderFrame,
 }
 
 impl CallbackTrait for FvRenderCallback {
     fn prepare(
         &self,
-        _device: &Device,
+        device: &Device,
         queue: &Queue,
         _screen_descriptor: &ScreenDescriptor,
-        _egui_encoder: &mut CommandEncoder,
+        egui_encoder: &mut CommandEncoder,
         callback_resources: &mut CallbackResources,
     ) -> Vec<CommandBuffer> {
         let resource = callback_resources
-            .get::<FvRendererResource>()
+            .get_mut::<FvRendererResource>()
             .expect("Missing FvRendererResource");
 
-        queue.write_buffer(
-            &resource.uniform_buffer,
-            0,
-            bytemuck::cast_slice(&[self.uniforms]),
-        );
+        resource.prepare(device, queue, egui_encoder, &self.frame);
         vec![]
     }
 
@@ -40,8 +35,6 @@ impl CallbackTrait for FvRenderCallback {
             .get::<FvRendererResource>()
             .expect("Missing FvRendererResource");
 
-        render_pass.set_pipeline(&resource.pipeline);
-        render_pass.set_bind_group(0, &resource.bind_group, &[]);
-        render_pass.draw(0..6, 0..1);
+        resource.paint(render_pass);
     }
 }
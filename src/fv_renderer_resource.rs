@@ -1,22 +1,110 @@
-use crate::uniforms::Uniforms;
-use egui_wgpu::RenderState;
+use crate::uniforms::{RenderMode, Uniforms};
+use log::debug;
+use std::sync::{Arc, OnceLock};
 use wgpu::wgt::BufferDescriptor;
 use wgpu::{
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, FragmentState,
-    PipelineLayoutDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderStages, VertexState,
-    include_wgsl,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAsyncError, BufferBindingType, BufferUsages,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    ErrorFilter, FragmentState, Limits, MapMode, PipelineLayout, PipelineLayoutDescriptor,
+    PollType, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, VertexState,
 };
 
+const SHADER_SOURCE: &str = include_str!("mandelbrot.wgsl");
+const FORMULA_MARKERS: [&str; 2] = ["// formula:begin", "// formula:end"];
+const DOMAIN_FUNCTION_MARKERS: [&str; 2] = ["// domain:begin", "// domain:end"];
+
+/// Must match `BUDDHABROT_CELLS` in the shader
+const BUDDHABROT_CELLS: u32 = 128;
+/// Three atomic maxima, the cell counter, the cell list and the boundary flags of the cells,
+/// see `BuddhabrotState` in the shader
+const BUDDHABROT_STATE_SIZE: u64 = (4 + 2 * BUDDHABROT_CELLS as u64 * BUDDHABROT_CELLS as u64) * 4;
+const DENSITY_CHANNELS: u64 = 3;
+const SAMPLE_WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUP_SIZE: u32 = 256;
+/// Keeps a single dispatch within the 65535 workgroups limit
+pub const MAX_BUDDHABROT_SAMPLES_PER_FRAME: u32 = 65_535 * SAMPLE_WORKGROUP_SIZE;
+/// Must match `RAW_DATA_CHANNELS` in the shader
+pub const RAW_DATA_CHANNELS: usize = 5;
+const RAW_DATA_WORKGROUP_SIZE: u32 = 8;
+
+/// Everything needed to render one frame
+#[derive(Clone, Copy)]
+pub struct RenderFrame {
+    pub uniforms: Uniforms,
+    /// Size of the render target in pixels
+    pub size: [u32; 2],
+    /// Orbits to add to the Buddhabrot density this frame, 0 pauses the accumulation
+    pub buddhabrot_samples: u32,
+    pub buddhabrot_reset: bool,
+}
+
+struct Pipelines {
+    render: RenderPipeline,
+    buddhabrot_cells: ComputePipeline,
+    buddhabrot_sample: ComputePipeline,
+    buddhabrot_max: ComputePipeline,
+    raw_data: ComputePipeline,
+}
+
+/// Progressive accumulation of the Buddhabrot density, restarted whenever the view changes
+struct BuddhabrotAccumulation {
+    size: [u32; 2],
+    density_buffer: Buffer,
+    state_buffer: Buffer,
+    samples: u64,
+    frame: u32,
+    last_uniforms: Option<Uniforms>,
+}
+
+/// Share of the pixels of a rendered frame whose orbits ran out of iterations
+#[derive(Clone, Copy)]
+pub struct LimitStatistics {
+    /// Increases with every measurement
+    pub id: u64,
+    /// Uniforms the frame was rendered with
+    pub uniforms: Uniforms,
+    pub limit_fraction: f32,
+}
+
+enum ReadbackState {
+    Idle,
+    /// The copy of the counter was submitted with the previous frame
+    Copied,
+    Mapping(Arc<OnceLock<Result<(), BufferAsyncError>>>),
+}
+
+/// Counts the pixels that hit the iteration limit and reads the count back without stalling:
+/// the counter is copied in one frame, mapped in the next and read once the mapping completes
+struct LimitCounter {
+    buffer: Buffer,
+    readback_buffer: Buffer,
+    state: ReadbackState,
+    /// Frame that writes to the counter during the current render pass
+    counted_frame: Option<(Uniforms, [u32; 2])>,
+    /// Frame whose count is being read back
+    pending_frame: Option<(Uniforms, [u32; 2])>,
+    latest: Option<LimitStatistics>,
+}
+
 pub struct FvRendererResource {
     pub bind_group: BindGroup,
-    pub pipeline: RenderPipeline,
     pub uniform_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    target_format: TextureFormat,
+    pipelines: Pipelines,
+    buddhabrot: BuddhabrotAccumulation,
+    limit_counter: LimitCounter,
+    /// Output of `cs_raw_data`, grown to the largest tile computed so far
+    raw_data_buffer: Buffer,
+    formula_wgsl: Option<String>,
+    domain_function_wgsl: Option<String>,
 }
 
 impl FvRendererResource {
-    pub fn new(render_state: &RenderState) -> Self {
-        let device = &render_state.device;
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
         let uniform_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Params buffer"),
             size: size_of::<Uniforms>() as u64,
@@ -24,30 +112,47 @@ impl FvRendererResource {
             mapped_at_creation: false,
         });
 
+        let storage_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
         let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("main bind group layout"),
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Buffer {
-                    ty: BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT | ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                storage_entry(1),
+                storage_entry(2),
+                storage_entry(3),
+                storage_entry(4),
+            ],
         });
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("main bind group"),
-            layout: &bind_group_layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
-
-        let module = device.create_shader_module(include_wgsl!("mandelbrot.wgsl"));
+        let buddhabrot = BuddhabrotAccumulation::new(device, [1, 1]);
+        let limit_counter = LimitCounter::new(device);
+        let raw_data_buffer = create_raw_data_buffer(device, 1);
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &buddhabrot,
+            &limit_counter,
+            &raw_data_buffer,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("main pipeline descriptor"),
@@ -55,31 +160,514 @@ impl FvRendererResource {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("main render pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: VertexState {
-                module: &module,
-                entry_point: Some("vs_main"),
-                compilation_options: Default::default(),
-                buffers: &[],
-            },
-            primitive: Default::default(),
-            depth_stencil: None,
-            multisample: Default::default(),
-            fragment: Some(FragmentState {
-                module: &module,
-                entry_point: Some("fs_main"),
-                compilation_options: Default::default(),
-                targets: &[Some(render_state.target_format.into())],
-            }),
-            multiview: None,
-            cache: None,
-        });
+        let pipelines = create_pipelines(device, &pipeline_layout, target_format, SHADER_SOURCE);
         Self {
             bind_group,
             uniform_buffer,
-            pipeline,
+            bind_group_layout,
+            pipeline_layout,
+            target_format,
+            pipelines,
+            buddhabrot,
+            limit_counter,
+            raw_data_buffer,
+            formula_wgsl: None,
+            domain_function_wgsl: None,
+        }
+    }
+
+    /// Rebuilds the pipelines with `formula_wgsl` as the body of `custom_formula`.
+    /// The current pipelines are kept if the shader fails validation
+    pub fn set_formula(&mut self, device: &Device, formula_wgsl: &str) -> Result<(), String> {
+        let domain_function_wgsl = self.domain_function_wgsl.clone();
+        self.rebuild_pipelines(device, Some(formula_wgsl), domain_function_wgsl.as_deref())?;
+        self.formula_wgsl = Some(formula_wgsl.to_string());
+        Ok(())
+    }
+
+    /// Rebuilds the pipelines with `function_wgsl` as the body of `domain_function`
+    pub fn set_domain_function(
+        &mut self,
+        device: &Device,
+        function_wgsl: &str,
+    ) -> Result<(), String> {
+        let formula_wgsl = self.formula_wgsl.clone();
+        self.rebuild_pipelines(device, formula_wgsl.as_deref(), Some(function_wgsl))?;
+        self.domain_function_wgsl = Some(function_wgsl.to_string());
+        Ok(())
+    }
+
+    fn rebuild_pipelines(
+        &mut self,
+        device: &Device,
+        formula_wgsl: Option<&str>,
+        domain_function_wgsl: Option<&str>,
+    ) -> Result<(), String> {
+        let mut source = SHADER_SOURCE.to_string();
+        if let Some(formula_wgsl) = formula_wgsl {
+            source = splice(&source, FORMULA_MARKERS, formula_wgsl);
+        }
+        if let Some(domain_function_wgsl) = domain_function_wgsl {
+            source = splice(&source, DOMAIN_FUNCTION_MARKERS, domain_function_wgsl);
+        }
+
+        device.push_error_scope(ErrorFilter::Validation);
+        let pipelines =
+            create_pipelines(device, &self.pipeline_layout, self.target_format, &source);
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(error.to_string());
+        }
+
+        self.pipelines = pipelines;
+        self.buddhabrot.last_uniforms = None;
+        Ok(())
+    }
+
+    /// Number of orbits traced since the Buddhabrot accumulation was last restarted
+    pub fn buddhabrot_samples(&self) -> u64 {
+        self.buddhabrot.samples
+    }
+
+    /// Latest measurement of the frames rendered with `auto_iterations`, a few frames behind
+    pub fn limit_statistics(&self) -> Option<LimitStatistics> {
+        self.limit_counter.latest
+    }
+
+    /// Uploads the uniforms and records the compute work of the frame
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        frame: &RenderFrame,
+    ) {
+        let mut uniforms = frame.uniforms;
+        let size = frame.size;
+
+        self.limit_counter.advance(device, encoder);
+        if uniforms.auto_iterations > 0 {
+            self.limit_counter.counted_frame = Some((frame.uniforms, size));
+            encoder.clear_buffer(&self.limit_counter.buffer, 0, None);
         }
+
+        let is_buddhabrot =
+            RenderMode::from_bits_truncate(uniforms.render_mode).contains(RenderMode::BUDDHABROT);
+
+        if is_buddhabrot {
+            let size = density_size(&device.limits(), size.map(|side| side.max(1)));
+            if self.buddhabrot.size != size {
+                self.buddhabrot = BuddhabrotAccumulation::new(device, size);
+                self.bind_group = create_bind_group(
+                    device,
+                    &self.bind_group_layout,
+                    &self.uniform_buffer,
+                    &self.buddhabrot,
+                    &self.limit_counter,
+                    &self.raw_data_buffer,
+                );
+            }
+            uniforms.buddhabrot_width = size[0];
+            uniforms.buddhabrot_height = size[1];
+        }
+
+        let accumulation_key = accumulation_key(&uniforms);
+        let restart = is_buddhabrot
+            && (frame.buddhabrot_reset
+                || self.buddhabrot.last_uniforms.is_none_or(|last| {
+                    bytemuck::bytes_of(&last) != bytemuck::bytes_of(&accumulation_key)
+                }));
+        if is_buddhabrot {
+            self.buddhabrot.last_uniforms = Some(accumulation_key);
+            uniforms.buddhabrot_seed = self.buddhabrot.frame;
+            self.buddhabrot.frame = self.buddhabrot.frame.wrapping_add(1);
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        if !is_buddhabrot {
+            return;
+        }
+
+        if restart {
+            self.buddhabrot.samples = 0;
+            encoder.clear_buffer(&self.buddhabrot.density_buffer, 0, None);
+            encoder.clear_buffer(&self.buddhabrot.state_buffer, 0, None);
+        }
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("buddhabrot pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.bind_group, &[]);
+
+        if restart {
+            pass.set_pipeline(&self.pipelines.buddhabrot_cells);
+            pass.dispatch_workgroups(
+                (BUDDHABROT_CELLS * BUDDHABROT_CELLS).div_ceil(SAMPLE_WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+
+        let samples = frame
+            .buddhabrot_samples
+            .min(MAX_BUDDHABROT_SAMPLES_PER_FRAME);
+        if samples > 0 {
+            pass.set_pipeline(&self.pipelines.buddhabrot_sample);
+            pass.dispatch_workgroups(samples.div_ceil(SAMPLE_WORKGROUP_SIZE), 1, 1);
+            self.buddhabrot.samples +=
+                samples.div_ceil(SAMPLE_WORKGROUP_SIZE) as u64 * SAMPLE_WORKGROUP_SIZE as u64;
+
+            pass.set_pipeline(&self.pipelines.buddhabrot_max);
+            let [width, height] = self.buddhabrot.size;
+            let pixels = width * height;
+            pass.dispatch_workgroups(pixels.div_ceil(MAX_WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    /// Uploads the uniforms and records the computation of the raw escape time values of the
+    /// `raw_data_width` × `raw_data_height` tile at `image_rect`. The returned buffer holds
+    /// [`RAW_DATA_CHANNELS`] floats per pixel, row by row
+    pub fn compute_raw_data(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        uniforms: &Uniforms,
+    ) -> &Buffer {
+        let pixels = uniforms.raw_data_width as u64 * uniforms.raw_data_height as u64;
+        if self.raw_data_buffer.size() < raw_data_size(pixels) {
+            self.raw_data_buffer = create_raw_data_buffer(device, pixels);
+            self.bind_group = create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.uniform_buffer,
+                &self.buddhabrot,
+                &self.limit_counter,
+                &self.raw_data_buffer,
+            );
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[*uniforms]));
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("raw data pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_pipeline(&self.pipelines.raw_data);
+        pass.dispatch_workgroups(
+            uniforms.raw_data_width.div_ceil(RAW_DATA_WORKGROUP_SIZE),
+            uniforms.raw_data_height.div_ceil(RAW_DATA_WORKGROUP_SIZE),
+            1,
+        );
+        drop(pass);
+
+        &self.raw_data_buffer
+    }
+
+    pub fn paint(&self, render_pass: &mut RenderPass) {
+        render_pass.set_pipeline(&self.pipelines.render);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
+
+impl BuddhabrotAccumulation {
+    fn new(device: &Device, size: [u32; 2]) -> Self {
+        let density_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Buddhabrot density buffer"),
+            size: size[0] as u64 * size[1] as u64 * DENSITY_CHANNELS * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let state_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Buddhabrot state buffer"),
+            size: BUDDHABROT_STATE_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            size,
+            density_buffer,
+            state_buffer,
+            samples: 0,
+            frame: 0,
+            last_uniforms: None,
+        }
+    }
+}
+
+impl LimitCounter {
+    fn new(device: &Device) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Iteration limit counter buffer"),
+            size: 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Iteration limit readback buffer"),
+            size: 4,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            readback_buffer,
+            state: ReadbackState::Idle,
+            counted_frame: None,
+            pending_frame: None,
+            latest: None,
+        }
+    }
+
+    /// Moves the readback one step further. Frames counted while a readback is in flight are dropped
+    fn advance(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        match &self.state {
+            ReadbackState::Idle => {}
+            ReadbackState::Copied => {
+                let mapped = Arc::new(OnceLock::new());
+                let callback_mapped = mapped.clone();
+                self.readback_buffer
+                    .slice(..)
+                    .map_async(MapMode::Read, move |result| {
+                        let _ = callback_mapped.set(result);
+                    });
+                self.state = ReadbackState::Mapping(mapped);
+                return;
+            }
+            ReadbackState::Mapping(mapped) => {
+                let _ = device.poll(PollType::Poll);
+                let Some(result) = mapped.get() else {
+                    return;
+                };
+                if result.is_ok() {
+                    self.read_mapped();
+                }
+                self.state = ReadbackState::Idle;
+            }
+        }
+
+        if let Some(frame) = self.counted_frame.take() {
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &self.readback_buffer, 0, 4);
+            self.pending_frame = Some(frame);
+            self.state = ReadbackState::Copied;
+        }
+    }
+
+    fn read_mapped(&mut self) {
+        let hits = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            *bytemuck::from_bytes::<u32>(&data[..4])
+        };
+        self.readback_buffer.unmap();
+
+        if let Some((uniforms, size)) = self.pending_frame.take() {
+            let pixels = (size[0].max(1) as u64 * size[1].max(1) as u64) as f32;
+            self.latest = Some(LimitStatistics {
+                id: self.latest.map_or(0, |latest| latest.id + 1),
+                uniforms,
+                limit_fraction: (hits as f32 / pixels).min(1.0),
+            });
+        }
+    }
+}
+
+/// Size of the Buddhabrot density, scaled down to fit into one storage buffer binding. The density
+/// is looked up by the position in the image, so a smaller one only makes large images coarser
+fn density_size(limits: &Limits, size: [u32; 2]) -> [u32; 2] {
+    let max_pixels = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+        / (DENSITY_CHANNELS * 4);
+    let pixels = size[0] as u64 * size[1] as u64;
+    if pixels <= max_pixels {
+        return size;
+    }
+
+    let scale = (max_pixels as f64 / pixels as f64).sqrt();
+    let scaled = size.map(|side| ((side as f64 * scale) as u32).max(1));
+    debug!(
+        "Buddhabrot density of {}x{} is reduced to {}x{} by the storage buffer limit",
+        size[0], size[1], scaled[0], scaled[1]
+    );
+    scaled
+}
+
+/// Uniforms with the display-only fields cleared, so that recoloring keeps the accumulated density
+fn accumulation_key(uniforms: &Uniforms) -> Uniforms {
+    Uniforms {
+        rgb_green: 0.0,
+        rgb_blue: 0.0,
+        color_scheme: 0,
+        hsv_saturation: 0.0,
+        hsv_brightness: 0.0,
+        show_axis: 0,
+        buddhabrot_gamma: 0.0,
+        palette_offset: 0.0,
+        ..*uniforms
+    }
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    uniform_buffer: &Buffer,
+    buddhabrot: &BuddhabrotAccumulation,
+    limit_counter: &LimitCounter,
+    raw_data_buffer: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some("main bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: buddhabrot.density_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: buddhabrot.state_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: limit_counter.buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: raw_data_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+/// Bytes of the raw data of `pixels` pixels
+pub fn raw_data_size(pixels: u64) -> u64 {
+    pixels * RAW_DATA_CHANNELS as u64 * 4
+}
+
+fn create_raw_data_buffer(device: &Device, pixels: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("Raw data buffer"),
+        size: raw_data_size(pixels),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+/// Replaces the code between the begin and end markers of the shader
+fn splice(source: &str, [begin_marker, end_marker]: [&str; 2], wgsl: &str) -> String {
+    let begin = source
+        .find(begin_marker)
+        .expect("Missing begin marker in shader")
+        + begin_marker.len();
+    let end = source
+        .find(end_marker)
+        .expect("Missing end marker in shader");
+
+    format!("{}\n{wgsl}\n{}", &source[..begin], &source[end..])
+}
+
+fn create_pipelines(
+    device: &Device,
+    pipeline_layout: &PipelineLayout,
+    target_format: TextureFormat,
+    source: &str,
+) -> Pipelines {
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("mandelbrot.wgsl"),
+        source: ShaderSource::Wgsl(source.into()),
+    });
+
+    let render = device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("main render pipeline"),
+        layout: Some(pipeline_layout),
+        vertex: VertexState {
+            module: &module,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        primitive: Default::default(),
+        depth_stencil: None,
+        multisample: Default::default(),
+        fragment: Some(FragmentState {
+            module: &module,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(target_format.into())],
+        }),
+        multiview: None,
+        cache: None,
+    });
+
+    Pipelines {
+        render,
+        buddhabrot_cells: create_compute_pipeline(
+            device,
+            pipeline_layout,
+            &module,
+            "cs_buddhabrot_cells",
+        ),
+        buddhabrot_sample: create_compute_pipeline(
+            device,
+            pipeline_layout,
+            &module,
+            "cs_buddhabrot_sample",
+        ),
+        buddhabrot_max: create_compute_pipeline(
+            device,
+            pipeline_layout,
+            &module,
+            "cs_buddhabrot_max",
+        ),
+        raw_data: create_compute_pipeline(device, pipeline_layout, &module, "cs_raw_data"),
+    }
+}
+
+fn create_compute_pipeline(
+    device: &Device,
+    pipeline_layout: &PipelineLayout,
+    module: &ShaderModule,
+    entry_point: &str,
+) -> ComputePipeline {
+    device.create_compute_pipeline(&ComputePipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(pipeline_layout),
+        module,
+        entry_point: Some(entry_point),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_settings::UserSettings;
+
+    #[test]
+    fn recoloring_keeps_the_accumulation() {
+        let settings = UserSettings::new();
+        let mut recolored = settings.clone();
+        recolored.palette_offset = 0.5;
+        recolored.buddhabrot_gamma = 2.0;
+        recolored.show_axis = !settings.show_axis;
+        assert!(
+            bytemuck::bytes_of(&accumulation_key(&settings.uniforms()))
+                == bytemuck::bytes_of(&accumulation_key(&recolored.uniforms()))
+        );
+
+        let mut moved = settings.clone();
+        moved.center_x += 0.1;
+        assert!(
+            bytemuck::bytes_of(&accumulation_key(&settings.uniforms()))
+                != bytemuck::bytes_of(&accumulation_key(&moved.uniforms()))
+        );
     }
 }
@@ -0,0 +1,870 @@
+use crate::animation::loop_frame_settings;
+use crate::fractal_renderer::{FractalRenderer, Image, RawData, RendererError};
+use crate::fv_renderer_resource::RAW_DATA_CHANNELS;
+use crate::uniforms::{RenderMode, Uniforms};
+use crate::user_settings::UserSettings;
+use png::StreamWriter;
+use std::borrow::Borrow;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::thread::JoinHandle;
+
+/// Keyword of the iTXt chunk with [`UserSettings::image_parameters`]
+pub const PARAMETERS_KEYWORD: &str = "mandelbrot_gpu parameters";
+
+/// Largest number of samples per pixel side
+pub const MAX_SUPERSAMPLING: u32 = 8;
+
+/// Extensions of the files [`save_raw_data`] writes
+pub const RAW_DATA_EXTENSIONS: [&str; 3] = ["npy", "exr", "png"];
+
+/// Extensions of the animations [`save_loop`] writes, `png` and `apng` are both APNG
+pub const LOOP_EXTENSIONS: [&str; 3] = ["gif", "png", "apng"];
+
+/// Colors of a GIF frame are picked per frame, faster with higher values, see [`gif::Frame::from_rgba_speed`]
+const GIF_QUANTIZATION_SPEED: i32 = 10;
+
+/// Keywords of the tEXt chunks of 16-bit PNG channels, the samples span minimum..=maximum
+pub const CHANNEL_KEYWORD: &str = "mandelbrot_gpu channel";
+pub const MINIMUM_KEYWORD: &str = "mandelbrot_gpu minimum";
+pub const MAXIMUM_KEYWORD: &str = "mandelbrot_gpu maximum";
+
+#[derive(Debug)]
+pub enum ExportError {
+    Render(RendererError),
+    Png(png::EncodingError),
+    Exr(exr::error::Error),
+    Gif(gif::EncodingError),
+    Io(std::io::Error),
+    /// The extension is none of [`RAW_DATA_EXTENSIONS`]
+    RawDataFormat(PathBuf),
+    /// The extension is none of [`LOOP_EXTENSIONS`]
+    LoopFormat(PathBuf),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Render(error) => error.fmt(f),
+            Self::Png(error) => write!(f, "failed to write PNG: {error}"),
+            Self::Exr(error) => write!(f, "failed to write EXR: {error}"),
+            Self::Gif(error) => write!(f, "failed to write GIF: {error}"),
+            Self::Io(error) => write!(f, "failed to write the file: {error}"),
+            Self::RawDataFormat(path) => write!(
+                f,
+                "unknown raw data format of {}, expected one of {}",
+                path.display(),
+                RAW_DATA_EXTENSIONS.join(", ")
+            ),
+            Self::LoopFormat(path) => write!(
+                f,
+                "unknown animation format of {}, expected one of {}",
+                path.display(),
+                LOOP_EXTENSIONS.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<RendererError> for ExportError {
+    fn from(error: RendererError) -> Self {
+        Self::Render(error)
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(error: png::EncodingError) -> Self {
+        Self::Png(error)
+    }
+}
+
+impl From<exr::error::Error> for ExportError {
+    fn from(error: exr::error::Error) -> Self {
+        Self::Exr(error)
+    }
+}
+
+impl From<gif::EncodingError> for ExportError {
+    fn from(error: gif::EncodingError) -> Self {
+        Self::Gif(error)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Writes 8-bit RGBA pixels as a PNG
+pub fn write_png(image: &Image, writer: impl Write) -> Result<(), png::EncodingError> {
+    let mut writer = png_encoder(writer, image.width, image.height).write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()
+}
+
+pub fn save_png(image: &Image, path: &Path) -> Result<(), png::EncodingError> {
+    write_png(image, BufWriter::new(File::create(path)?))
+}
+
+/// Renders the settings strip by strip straight into a PNG, so neither the GPU nor the memory
+/// ever holds the whole image. `supersampling`² samples are averaged per pixel.
+/// The parameters of the image are embedded for [`read_parameters`]
+pub fn render_png(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+    supersampling: u32,
+    writer: impl Write,
+) -> Result<(), ExportError> {
+    let mut strips = StripRender::new(renderer, settings, width, height, supersampling)?;
+    let mut writer = png_header(writer, settings, width, height)?;
+    let mut stream = writer.stream_writer()?;
+    while !strips.is_done() {
+        stream.write_all(&strips.render_strip(renderer)?.pixels)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+pub fn save_rendered_png(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+    supersampling: u32,
+    path: &Path,
+) -> Result<(), ExportError> {
+    let file = BufWriter::new(File::create(path)?);
+    render_png(renderer, settings, width, height, supersampling, file)
+}
+
+/// Image of the settings rendered one strip of rows per [`StripRender::render_strip`], so that a
+/// window can stay responsive and show the progress between the strips
+pub struct StripRender {
+    settings: UserSettings,
+    uniforms: Uniforms,
+    supersampling: u32,
+    render_width: u32,
+    render_height: u32,
+    strip_height: u32,
+    next_row: u32,
+}
+
+impl StripRender {
+    pub fn new(
+        renderer: &mut FractalRenderer,
+        settings: &UserSettings,
+        width: u32,
+        height: u32,
+        supersampling: u32,
+    ) -> Result<Self, ExportError> {
+        renderer.apply_settings(settings)?;
+
+        let supersampling = supersampling.max(1);
+        let (Some(render_width), Some(render_height)) = (
+            width.checked_mul(supersampling),
+            height.checked_mul(supersampling),
+        ) else {
+            return Err(RendererError::InvalidSize { width, height }.into());
+        };
+
+        // The Buddhabrot density cannot be split into strips
+        let strip_height = if settings.render_mode.contains(RenderMode::BUDDHABROT) {
+            render_height
+        } else {
+            (strip_rows(renderer, render_width) / supersampling).max(1) * supersampling
+        };
+
+        Ok(Self {
+            settings: settings.clone(),
+            uniforms: settings.uniforms(),
+            supersampling,
+            render_width,
+            render_height,
+            strip_height,
+            next_row: 0,
+        })
+    }
+
+    /// Renders the next rows, downsampled to the size of the image
+    pub fn render_strip(&mut self, renderer: &mut FractalRenderer) -> Result<Image, ExportError> {
+        // The renderer may have drawn other settings since the previous strip
+        renderer.apply_settings(&self.settings)?;
+        let rows = self.next_row..(self.next_row + self.strip_height).min(self.render_height);
+        let strip = renderer.render_rows(
+            &self.uniforms,
+            self.render_width,
+            self.render_height,
+            rows.clone(),
+        )?;
+        self.next_row = rows.end;
+        Ok(strip.downsample(self.supersampling))
+    }
+
+    /// Share of the rows rendered so far, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.next_row as f32 / self.render_height as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_row >= self.render_height
+    }
+}
+
+/// PNG file being written by [`StripRender`], see [`save_rendered_png`] for the whole file at once
+pub struct PngExport {
+    strips: StripRender,
+    stream: StreamWriter<'static, BufWriter<File>>,
+}
+
+impl PngExport {
+    /// Creates the file and writes the header, the strips follow with [`PngExport::render_strip`]
+    pub fn create(
+        renderer: &mut FractalRenderer,
+        settings: &UserSettings,
+        width: u32,
+        height: u32,
+        supersampling: u32,
+        path: &Path,
+    ) -> Result<Self, ExportError> {
+        let strips = StripRender::new(renderer, settings, width, height, supersampling)?;
+        let file = BufWriter::new(File::create(path)?);
+        let stream = png_header(file, settings, width, height)?.into_stream_writer()?;
+        Ok(Self { strips, stream })
+    }
+
+    /// Renders and writes the next strip
+    pub fn render_strip(&mut self, renderer: &mut FractalRenderer) -> Result<(), ExportError> {
+        let strip = self.strips.render_strip(renderer)?;
+        self.stream.write_all(&strip.pixels)?;
+        Ok(())
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.strips.progress()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.strips.is_done()
+    }
+
+    /// Completes the file once every strip is written
+    pub fn finish(self) -> Result<(), ExportError> {
+        self.stream.finish()?;
+        Ok(())
+    }
+}
+
+/// Rows of a strip of about as many samples as a tile, so very wide images get strips lower than
+/// a tile
+fn strip_rows(renderer: &FractalRenderer, width: u32) -> u32 {
+    let tile_size = renderer.tile_size.clamp(1, renderer.max_image_size()) as u64;
+    ((tile_size * tile_size / width as u64).min(tile_size) as u32).max(1)
+}
+
+/// Writes the header of a PNG of the settings with their parameters for [`read_parameters`]
+fn png_header<W: Write>(
+    writer: W,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+) -> Result<png::Writer<W>, png::EncodingError> {
+    let mut encoder = png_encoder(writer, width, height);
+    encoder.add_text_chunk(
+        "Software".to_string(),
+        concat!("mandelbrot_gpu ", env!("CARGO_PKG_VERSION")).to_string(),
+    )?;
+    encoder.add_itxt_chunk(PARAMETERS_KEYWORD.to_string(), settings.image_parameters())?;
+    encoder.write_header()
+}
+
+/// Renders the settings into a single image in memory, `supersampling`² samples per pixel
+pub fn render_image(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+    supersampling: u32,
+) -> Result<Image, ExportError> {
+    renderer.apply_settings(settings)?;
+    let supersampling = supersampling.max(1);
+    let (Some(render_width), Some(render_height)) = (
+        width.checked_mul(supersampling),
+        height.checked_mul(supersampling),
+    ) else {
+        return Err(RendererError::InvalidSize { width, height }.into());
+    };
+    let image = renderer.render_to_image(&settings.uniforms(), render_width, render_height)?;
+    Ok(image.downsample(supersampling))
+}
+
+/// Renders the loop of the settings, see [`loop_frame_settings`], at `loop_size` with the
+/// supersampling of the screenshots. Writes an animated GIF or APNG by the extension of `path`
+pub fn save_loop(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    path: &Path,
+) -> Result<(), ExportError> {
+    LoopExport::create(settings, path)?.render(renderer)
+}
+
+/// Writes the loop as an endlessly repeating APNG with the parameters of the settings embedded
+pub fn write_apng(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    writer: impl Write,
+) -> Result<(), ExportError> {
+    LoopExport::apng(settings, writer)?.render(renderer)
+}
+
+/// Writes the loop as an endlessly repeating GIF. Every frame gets its own palette of
+/// 256 colors and the frame time is rounded to 1/100 s
+pub fn write_gif(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    writer: impl Write,
+) -> Result<(), ExportError> {
+    LoopExport::gif(settings, writer)?.render(renderer)
+}
+
+/// Loop being written one frame per [`LoopExport::render_frame`], so that a window can stay
+/// responsive and show the progress between the frames
+pub struct LoopExport<W: Write> {
+    settings: UserSettings,
+    encoder: LoopEncoder<W>,
+    next_frame: u32,
+    frame_count: u32,
+}
+
+enum LoopEncoder<W: Write> {
+    Apng(png::Writer<W>),
+    Gif(gif::Encoder<W>),
+}
+
+impl LoopExport<BufWriter<File>> {
+    /// Creates a GIF or APNG file by the extension of `path`
+    pub fn create(settings: &UserSettings, path: &Path) -> Result<Self, ExportError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("gif") => Self::gif(settings, BufWriter::new(File::create(path)?)),
+            Some("png" | "apng") => Self::apng(settings, BufWriter::new(File::create(path)?)),
+            _ => Err(ExportError::LoopFormat(path.to_path_buf())),
+        }
+    }
+}
+
+impl<W: Write> LoopExport<W> {
+    pub fn apng(settings: &UserSettings, writer: W) -> Result<Self, ExportError> {
+        let [width, height] = settings.loop_size;
+        let frames = settings.loop_frames.max(1);
+        let delay_ms = (settings.loop_duration * 1000.0 / frames as f32).round() as u16;
+
+        let mut encoder = png_encoder(writer, width, height);
+        encoder.add_itxt_chunk(PARAMETERS_KEYWORD.to_string(), settings.image_parameters())?;
+        encoder.set_animated(frames, 0)?;
+        encoder.set_frame_delay(delay_ms, 1000)?;
+        Ok(Self::new(
+            settings,
+            LoopEncoder::Apng(encoder.write_header()?),
+        ))
+    }
+
+    pub fn gif(settings: &UserSettings, writer: W) -> Result<Self, ExportError> {
+        let [width, height] = settings.loop_size;
+        let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(RendererError::InvalidSize { width, height }.into());
+        };
+
+        let mut encoder = gif::Encoder::new(writer, gif_width, gif_height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(Self::new(settings, LoopEncoder::Gif(encoder)))
+    }
+
+    fn new(settings: &UserSettings, encoder: LoopEncoder<W>) -> Self {
+        Self {
+            settings: settings.clone(),
+            encoder,
+            next_frame: 0,
+            frame_count: settings.loop_frames.max(1),
+        }
+    }
+
+    /// Renders and writes the next frame
+    pub fn render_frame(&mut self, renderer: &mut FractalRenderer) -> Result<(), ExportError> {
+        let settings = &self.settings;
+        let [width, height] = settings.loop_size;
+        let frame = loop_frame_settings(settings, self.next_frame);
+        let mut image = render_image(
+            renderer,
+            &frame,
+            width,
+            height,
+            settings.screenshot_supersampling,
+        )?;
+
+        match &mut self.encoder {
+            LoopEncoder::Apng(writer) => writer.write_image_data(&image.pixels)?,
+            LoopEncoder::Gif(encoder) => {
+                let mut gif_frame = gif::Frame::from_rgba_speed(
+                    width as u16,
+                    height as u16,
+                    &mut image.pixels,
+                    GIF_QUANTIZATION_SPEED,
+                );
+                gif_frame.delay =
+                    (settings.loop_duration * 100.0 / self.frame_count as f32).round() as u16;
+                encoder.write_frame(&gif_frame)?;
+            }
+        }
+        self.next_frame += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far
+    pub fn next_frame(&self) -> u32 {
+        self.next_frame
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_frame >= self.frame_count
+    }
+
+    /// Completes the file once every frame is written
+    pub fn finish(self) -> Result<(), ExportError> {
+        match self.encoder {
+            LoopEncoder::Apng(writer) => writer.finish()?,
+            LoopEncoder::Gif(encoder) => encoder.into_inner()?.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Renders every remaining frame and completes the file
+    fn render(mut self, renderer: &mut FractalRenderer) -> Result<(), ExportError> {
+        while !self.is_done() {
+            self.render_frame(renderer)?;
+        }
+        self.finish()
+    }
+}
+
+/// Computes the raw escape time values of the settings, see [`RawData::CHANNELS`].
+/// Unlike [`render_png`] the whole image is kept in memory, 20 bytes per pixel
+pub fn render_raw_data(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+) -> Result<RawData, ExportError> {
+    renderer.apply_settings(settings)?;
+    Ok(renderer.render_raw_data(&settings.uniforms(), width, height)?)
+}
+
+/// Computes the raw data strip by strip and writes it in the format of the extension of `path`,
+/// see [`RAW_DATA_EXTENSIONS`]: a `(height, width, channels)` NumPy array, an OpenEXR image with a
+/// 32-bit float channel each, or one 16-bit grayscale PNG per channel named
+/// `<stem>_<channel>.png`. Returns the written files
+pub fn save_raw_data(
+    renderer: &mut FractalRenderer,
+    settings: &UserSettings,
+    width: u32,
+    height: u32,
+    path: &Path,
+) -> Result<Vec<PathBuf>, ExportError> {
+    let mut export = RawDataExport::create(renderer, settings, width, height, path)?;
+    while !export.is_done() {
+        export.render_strip(renderer)?;
+    }
+    export.finish()
+}
+
+/// Raw data being computed and written one strip of rows per [`RawDataExport::render_strip`],
+/// so that only one strip is ever in memory and a window can stay responsive in between
+pub struct RawDataExport {
+    settings: UserSettings,
+    uniforms: Uniforms,
+    width: u32,
+    height: u32,
+    strip_height: u32,
+    passes: u32,
+    /// Rows of all passes rendered so far
+    next_row: u64,
+    paths: Vec<PathBuf>,
+    writer: RawDataWriter,
+}
+
+enum RawDataWriter {
+    Npy(BufWriter<File>),
+    /// The EXR encoder asks for the strips itself, so it runs on a thread of its own
+    Exr {
+        strips: Option<SyncSender<RawData>>,
+        thread: Option<JoinHandle<Result<(), exr::error::Error>>>,
+    },
+    /// The value ranges go into the headers, so the first pass only finds them
+    /// and the second one writes the files
+    Png {
+        ranges: [(f32, f32); RAW_DATA_CHANNELS],
+        files: Vec<StreamWriter<'static, BufWriter<File>>>,
+    },
+}
+
+impl RawDataExport {
+    /// Creates the files of the format of the extension of `path`
+    pub fn create(
+        renderer: &mut FractalRenderer,
+        settings: &UserSettings,
+        width: u32,
+        height: u32,
+        path: &Path,
+    ) -> Result<Self, ExportError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::InvalidSize { width, height }.into());
+        }
+        if settings.render_mode != RenderMode::ESCAPE_TIME {
+            return Err(RendererError::RawDataMode.into());
+        }
+        renderer.apply_settings(settings)?;
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let (paths, passes, writer) = match extension.as_deref() {
+            Some("npy") => {
+                let mut file = BufWriter::new(File::create(path)?);
+                file.write_all(&npy_header(width, height))?;
+                (vec![path.to_path_buf()], 1, RawDataWriter::Npy(file))
+            }
+            Some("exr") => {
+                let file = BufWriter::new(File::create(path)?);
+                let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+                let thread = std::thread::spawn(move || {
+                    write_exr_strips(file, width, height, receiver.into_iter())
+                });
+                let writer = RawDataWriter::Exr {
+                    strips: Some(sender),
+                    thread: Some(thread),
+                };
+                (vec![path.to_path_buf()], 1, writer)
+            }
+            Some("png") => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let paths = RawData::CHANNELS
+                    .iter()
+                    .map(|name| path.with_file_name(format!("{stem}_{name}.png")))
+                    .collect();
+                let writer = RawDataWriter::Png {
+                    ranges: [(f32::INFINITY, f32::NEG_INFINITY); RAW_DATA_CHANNELS],
+                    files: vec![],
+                };
+                (paths, 2, writer)
+            }
+            _ => return Err(ExportError::RawDataFormat(path.to_path_buf())),
+        };
+
+        Ok(Self {
+            settings: settings.clone(),
+            uniforms: settings.uniforms(),
+            width,
+            height,
+            strip_height: strip_rows(renderer, width),
+            passes,
+            next_row: 0,
+            paths,
+            writer,
+        })
+    }
+
+    /// Computes and writes the next strip
+    pub fn render_strip(&mut self, renderer: &mut FractalRenderer) -> Result<(), ExportError> {
+        // The renderer may have drawn other settings since the previous strip
+        renderer.apply_settings(&self.settings)?;
+        let start = (self.next_row % self.height as u64) as u32;
+        let rows = start..(start + self.strip_height).min(self.height);
+        let strip =
+            renderer.render_raw_rows(&self.uniforms, self.width, self.height, rows.clone())?;
+        self.next_row += rows.len() as u64;
+
+        match &mut self.writer {
+            RawDataWriter::Npy(file) => file.write_all(bytemuck::cast_slice(&strip.values))?,
+            RawDataWriter::Exr { strips, thread } => {
+                let sent = strips.as_ref().map(|strips| strips.send(strip));
+                if !matches!(sent, Some(Ok(()))) {
+                    // The encoder stopped early, its error tells why
+                    strips.take();
+                    join_exr_thread(thread)?;
+                    return Err(exr::error::Error::Aborted.into());
+                }
+            }
+            RawDataWriter::Png { ranges, files } if files.is_empty() => {
+                for (channel, range) in ranges.iter_mut().enumerate() {
+                    *range = channel_range(strip.channel(channel), *range);
+                }
+                if rows.end == self.height {
+                    for (channel, path) in self.paths.iter().enumerate() {
+                        let file = BufWriter::new(File::create(path)?);
+                        let encoder = channel_png_encoder(
+                            file,
+                            self.width,
+                            self.height,
+                            channel,
+                            ranges[channel],
+                        )?;
+                        files.push(encoder.write_header()?.into_stream_writer()?);
+                    }
+                }
+            }
+            RawDataWriter::Png { ranges, files } => {
+                for (channel, file) in files.iter_mut().enumerate() {
+                    file.write_all(&channel_samples(strip.channel(channel), ranges[channel]))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Share of the work done so far, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.next_row as f32 / self.height as f32 / self.passes as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next_row >= self.height as u64 * self.passes as u64
+    }
+
+    /// Files written by the export, also the incomplete ones before [`RawDataExport::finish`]
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Completes the files once every strip is written and returns them
+    pub fn finish(self) -> Result<Vec<PathBuf>, ExportError> {
+        match self.writer {
+            RawDataWriter::Npy(mut file) => file.flush()?,
+            RawDataWriter::Exr {
+                mut strips,
+                mut thread,
+            } => {
+                strips.take();
+                join_exr_thread(&mut thread)?;
+            }
+            RawDataWriter::Png { files, .. } => {
+                for file in files {
+                    file.finish()?;
+                }
+            }
+        }
+        Ok(self.paths)
+    }
+}
+
+fn join_exr_thread(
+    thread: &mut Option<JoinHandle<Result<(), exr::error::Error>>>,
+) -> Result<(), ExportError> {
+    match thread.take().map(JoinHandle::join) {
+        Some(Ok(result)) => Ok(result?),
+        Some(Err(panic)) => std::panic::resume_unwind(panic),
+        None => Ok(()),
+    }
+}
+
+/// Writes a little-endian float32 NumPy array of shape `(height, width, channels)`
+pub fn write_npy(raw: &RawData, mut writer: impl Write) -> std::io::Result<()> {
+    writer.write_all(&npy_header(raw.width, raw.height))?;
+    for value in &raw.values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Magic, version, length and header of a NumPy array of raw data, the values follow
+fn npy_header(width: u32, height: u32) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({height}, {width}, {RAW_DATA_CHANNELS}), }}"
+    );
+    // Magic, version and header length take 10 bytes, the data starts at a multiple of 64
+    let padding = (10 + header.len() + 1).next_multiple_of(64) - (10 + header.len() + 1);
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend((header.len() as u16).to_le_bytes());
+    bytes.extend(header.as_bytes());
+    bytes
+}
+
+/// Writes an OpenEXR image with one 32-bit float channel per raw data channel
+pub fn write_exr(raw: &RawData, writer: impl Write + Seek) -> Result<(), ExportError> {
+    write_exr_strips(writer, raw.width, raw.height, std::iter::once(raw))?;
+    Ok(())
+}
+
+/// Writes the strips of a `width` x `height` image as scan lines, the strips must cover the
+/// image from the top. Running out of strips early aborts the file
+fn write_exr_strips<S: Borrow<RawData>>(
+    writer: impl Write + Seek,
+    width: u32,
+    height: u32,
+    mut strips: impl Iterator<Item = S>,
+) -> Result<(), exr::error::Error> {
+    use exr::block::writer::ChunksWriter;
+    use exr::block::{UncompressedBlock, enumerate_ordered_header_block_indices};
+    use exr::meta::BlockDescription;
+    use exr::meta::header::Header;
+    use exr::prelude::{ChannelDescription, Compression, LineOrder, SampleType, SmallVec, Text};
+
+    // The channels of a file are sorted by name
+    let mut channels: SmallVec<[ChannelDescription; RAW_DATA_CHANNELS]> = RawData::CHANNELS
+        .iter()
+        .map(|name| ChannelDescription::named(*name, SampleType::F32))
+        .collect();
+    channels.sort_unstable_by(|left, right| left.name.cmp(&right.name));
+    // Run length encoded scan lines are blocks of one row, so no block spans two strips
+    let header = Header::new(
+        Text::from("mandelbrot_gpu"),
+        (width as usize, height as usize),
+        channels,
+    )
+    .with_encoding(
+        Compression::RLE,
+        BlockDescription::ScanLines,
+        LineOrder::Increasing,
+    );
+    let channel_indices: Vec<usize> = header
+        .channels
+        .list
+        .iter()
+        .map(|channel| {
+            RawData::CHANNELS
+                .iter()
+                .position(|name| channel.name == **name)
+                .expect("Every channel of the file is a raw data channel")
+        })
+        .collect();
+
+    exr::block::write(
+        writer,
+        SmallVec::from_elem(header, 1),
+        true,
+        |meta, chunks| {
+            let mut strip: Option<S> = None;
+            let mut strip_start = 0;
+            for (index, block) in enumerate_ordered_header_block_indices(&meta.headers) {
+                let y = block.pixel_position.y();
+                while strip
+                    .as_ref()
+                    .is_none_or(|strip| y >= strip_start + strip.borrow().height as usize)
+                {
+                    if let Some(strip) = &strip {
+                        strip_start += strip.borrow().height as usize;
+                    }
+                    strip = Some(strips.next().ok_or(exr::error::Error::Aborted)?);
+                }
+                let strip = strip.as_ref().unwrap().borrow();
+
+                let block =
+                    UncompressedBlock::from_lines(&meta.headers[0].channels, block, |line| {
+                        let row = line.location.position.y() - strip_start;
+                        let start = row * width as usize + line.location.position.x();
+                        let channel = channel_indices[line.location.channel];
+                        line.write_samples(|x| {
+                            strip.values[(start + x) * RAW_DATA_CHANNELS + channel]
+                        })
+                        .expect("A line holds one sample per pixel");
+                    });
+                chunks.write_chunk(index, block.compress_to_chunk(&meta.headers)?)?;
+            }
+            Ok(())
+        },
+    )
+}
+
+/// Writes one channel as a 16-bit grayscale PNG. The finite values are scaled linearly
+/// from their minimum to their maximum, which are kept in tEXt chunks. Other values become 0
+pub fn write_channel_png(
+    raw: &RawData,
+    channel: usize,
+    writer: impl Write,
+) -> Result<(), ExportError> {
+    let range = channel_range(raw.channel(channel), (f32::INFINITY, f32::NEG_INFINITY));
+    let encoder = channel_png_encoder(writer, raw.width, raw.height, channel, range)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&channel_samples(raw.channel(channel), range))?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Extends the range `(minimum, maximum)` by the finite values
+fn channel_range(values: impl Iterator<Item = f32>, range: (f32, f32)) -> (f32, f32) {
+    values
+        .filter(|value| value.is_finite())
+        .fold(range, |(min, max), value| (min.min(value), max.max(value)))
+}
+
+fn channel_png_encoder<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    channel: usize,
+    (minimum, maximum): (f32, f32),
+) -> Result<png::Encoder<'static, W>, png::EncodingError> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Sixteen);
+    encoder.add_text_chunk(
+        CHANNEL_KEYWORD.to_string(),
+        RawData::CHANNELS[channel].to_string(),
+    )?;
+    if minimum <= maximum {
+        encoder.add_text_chunk(MINIMUM_KEYWORD.to_string(), minimum.to_string())?;
+        encoder.add_text_chunk(MAXIMUM_KEYWORD.to_string(), maximum.to_string())?;
+    }
+    Ok(encoder)
+}
+
+/// Big-endian 16-bit samples of the values scaled to the range
+fn channel_samples(values: impl Iterator<Item = f32>, (minimum, maximum): (f32, f32)) -> Vec<u8> {
+    let range = maximum - minimum;
+    values
+        .flat_map(|value| {
+            let sample = if value.is_finite() && range > 0.0 {
+                ((value - minimum) / range * u16::MAX as f32).round() as u16
+            } else {
+                0
+            };
+            sample.to_be_bytes()
+        })
+        .collect()
+}
+
+/// Parameters embedded by [`render_png`], `None` for PNG images from elsewhere
+pub fn read_parameters(reader: impl Read) -> Result<Option<String>, png::DecodingError> {
+    let reader = png::Decoder::new(reader).read_info()?;
+    reader
+        .info()
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == PARAMETERS_KEYWORD)
+        .map(png::text_metadata::ITXtChunk::get_text)
+        .transpose()
+}
+
+fn png_encoder<W: Write>(writer: W, width: u32, height: u32) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+}
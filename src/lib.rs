@@ -1,5 +1,13 @@
+pub mod animation;
+pub mod bookmarks;
+pub mod cli;
+mod formula;
 pub mod fractal_app;
+pub mod fractal_renderer;
 mod fv_render_callback;
 mod fv_renderer_resource;
-mod uniforms;
-mod user_settings;
+pub mod image_export;
+pub mod location;
+mod polynomial;
+pub mod uniforms;
+pub mod user_settings;
@@ -0,0 +1,125 @@
+//! Short text form of the view, to share a location in a chat and paste it back.
+//!
+//! `mandelbrot:1:eNqrVkrJT…` is the format version and the URL-safe base64 of the zlib-compressed
+//! JSON of the image fields that differ from the defaults. The zlib checksum rejects strings that
+//! were cut off or mistyped
+
+use crate::user_settings::UserSettings;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::{DecodeError, Engine};
+use miniz_oxide::inflate::TINFLStatus;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+/// Start of every location string
+pub const LOCATION_PREFIX: &str = "mandelbrot";
+
+/// Version of the location strings. Increase it when the defaults of image fields change,
+/// since the strings leave out the fields that have their default values
+pub const LOCATION_VERSION: u32 = 1;
+
+const COMPRESSION_LEVEL: u8 = 9;
+
+/// Decompressed parameters are far smaller, a larger result is not a location
+const MAX_PARAMETERS_SIZE: usize = 1 << 20;
+
+#[derive(Debug)]
+pub enum LocationError {
+    /// Does not start with [`LOCATION_PREFIX`] and a version
+    NotLocation,
+    /// Written by an older version whose fields no longer mean the same
+    Outdated(u32),
+    /// Written by a newer version of the app
+    Newer(u32),
+    /// Cut off when copied
+    Truncated,
+    /// Characters were changed or lost in the middle
+    Corrupted,
+    /// The parameters do not fit the settings
+    Parameters(serde_json::Error),
+}
+
+impl Display for LocationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotLocation => write!(f, "это не место, ожидалось {LOCATION_PREFIX}:…"),
+            Self::Outdated(version) => write!(
+                f,
+                "место версии {version} устарело, поддерживается версия {LOCATION_VERSION}"
+            ),
+            Self::Newer(version) => write!(
+                f,
+                "место версии {version} новее поддерживаемой версии {LOCATION_VERSION}"
+            ),
+            Self::Truncated => write!(f, "место обрезано"),
+            Self::Corrupted => write!(f, "место повреждено"),
+            Self::Parameters(error) => write!(f, "неверные параметры места: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LocationError {}
+
+/// Location string of the view of `settings`
+pub fn encode_location(settings: &UserSettings) -> String {
+    let parameters: Value = serde_json::from_str(&settings.image_parameters())
+        .expect("Image parameters are always valid JSON");
+    let defaults: Value = serde_json::from_str(&UserSettings::new().image_parameters())
+        .expect("Image parameters are always valid JSON");
+
+    let (Value::Object(mut fields), Value::Object(defaults)) = (parameters, defaults) else {
+        unreachable!("Image parameters are always a JSON object");
+    };
+    fields.retain(|name, value| defaults.get(name) != Some(value));
+
+    let json = Value::Object(fields).to_string();
+    let compressed = miniz_oxide::deflate::compress_to_vec_zlib(json.as_bytes(), COMPRESSION_LEVEL);
+    format!(
+        "{LOCATION_PREFIX}:{LOCATION_VERSION}:{}",
+        URL_SAFE_NO_PAD.encode(compressed)
+    )
+}
+
+/// Copy of `settings` showing the view of a location string. Whitespace around the string and
+/// line breaks inside it, e.g. added by a chat, are ignored
+pub fn decode_location(
+    location: &str,
+    settings: &UserSettings,
+) -> Result<UserSettings, LocationError> {
+    let location: String = location.split_whitespace().collect();
+    let mut parts = location.splitn(3, ':');
+    let (Some(LOCATION_PREFIX), Some(version), Some(data)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(LocationError::NotLocation);
+    };
+    let version: u32 = version.parse().map_err(|_| LocationError::NotLocation)?;
+    if version < LOCATION_VERSION {
+        return Err(LocationError::Outdated(version));
+    }
+    if version > LOCATION_VERSION {
+        return Err(LocationError::Newer(version));
+    }
+
+    let compressed = URL_SAFE_NO_PAD.decode(data).map_err(|error| match error {
+        DecodeError::InvalidLength(_) | DecodeError::InvalidLastSymbol(..) => {
+            LocationError::Truncated
+        }
+        _ => LocationError::Corrupted,
+    })?;
+    let json =
+        miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed, MAX_PARAMETERS_SIZE)
+            .map_err(|error| match error.status {
+            TINFLStatus::FailedCannotMakeProgress => LocationError::Truncated,
+            _ => LocationError::Corrupted,
+        })?;
+    let json = String::from_utf8(json).map_err(|_| LocationError::Corrupted)?;
+
+    // The left out fields are the defaults, the window fields stay as they are
+    let view = UserSettings::new()
+        .with_image_parameters(&json)
+        .map_err(LocationError::Parameters)?;
+    settings
+        .with_image_parameters(&view.image_parameters())
+        .map_err(LocationError::Parameters)
+}
@@ -1,7 +1,7 @@
 use eframe::NativeOptions;
 use egui::ViewportBuilder;
 use egui_wgpu::WgpuConfiguration;
-use mandelbrot_gpu::fractal_app::FractalApp;
+use mandelbrot_gpu::fractal_app::{APP_NAME, FractalApp};
 use mimalloc::MiMalloc;
 use wgpu::PresentMode;
 
@@ -26,7 +26,7 @@ fn main() -> Result<(), eframe::Error> {
     };
 
     eframe::run_native(
-        "Mandelbrot wgpu",
+        APP_NAME,
         options,
         Box::new(|cc| Ok(Box::new(FractalApp::new(cc)))),
     )?;
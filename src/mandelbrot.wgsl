@@ -1,13 +1,72 @@
 @group(0) @binding(0) var <uniform> params: Params;
+// Three channels per pixel, row by row
+@group(0) @binding(1) var<storage, read_write> density: array<atomic<u32>>;
+@group(0) @binding(2) var<storage, read_write> buddhabrot_state: BuddhabrotState;
+// Pixels whose orbits ran out of iterations, counted for the automatic iteration limit
+@group(0) @binding(3) var<storage, read_write> limit_hits: atomic<u32>;
+// RAW_DATA_CHANNELS values per pixel of the tile written by cs_raw_data
+@group(0) @binding(4) var<storage, read_write> raw_data: array<f32>;
 
 const RGB_SCHEME: u32 = 1;
 const HSV_SCHEME: u32 = 2;
 
+const ESCAPE_TIME_RENDER_MODE: u32 = 1;
+const LYAPUNOV_RENDER_MODE: u32 = 2;
+const BUDDHABROT_RENDER_MODE: u32 = 4;
+const RAYMARCH_3D_RENDER_MODE: u32 = 8;
+const DOMAIN_COLORING_RENDER_MODE: u32 = 16;
+
+const DOMAIN_MODULUS_CONTOURS: u32 = 1;
+const DOMAIN_GRID: u32 = 2;
+
+const MANDELBULB_FRACTAL_3D: u32 = 1;
+const QUATERNION_JULIA_FRACTAL_3D: u32 = 2;
+
+const RAYMARCH_SOFT_SHADOWS: u32 = 1;
+const RAYMARCH_AMBIENT_OCCLUSION: u32 = 2;
+const RAYMARCH_ORBIT_TRAP: u32 = 4;
+
+const BUDDHABROT_ANTI: u32 = 1;
+const BUDDHABROT_NEBULA: u32 = 2;
+const BUDDHABROT_IMPORTANCE: u32 = 4;
+// Cells per side of the importance sampling grid
+const BUDDHABROT_CELLS: u32 = 128;
+// Orbits add their weight in fixed point, so that the density stays in integer atomics
+const BUDDHABROT_WEIGHT_SCALE: f32 = 16.0;
+
+const NORM_BAILOUT: u32 = 1;
+const REAL_BAILOUT: u32 = 2;
+const IMAGINARY_BAILOUT: u32 = 4;
+const MANHATTAN_BAILOUT: u32 = 8;
+const MAX_NORM_BAILOUT: u32 = 16;
+const PRODUCT_BAILOUT: u32 = 32;
+const CONVERGENCE_BAILOUT: u32 = 64;
+
+const PERIODICITY_CHECK: u32 = 1;
+const PERIODICITY_INTERIOR_COLORING: u32 = 2;
+
 const EPSILON: f32 = 0.001;
 const AXIS_EPSILON: f32 = 0.005;
 
 const JULIA_FRACTAL_TYPE: u32 = 2;
 const MANDELBROT_FRACTAL_TYPE: u32 = 1;
+const BURNING_SHIP_FRACTAL_TYPE: u32 = 4;
+const TRICORN_FRACTAL_TYPE: u32 = 8;
+const CELTIC_FRACTAL_TYPE: u32 = 16;
+const BUFFALO_FRACTAL_TYPE: u32 = 32;
+const PERPENDICULAR_MANDELBROT_FRACTAL_TYPE: u32 = 64;
+const PERPENDICULAR_BURNING_SHIP_FRACTAL_TYPE: u32 = 128;
+const HEART_FRACTAL_TYPE: u32 = 256;
+const NEWTON_FRACTAL_TYPE: u32 = 512;
+const PHOENIX_FRACTAL_TYPE: u32 = 1024;
+const MAGNET_1_FRACTAL_TYPE: u32 = 2048;
+const MAGNET_2_FRACTAL_TYPE: u32 = 4096;
+const CUSTOM_FRACTAL_TYPE: u32 = 8192;
+const HYBRID_FRACTAL_TYPE: u32 = 16384;
+
+const MAX_NEWTON_ROOTS: u32 = 16;
+const MAX_FORMULA_PARAMS: u32 = 8;
+const MAX_HYBRID_STEPS: u32 = 8;
 
 struct Params {
     center: vec4f, // 2 points
@@ -22,7 +81,62 @@ struct Params {
     show_axis: u32,
     escape_threshold: f32,
     fractal_type: u32,
-    pow: u32
+    pow: f32,
+    newton_root_count: u32,
+    newton_nova: u32,
+    newton_tolerance: f32,
+    magnet_tolerance: f32,
+    hybrid_step_count: u32,
+    newton_relaxation: vec4f, // 2 points
+    newton_roots: array<vec4f, MAX_NEWTON_ROOTS>, // 2 points each
+    phoenix_p: vec4f, // 2 points
+    formula_params: array<vec4f, MAX_FORMULA_PARAMS / 2>, // 4 points each, 2 parameters
+    hybrid_steps: array<vec4u, MAX_HYBRID_STEPS / 4>, // 4 steps each: fractal type bits | repeats << 16
+    render_mode: u32,
+    lyapunov_sequence: u32, // bit i is 1 if the i-th letter is B
+    lyapunov_sequence_length: u32,
+    lyapunov_warmup: u32,
+    lyapunov_iterations: u32,
+    buddhabrot_width: u32,
+    buddhabrot_height: u32,
+    buddhabrot_seed: u32,
+    buddhabrot_flags: u32,
+    buddhabrot_importance: f32,
+    buddhabrot_gamma: f32,
+    buddhabrot_limits: vec4u, // red, green, blue
+    quaternion_c: vec4f,
+    camera_yaw: f32,
+    camera_pitch: f32,
+    camera_distance: f32,
+    camera_fov: f32, // vertical, radians
+    fractal_3d: u32,
+    raymarch_flags: u32,
+    raymarch_steps: u32,
+    raymarch_iterations: u32,
+    domain_flags: u32,
+    domain_contour_base: f32,
+    domain_grid_spacing: f32,
+    bailout_test: u32,
+    convergence_epsilon: f32,
+    periodicity_flags: u32,
+    periodicity_epsilon: f32,
+    cardioid_check: u32,
+    auto_iterations: u32,
+    // Origin of the render target in the image and the size of the whole image, in pixels.
+    // A zero size means that the target is the whole image
+    image_rect: vec4u,
+    // Size of the tile of cs_raw_data, zero unless raw data is exported
+    raw_data_width: u32,
+    raw_data_height: u32,
+    // Shift of the escape time colors along the palette
+    palette_offset: f32,
+}
+
+struct BuddhabrotState {
+    max_density: array<atomic<u32>, 3>,
+    cell_count: atomic<u32>,
+    cells: array<u32, BUDDHABROT_CELLS * BUDDHABROT_CELLS>,
+    boundary: array<u32, BUDDHABROT_CELLS * BUDDHABROT_CELLS>, // 1 for the cells listed in cells
 }
 
 struct Complex {
@@ -37,13 +151,64 @@ fn norm_sqrt(c: Complex) -> f32 {
     return sqrt(norm_sqr(c));
 }
 
-fn complex_pow(c: Complex, n: u32) -> Complex {
-    var r = norm_sqrt(c);
-    let pow_degree = f32(n);
+// Integer exponents up to this magnitude are computed by repeated squaring
+const INTEGER_POW_LIMIT: f32 = 255.0;
+const PI: f32 = 3.14159265358979;
+const E: f32 = 2.71828182845905;
+// Stands in for infinity so that escape tests still work with finite arithmetic
+const COMPLEX_INFINITY: f32 = 1e18;
+// Close to the largest f32, orbits beyond it have overflowed
+const OVERFLOW_LIMIT: f32 = 3e38;
+
+fn complex_pow(c: Complex, n: f32) -> Complex {
+    if (n == 0.0) {
+        return Complex(1.0, 0.0);
+    }
+
+    let r_sqr = norm_sqr(c);
+    if (r_sqr == 0.0) {
+        if (n < 0.0) {
+            return Complex(COMPLEX_INFINITY, 0.0);
+        }
+        return Complex(0.0, 0.0);
+    }
+
+    if (n == round(n) && abs(n) <= INTEGER_POW_LIMIT) {
+        var result = Complex(1.0, 0.0);
+        var base = c;
+        var e = u32(abs(n));
+        while (e > 0u) {
+            if ((e & 1u) == 1u) {
+                result = mul(result, base);
+            }
+            e = e >> 1u;
+            if (e > 0u) {
+                base = mul(base, base);
+            }
+        }
+        if (n < 0.0) {
+            return reciprocal(result);
+        }
+        return result;
+    }
+
+    // Principal branch: arg in (-pi, pi], so the cut lies along the negative real axis
     var theta = atan2(c.im, c.re);
-    var rn = pow(r, pow_degree);
+    if (theta <= -PI) {
+        theta += 2.0 * PI;
+    }
+    let rn = exp(0.5 * n * log(r_sqr));
+    let angle = n * theta;
+
+    return Complex(rn * cos(angle), rn * sin(angle));
+}
 
-    return Complex(rn * cos(pow_degree * theta), rn * sin(pow_degree * theta));
+fn reciprocal(c: Complex) -> Complex {
+    let d = norm_sqr(c);
+    if (d == 0.0) {
+        return Complex(COMPLEX_INFINITY, 0.0);
+    }
+    return Complex(c.re / d, -c.im / d);
 }
 
 fn mul(c1: Complex, c2: Complex) -> Complex {
@@ -54,30 +219,396 @@ fn sum(c1: Complex, c2: Complex) -> Complex {
     return Complex(c1.re + c2.re, c1.im + c2.im);
 }
 
-fn escape_time(c: Complex, limit: u32) -> i32 {
+fn sub(c1: Complex, c2: Complex) -> Complex {
+    return Complex(c1.re - c2.re, c1.im - c2.im);
+}
+
+fn neg(c: Complex) -> Complex {
+    return Complex(-c.re, -c.im);
+}
+
+fn div(c1: Complex, c2: Complex) -> Complex {
+    return mul(c1, reciprocal(c2));
+}
+
+fn conj(c: Complex) -> Complex {
+    return Complex(c.re, -c.im);
+}
+
+fn c_arg(c: Complex) -> f32 {
+    return atan2(c.im, c.re);
+}
+
+fn c_exp(c: Complex) -> Complex {
+    let r = exp(c.re);
+    return Complex(r * cos(c.im), r * sin(c.im));
+}
+
+// Principal branch
+fn c_log(c: Complex) -> Complex {
+    return Complex(0.5 * log(norm_sqr(c)), c_arg(c));
+}
+
+// Principal branch, the result has a non-negative real part
+fn c_sqrt(c: Complex) -> Complex {
+    let r = norm_sqrt(c);
+    let re = sqrt(max(0.5 * (r + c.re), 0.0));
+    var im = sqrt(max(0.5 * (r - c.re), 0.0));
+    if (c.im < 0.0) {
+        im = -im;
+    }
+    return Complex(re, im);
+}
+
+fn c_pow(base: Complex, exponent: Complex) -> Complex {
+    if (norm_sqr(base) == 0.0) {
+        return Complex(0.0, 0.0);
+    }
+    return c_exp(mul(exponent, c_log(base)));
+}
+
+fn c_sin(c: Complex) -> Complex {
+    return Complex(sin(c.re) * cosh(c.im), cos(c.re) * sinh(c.im));
+}
+
+fn c_cos(c: Complex) -> Complex {
+    return Complex(cos(c.re) * cosh(c.im), -sin(c.re) * sinh(c.im));
+}
+
+fn c_tan(c: Complex) -> Complex {
+    return div(c_sin(c), c_cos(c));
+}
+
+fn c_sinh(c: Complex) -> Complex {
+    return Complex(sinh(c.re) * cos(c.im), cosh(c.re) * sin(c.im));
+}
+
+fn c_cosh(c: Complex) -> Complex {
+    return Complex(cosh(c.re) * cos(c.im), sinh(c.re) * sin(c.im));
+}
+
+fn formula_param(i: u32) -> Complex {
+    let pair = params.formula_params[i / 2];
+    if (i % 2 == 0) {
+        return Complex(pair.x, pair.y);
+    }
+    return Complex(pair.z, pair.w);
+}
+
+// The expression between the markers is replaced with the compiled user formula at runtime
+fn custom_formula(z: Complex, c: Complex) -> Complex {
+    return
+        // formula:begin
+        sum(mul(z, z), c)
+        // formula:end
+    ;
+}
+
+// Same as custom_formula, for the function plotted by the domain coloring
+fn domain_function(z: Complex) -> Complex {
+    return
+        // domain:begin
+        z
+        // domain:end
+    ;
+}
+
+// escape_threshold is the squared escape radius R^2 of the classic |z|^2 > R^2 test.
+// Orbits that overflowed escape under every test, otherwise they would turn into NaN
+fn is_escaped(z: Complex) -> bool {
+    if (!(norm_sqr(z) < OVERFLOW_LIMIT)) {
+        return true;
+    }
+
+    let radius = sqrt(params.escape_threshold);
+    let test = params.bailout_test;
+    if ((test & REAL_BAILOUT) > 0) {
+        return abs(z.re) > radius;
+    }
+    if ((test & IMAGINARY_BAILOUT) > 0) {
+        return abs(z.im) > radius;
+    }
+    if ((test & MANHATTAN_BAILOUT) > 0) {
+        return abs(z.re) + abs(z.im) > radius;
+    }
+    if ((test & MAX_NORM_BAILOUT) > 0) {
+        return max(abs(z.re), abs(z.im)) > radius;
+    }
+    if ((test & PRODUCT_BAILOUT) > 0) {
+        return abs(z.re * z.im) > params.escape_threshold;
+    }
+    return norm_sqr(z) > params.escape_threshold;
+}
+
+struct EscapeResult {
+    iterations: i32, // -1 if the orbit neither escaped nor converged within the limit
+    root: i32, // index of the Newton root the orbit converged to, -1 otherwise
+    converged: bool, // the orbit stopped at a fixed point instead of escaping
+    period: i32, // period of the attracting cycle found by the periodicity check, 0 if none
+    z: Complex, // last value of the orbit
+    derivative: f32, // |dz/dc| for the distance estimate, only tracked for raw data, 0 if unknown
+}
+
+fn newton_root(i: u32) -> Complex {
+    let root = params.newton_roots[i];
+    return Complex(root.x, root.y);
+}
+
+// z - a * p(z) / p'(z) (+ c for Nova), where p'(z) / p(z) is the sum of 1 / (z - root)
+fn newton_step(z: Complex, c: Complex) -> Complex {
+    var s = Complex(0.0, 0.0);
+    for (var i: u32 = 0; i < params.newton_root_count; i++) {
+        s = sum(s, reciprocal(sub(z, newton_root(i))));
+    }
+
+    let relaxation = Complex(params.newton_relaxation.x, params.newton_relaxation.y);
+    var next = sub(z, mul(relaxation, reciprocal(s)));
+    if (params.newton_nova > 0) {
+        next = sum(next, c);
+    }
+    return next;
+}
+
+// Nova shifts the fixed points away from the roots, so it stops on any convergence instead
+fn newton_time(z0: Complex, c: Complex, limit: u32) -> EscapeResult {
+    let tolerance_sqr = params.newton_tolerance * params.newton_tolerance;
+    var z = z0;
+    let l = i32(limit);
+    for (var i: i32 = 0; i < l; i++) {
+        if (params.newton_nova > 0) {
+            let next = newton_step(z, c);
+            if (norm_sqr(sub(next, z)) < tolerance_sqr) {
+                return EscapeResult(i, -1, true, 0, next, 0.0);
+            }
+            z = next;
+            continue;
+        }
+
+        for (var r: u32 = 0; r < params.newton_root_count; r++) {
+            if (norm_sqr(sub(z, newton_root(r))) < tolerance_sqr) {
+                return EscapeResult(i, i32(r), true, 0, z, 0.0);
+            }
+        }
+        z = newton_step(z, c);
+    }
+    return EscapeResult(-1, -1, false, 0, z, 0.0);
+}
+
+// Applies the abs/conjugate transforms of the variant around z^pow
+fn fractal_step(z: Complex, c: Complex, fractal_type: u32) -> Complex {
+    var w = z;
+    if ((fractal_type & BURNING_SHIP_FRACTAL_TYPE) > 0) {
+        w = Complex(abs(w.re), abs(w.im));
+    }
+    if ((fractal_type & TRICORN_FRACTAL_TYPE) > 0) {
+        w = Complex(w.re, -w.im);
+    }
+    if ((fractal_type & PERPENDICULAR_MANDELBROT_FRACTAL_TYPE) > 0) {
+        w = Complex(abs(w.re), -w.im);
+    }
+    if ((fractal_type & PERPENDICULAR_BURNING_SHIP_FRACTAL_TYPE) > 0) {
+        w = Complex(w.re, -abs(w.im));
+    }
+    if ((fractal_type & HEART_FRACTAL_TYPE) > 0) {
+        w = Complex(abs(w.re), w.im);
+    }
+
+    w = complex_pow(w, params.pow);
+
+    if ((fractal_type & CELTIC_FRACTAL_TYPE) > 0) {
+        w = Complex(abs(w.re), w.im);
+    }
+    if ((fractal_type & BUFFALO_FRACTAL_TYPE) > 0) {
+        w = Complex(abs(w.re), abs(w.im));
+    }
+
+    return sum(w, c);
+}
+
+// ((z^2 + c - 1) / (2z + c - 2))^2
+fn magnet_1_step(z: Complex, c: Complex) -> Complex {
+    let one = Complex(1.0, 0.0);
+    let two = Complex(2.0, 0.0);
+    let numerator = sub(sum(mul(z, z), c), one);
+    let denominator = sub(sum(mul(two, z), c), two);
+    let w = mul(numerator, reciprocal(denominator));
+    return mul(w, w);
+}
+
+// ((z^3 + 3(c - 1)z + (c - 1)(c - 2)) / (3z^2 + 3(c - 2)z + (c - 1)(c - 2) + 1))^2
+fn magnet_2_step(z: Complex, c: Complex) -> Complex {
+    let three = Complex(3.0, 0.0);
+    let c1 = sub(c, Complex(1.0, 0.0));
+    let c2 = sub(c, Complex(2.0, 0.0));
+    let c1c2 = mul(c1, c2);
+    let z2 = mul(z, z);
+    let numerator = sum(sum(mul(z2, z), mul(mul(three, c1), z)), c1c2);
+    let denominator = sum(sum(sum(mul(three, z2), mul(mul(three, c2), z)), c1c2), Complex(1.0, 0.0));
+    let w = mul(numerator, reciprocal(denominator));
+    return mul(w, w);
+}
+
+fn hybrid_step_at(index: u32) -> u32 {
+    return params.hybrid_steps[index / 4][index % 4];
+}
+
+fn hybrid_step(z: Complex, c: Complex, fractal_type: u32) -> Complex {
+    if ((fractal_type & CUSTOM_FRACTAL_TYPE) > 0) {
+        return custom_formula(z, c);
+    }
+    return fractal_step(z, c, fractal_type);
+}
+
+// Main cardioid and period-2 bulb of the classic z^2 + c with z0 = 0, whose points never escape.
+// Returns the period of the attracting cycle of c, 0 outside of both
+fn cardioid_or_bulb_period(c: Complex) -> i32 {
+    let x = c.re - 0.25;
+    let y_sqr = c.im * c.im;
+    let q = x * x + y_sqr;
+    if (q * (q + x) <= 0.25 * y_sqr) {
+        return 1;
+    }
+    let x_bulb = c.re + 1.0;
+    if (x_bulb * x_bulb + y_sqr <= 0.0625) {
+        return 2;
+    }
+    return 0;
+}
+
+fn escape_time(c: Complex, limit: u32) -> EscapeResult {
     let constant = Complex(params.initial_value.x, params.initial_value.y);
+
+    // The convergence test would stop these orbits at their fixed point instead of running to the limit
+    if (params.cardioid_check > 0
+        && params.fractal_type == MANDELBROT_FRACTAL_TYPE
+        && params.pow == 2.0
+        && constant.re == 0.0 && constant.im == 0.0
+        && (params.bailout_test & CONVERGENCE_BAILOUT) == 0) {
+        let period = cardioid_or_bulb_period(c);
+        if (period > 0) {
+            return EscapeResult(-1, -1, false, period, Complex(0.0, 0.0), 0.0);
+        }
+    }
+
+    if ((params.fractal_type & NEWTON_FRACTAL_TYPE) > 0) {
+        // Without Nova there is no c, so the pixel is always the starting point.
+        // Nova starts at z0 = 1 shifted by the initial value: z = 0 is a critical point of the
+        // default z^3 - 1, where p'(z) = 0 and the first step would divide by zero
+        if (params.newton_nova > 0 && (params.fractal_type & MANDELBROT_FRACTAL_TYPE) > 0) {
+            return newton_time(sum(Complex(1.0, 0.0), constant), c, limit);
+        }
+        return newton_time(c, constant, limit);
+    }
+
     var z: Complex;
+    let is_julia = (params.fractal_type & JULIA_FRACTAL_TYPE) > 0;
 
-    if ((params.fractal_type & JULIA_FRACTAL_TYPE) > 0) {
+    if (is_julia) {
         z = c;
     } else {
         z = constant;
     }
+    var k = c;
+    if ((params.fractal_type & JULIA_FRACTAL_TYPE) > 0) {
+        k = constant;
+    }
+
+    let is_magnet = (params.fractal_type & (MAGNET_1_FRACTAL_TYPE | MAGNET_2_FRACTAL_TYPE)) > 0;
+    let magnet_tolerance_sqr = params.magnet_tolerance * params.magnet_tolerance;
+    let phoenix_p = Complex(params.phoenix_p.x, params.phoenix_p.y);
+    var z_prev = Complex(0.0, 0.0);
+    let is_hybrid = (params.fractal_type & HYBRID_FRACTAL_TYPE) > 0 && params.hybrid_step_count > 0;
+    var hybrid_index: u32 = 0;
+    var hybrid_repeat: u32 = 0;
+
+    let is_convergence_test = (params.bailout_test & CONVERGENCE_BAILOUT) > 0;
+    let convergence_epsilon_sqr = params.convergence_epsilon * params.convergence_epsilon;
+
+    // The orbit must be determined by z alone: Phoenix also depends on the previous value,
+    // hybrids on the position in the sequence, and magnets have their own convergence test
+    let check_periodicity = (params.periodicity_flags & PERIODICITY_CHECK) > 0
+        && (params.fractal_type & (PHOENIX_FRACTAL_TYPE | HYBRID_FRACTAL_TYPE)) == 0
+        && !is_magnet;
+    let periodicity_epsilon_sqr = params.periodicity_epsilon * params.periodicity_epsilon;
+
+    // Only the plain z^pow + c follows the power law of the distance estimate. The abs and
+    // conjugate variants, Phoenix, hybrids, magnets and custom formulas have none
+    let track_derivative = params.raw_data_width > 0
+        && (params.fractal_type & ~(MANDELBROT_FRACTAL_TYPE | JULIA_FRACTAL_TYPE)) == 0;
+    // dz/dc for Mandelbrot, dz/dz0 for Julia
+    var derivative = Complex(select(0.0, 1.0, is_julia), 0.0);
+    var saved_z = z;
+    var saved_interval: i32 = 1;
+    var steps_since_saved: i32 = 0;
+
     let l = i32(limit);
     for (var i: i32 = 0; i < l; i++) {
-        let z_sqrt = norm_sqr(z);
+        if (is_escaped(z)) {
+            let modulus = select(0.0, norm_sqrt(derivative), track_derivative);
+            return EscapeResult(i, -1, false, 0, z, modulus);
+        }
 
-        if z_sqrt > params.escape_threshold {
-            return i;
+        // 1 is a superattracting fixed point of both magnet maps
+        if (is_magnet && norm_sqr(sub(z, Complex(1.0, 0.0))) < magnet_tolerance_sqr) {
+            return EscapeResult(i, -1, true, 0, z, 0.0);
         }
 
-        if ((params.fractal_type & JULIA_FRACTAL_TYPE) > 0) {
-            z = sum(complex_pow(z, params.pow), constant);
+        // Running derivative of the power law: dz' = pow z^(pow - 1) dz, plus 1 for dc.
+        // Kept complex, the moduli alone miss the cancellation between the two terms
+        if (track_derivative) {
+            derivative = mul(complex_pow(z, params.pow - 1.0), derivative);
+            derivative = Complex(
+                params.pow * derivative.re + select(1.0, 0.0, is_julia),
+                params.pow * derivative.im,
+            );
+        }
+
+        var next: Complex;
+        if ((params.fractal_type & MAGNET_1_FRACTAL_TYPE) > 0) {
+            next = magnet_1_step(z, k);
+        } else if ((params.fractal_type & MAGNET_2_FRACTAL_TYPE) > 0) {
+            next = magnet_2_step(z, k);
+        } else if ((params.fractal_type & CUSTOM_FRACTAL_TYPE) > 0) {
+            next = custom_formula(z, k);
+        } else if (is_hybrid) {
+            let step = hybrid_step_at(hybrid_index);
+            next = hybrid_step(z, k, step & 0xFFFF);
+            hybrid_repeat++;
+            if (hybrid_repeat >= (step >> 16)) {
+                hybrid_repeat = 0;
+                hybrid_index = (hybrid_index + 1) % params.hybrid_step_count;
+            }
         } else {
-            z = sum(complex_pow(z, params.pow), c);
+            next = fractal_step(z, k, params.fractal_type);
+        }
+
+        if ((params.fractal_type & PHOENIX_FRACTAL_TYPE) > 0) {
+            next = sum(next, mul(phoenix_p, z_prev));
+        }
+
+        z_prev = z;
+        z = next;
+
+        // Also stops at attracting cycles of period 1, complementing the divergence test
+        if (is_convergence_test && norm_sqr(sub(z, z_prev)) < convergence_epsilon_sqr) {
+            return EscapeResult(i + 1, -1, true, 0, z, 0.0);
+        }
+
+        // Brent's cycle detection: compare with a point saved at doubling intervals,
+        // so that cycles of any length are found without storing the orbit
+        if (check_periodicity) {
+            steps_since_saved++;
+            if (norm_sqr(sub(z, saved_z)) < periodicity_epsilon_sqr) {
+                return EscapeResult(-1, -1, false, steps_since_saved, z, 0.0);
+            }
+            if (steps_since_saved == saved_interval) {
+                saved_z = z;
+                saved_interval *= 2;
+                steps_since_saved = 0;
+            }
         }
     }
-    return -1;
+    return EscapeResult(-1, -1, false, 0, z, 0.0);
 }
 
 struct VsOut {
@@ -138,46 +669,665 @@ fn vs_main(@builtin(vertex_index) index: u32) -> VsOut {
     return out;
 }
 
+// Position of the pixel in the whole image, [0..=1] from the bottom left corner.
+// Computed from the pixel coordinates for a tile, so that tiles meet without seams
+fn image_uv(in: VsOut) -> vec2f {
+    if (params.image_rect.z == 0) {
+        return in.uv;
+    }
+    let pixel = vec2f(params.image_rect.xy) + in.position.xy;
+    let size = vec2f(params.image_rect.zw);
+    return vec2f(pixel.x / size.x, 1.0 - pixel.y / size.y);
+}
+
+// Point of the complex plane at a position in the image
+fn plane_point(uv: vec2f) -> Complex {
+    let x = (uv.x - 0.5) / params.zoom * 3.0;
+    let y = (uv.y - 0.5) / params.zoom * 2.0;
+    return sum(Complex(params.center.x, params.center.y), Complex(x, y));
+}
+
 @fragment
 fn fs_main(in: VsOut) -> @location(0) vec4f {
-    let center = Complex(params.center.x, params.center.y);
+    let uv = image_uv(in);
+
+    // The axes belong to the complex plane, which the 3D view does not show
+    if ((params.render_mode & RAYMARCH_3D_RENDER_MODE) > 0) {
+        return raymarch_color(uv);
+    }
+
     let scale = params.zoom;
-    let x = (in.uv.x - 0.5)  / scale * 3.0;
-    let y = (in.uv.y - 0.5) / scale * 2.0;
+    let c = plane_point(uv);
 
-    let current_point = Complex(x, y);
-    let c = sum(center, current_point);
+    // Screen-space derivatives of the grid lines must be taken before any per-pixel return
+    var domain = vec4f(0.0);
+    if ((params.render_mode & DOMAIN_COLORING_RENDER_MODE) > 0) {
+        domain = domain_color(c);
+    }
 
-    if ((params.show_axis & 1) > 0) {
-        let scaled_epsilon = EPSILON / scale;
-        let scaled_axis_epsilon = AXIS_EPSILON / scale;
-        let axis_epsilon = scaled_epsilon * 25;
+    if ((params.show_axis & 1) > 0 && is_on_axis(c, scale)) {
+        return vec4f(1.0);
+    }
 
-        if (abs(c.im) >= axis_epsilon && abs(abs(c.im) - abs(floor(c.im))) <= scaled_axis_epsilon && abs(c.re) <= axis_epsilon) {
-            return vec4f(255, 255, 255, 0);
-        }
-        if (abs(c.re) >= axis_epsilon && abs(abs(c.re) - abs(floor(c.re))) <= scaled_axis_epsilon && abs(c.im) <= axis_epsilon) {
-            return vec4f(255, 255, 255, 0);
+    if ((params.render_mode & DOMAIN_COLORING_RENDER_MODE) > 0) {
+        return domain;
+    }
+    if ((params.render_mode & LYAPUNOV_RENDER_MODE) > 0) {
+        return lyapunov_color(c);
+    }
+    if ((params.render_mode & BUDDHABROT_RENDER_MODE) > 0) {
+        return buddhabrot_color(uv);
+    }
+    return escape_time_color(c);
+}
+
+// Both axes with a tick at every integer
+fn is_on_axis(c: Complex, scale: f32) -> bool {
+    let scaled_epsilon = EPSILON / scale;
+    let scaled_axis_epsilon = AXIS_EPSILON / scale;
+    let axis_epsilon = scaled_epsilon * 25;
+
+    if (abs(c.im) >= axis_epsilon && abs(abs(c.im) - abs(floor(c.im))) <= scaled_axis_epsilon && abs(c.re) <= axis_epsilon) {
+        return true;
+    }
+    if (abs(c.re) >= axis_epsilon && abs(abs(c.re) - abs(floor(c.re))) <= scaled_axis_epsilon && abs(c.im) <= axis_epsilon) {
+        return true;
+    }
+    return abs(c.re) <= scaled_epsilon || abs(c.im) <= scaled_epsilon;
+}
+
+// Hue is the argument of f(z); contours and grid lines darken the color
+fn domain_color(z: Complex) -> vec4f {
+    let w = domain_function(z);
+    let modulus = norm_sqrt(w);
+    let grid = vec2f(w.re, w.im) / params.domain_grid_spacing;
+    // Pixel footprint of the grid, keeps the lines about a pixel wide however f stretches the plane
+    let grid_width = fwidth(grid);
+
+    // Poles and overflows
+    if (!(modulus < COMPLEX_INFINITY)) {
+        return vec4f(1.0, 1.0, 1.0, 1.0);
+    }
+
+    let hue = fract(c_arg(w) / (2.0 * PI) + 1.0);
+    var brightness = params.hsv_brightness;
+    if ((params.domain_flags & DOMAIN_MODULUS_CONTOURS) > 0 && modulus > 0.0) {
+        let level = log(modulus) / log(params.domain_contour_base);
+        brightness *= 0.6 + 0.4 * fract(level);
+    }
+    var colors = hsv_rgb(vec3f(hue, params.hsv_saturation, brightness));
+
+    if ((params.domain_flags & DOMAIN_GRID) > 0) {
+        let distance = abs(fract(grid - 0.5) - 0.5) / max(grid_width, vec2f(1e-6));
+        let line = 1.0 - clamp(min(distance.x, distance.y), 0.0, 1.0);
+        colors = mix(colors, vec3f(0.0), 0.7 * line);
+    }
+    return vec4f(colors, 1.0);
+}
+
+// Lyapunov exponent of the logistic map x <- r x (1 - x), where r alternates between
+// r_a and r_b following the A/B sequence
+fn lyapunov_exponent(r_a: f32, r_b: f32) -> f32 {
+    var x = 0.5;
+    var n: u32 = 0;
+    for (var i: u32 = 0; i < params.lyapunov_warmup; i++) {
+        let r = lyapunov_r(n, r_a, r_b);
+        x = r * x * (1.0 - x);
+        n = (n + 1) % params.lyapunov_sequence_length;
+    }
+
+    var total = 0.0;
+    for (var i: u32 = 0; i < params.lyapunov_iterations; i++) {
+        let r = lyapunov_r(n, r_a, r_b);
+        x = r * x * (1.0 - x);
+        // The derivative of the map; clamped to keep log finite at superstable points
+        total += log(max(abs(r * (1.0 - 2.0 * x)), 1e-30));
+        n = (n + 1) % params.lyapunov_sequence_length;
+    }
+    return total / f32(max(params.lyapunov_iterations, 1u));
+}
+
+fn lyapunov_r(n: u32, r_a: f32, r_b: f32) -> f32 {
+    if (((params.lyapunov_sequence >> n) & 1) == 1) {
+        return r_b;
+    }
+    return r_a;
+}
+
+// Stable regions (negative exponent) are gold, chaotic ones (positive exponent) fade from blue to black
+fn lyapunov_color(c: Complex) -> vec4f {
+    let exponent = lyapunov_exponent(c.re, c.im);
+
+    if (exponent <= 0.0) {
+        let t = 1.0 - exp(exponent);
+        return vec4f(vec3f(1.0, 0.8, 0.1) * t, 1.0);
+    }
+
+    let t = exp(-exponent);
+    return vec4f(vec3f(0.1, 0.25, 0.8) * t, 1.0);
+}
+
+// Iterations (-1 inside), smooth iterations, distance estimate and the real and imaginary part of z
+const RAW_DATA_CHANNELS: u32 = 5;
+
+// Raw escape time values of the pixels of a tile, for export instead of colors
+@compute @workgroup_size(8, 8)
+fn cs_raw_data(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= params.raw_data_width || id.y >= params.raw_data_height) {
+        return;
+    }
+
+    // Same pixel centers as image_uv
+    let pixel = vec2f(params.image_rect.xy + id.xy) + 0.5;
+    let size = vec2f(params.image_rect.zw);
+    let c = plane_point(vec2f(pixel.x / size.x, 1.0 - pixel.y / size.y));
+    let result = escape_time(c, params.max_iter);
+
+    // Inside points have no distance, -1 marks escaped points without an estimate
+    var smooth_iterations = f32(result.iterations);
+    var distance = 0.0;
+    if (result.iterations >= 0 && !result.converged) {
+        let modulus = norm_sqrt(result.z);
+        let radius = sqrt(params.escape_threshold);
+        // Fraction of the last step, continuous across the bands of equal iteration counts
+        if (radius > 1.0 && modulus > radius && params.pow > 1.0) {
+            smooth_iterations += 1.0 - log(log(modulus) / log(radius)) / log(params.pow);
         }
-        if (abs(c.re) <= scaled_epsilon || abs(c.im) <= scaled_epsilon) {
-            return vec4f(255, 255, 255, 0);
+        distance = -1.0;
+        if (result.derivative > 0.0 && modulus > 1.0) {
+            distance = 0.5 * modulus * log(modulus) / result.derivative;
         }
     }
 
-    let time = escape_time(c, params.max_iter);
+    let index = (id.y * params.raw_data_width + id.x) * RAW_DATA_CHANNELS;
+    raw_data[index] = f32(result.iterations);
+    raw_data[index + 1] = smooth_iterations;
+    raw_data[index + 2] = distance;
+    raw_data[index + 3] = result.z.re;
+    raw_data[index + 4] = result.z.im;
+}
+
+// Moves a color of the [0..=1] palette by the palette offset, the palette wraps around
+fn shift_palette(color: f32) -> f32 {
+    if (params.palette_offset == 0.0) {
+        return color;
+    }
+    return fract(color + params.palette_offset);
+}
+
+fn escape_time_color(c: Complex) -> vec4f {
+    let result = escape_time(c, params.max_iter);
+    let time = result.iterations;
+
+    // Cycles found by the periodicity check are known to be interior, more iterations would not help
+    if (params.auto_iterations > 0 && time == -1 && result.period == 0) {
+        atomicAdd(&limit_hits, 1u);
+    }
 
     if (time == -1) {
+        if ((params.periodicity_flags & PERIODICITY_INTERIOR_COLORING) > 0 && result.period > 0) {
+            // Golden ratio steps keep the hues of neighbouring periods apart
+            let hue = fract(f32(result.period) * 0.618034);
+            if ((params.color_scheme & HSV_SCHEME) > 0) {
+                let colors = vec3f(hue, params.hsv_saturation, 0.5 * params.hsv_brightness);
+                return vec4f(hsv_rgb(colors), 1.0);
+            }
+            return vec4f(0.5 * vec3f(hue, params.rgb_green, params.rgb_blue), 1.0);
+        }
         return vec4(0.0, 0.0, 0.0, 1.0);
     }
 
+    if (result.root >= 0) {
+        let hue = f32(result.root) / f32(params.newton_root_count);
+        let speed = log(f32(time) + 1) / log(f32(params.max_iter) + 1);
+        let colors = vec3f(hue, params.hsv_saturation, params.hsv_brightness * (1.0 - speed));
+        return vec4f(hsv_rgb(colors), 1.0);
+    }
+
     if ((params.color_scheme & HSV_SCHEME) > 0) {
-        let color = log(f32(time) + 1) / log(f32(params.max_iter) + 1);
+        var color = log(f32(time) + 1) / log(f32(params.max_iter) + 1);
+        // Mirrors the palette so that basins of convergence stand out from escaping areas
+        if (result.converged) {
+            color = 1.0 - color;
+        }
+        color = shift_palette(color);
         let colors = vec3f(color, params.hsv_saturation, params.hsv_brightness);
         return vec4f(hsv_rgb(colors), 1.0);
     }
     else {
-        let color = f32(time) / f32(params.max_iter);
+        var color = f32(time) / f32(params.max_iter);
+        if (result.converged) {
+            color = 1.0 - color;
+        }
+        color = shift_palette(color);
         let colors = vec3f(color, params.rgb_green, params.rgb_blue);
         return vec4f(colors, 1.0);
     }
-}
\ No newline at end of file
+}
+
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1)
+fn random_f32(state: ptr<function, u32>) -> f32 {
+    *state = pcg_hash(*state);
+    return f32(*state >> 8) / 16777216.0;
+}
+
+// Half the side of the square of sampled points, centered at the origin
+fn buddhabrot_radius() -> f32 {
+    return clamp(sqrt(params.escape_threshold), 2.0, 4.0);
+}
+
+fn buddhabrot_step(z: Complex, k: Complex) -> Complex {
+    return hybrid_step(z, k, params.fractal_type);
+}
+
+// Returns the iteration at which the orbit of the sample escaped, -1 if it did not
+fn buddhabrot_escape(z0: Complex, k: Complex, limit: u32) -> i32 {
+    var z = z0;
+    for (var i: u32 = 0; i < limit; i++) {
+        if (is_escaped(z)) {
+            return i32(i);
+        }
+        z = buddhabrot_step(z, k);
+    }
+    return -1;
+}
+
+// Starting value and constant of the orbit of a sampled point, following the fractal mode
+fn buddhabrot_orbit_start(sample: Complex) -> array<Complex, 2> {
+    let constant = Complex(params.initial_value.x, params.initial_value.y);
+    if ((params.fractal_type & JULIA_FRACTAL_TYPE) > 0) {
+        return array<Complex, 2>(sample, constant);
+    }
+    return array<Complex, 2>(constant, sample);
+}
+
+// Index of the pixel showing the point, -1 outside of the view
+fn buddhabrot_pixel(z: Complex) -> i32 {
+    let u = (z.re - params.center.x) * params.zoom / 3.0 + 0.5;
+    let v = 0.5 - (z.im - params.center.y) * params.zoom / 2.0;
+    if (u < 0.0 || u >= 1.0 || v < 0.0 || v >= 1.0) {
+        return -1;
+    }
+    let x = min(u32(u * f32(params.buddhabrot_width)), params.buddhabrot_width - 1);
+    let y = min(u32(v * f32(params.buddhabrot_height)), params.buddhabrot_height - 1);
+    return i32(y * params.buddhabrot_width + x);
+}
+
+fn buddhabrot_max_limit() -> u32 {
+    let limits = params.buddhabrot_limits;
+    if ((params.buddhabrot_flags & BUDDHABROT_NEBULA) > 0) {
+        return max(limits.x, max(limits.y, limits.z));
+    }
+    return limits.x;
+}
+
+// Marks grid cells whose corners and center disagree on escaping, i.e. cells on the boundary.
+// Orbits that contribute the most to the image start there
+@compute @workgroup_size(64)
+fn cs_buddhabrot_cells(@builtin(global_invocation_id) id: vec3u) {
+    if (id.x >= BUDDHABROT_CELLS * BUDDHABROT_CELLS) {
+        return;
+    }
+
+    let radius = buddhabrot_radius();
+    let cell_size = 2.0 * radius / f32(BUDDHABROT_CELLS);
+    let origin = Complex(
+        -radius + f32(id.x % BUDDHABROT_CELLS) * cell_size,
+        -radius + f32(id.x / BUDDHABROT_CELLS) * cell_size
+    );
+    let offsets = array<vec2f, 5>(
+        vec2f(0.0, 0.0),
+        vec2f(1.0, 0.0),
+        vec2f(0.0, 1.0),
+        vec2f(1.0, 1.0),
+        vec2f(0.5, 0.5)
+    );
+
+    let limit = buddhabrot_max_limit();
+    var escaped: u32 = 0;
+    for (var i = 0; i < 5; i++) {
+        let sample = sum(origin, Complex(offsets[i].x * cell_size, offsets[i].y * cell_size));
+        let start = buddhabrot_orbit_start(sample);
+        if (buddhabrot_escape(start[0], start[1], limit) >= 0) {
+            escaped++;
+        }
+    }
+
+    if (escaped > 0 && escaped < 5) {
+        let index = atomicAdd(&buddhabrot_state.cell_count, 1u);
+        buddhabrot_state.cells[index] = id.x;
+        buddhabrot_state.boundary[id.x] = 1u;
+    }
+}
+
+// Traces the orbit of one random point and adds every visited pixel to the density.
+// Each channel has its own iteration limit, which gives the Nebulabrot coloring
+@compute @workgroup_size(64)
+fn cs_buddhabrot_sample(@builtin(global_invocation_id) id: vec3u) {
+    var rng = pcg_hash(id.x ^ pcg_hash(params.buddhabrot_seed));
+    let radius = buddhabrot_radius();
+    let cell_size = 2.0 * radius / f32(BUDDHABROT_CELLS);
+
+    var sample: Complex;
+    var sample_cell: u32;
+    let cell_count = atomicLoad(&buddhabrot_state.cell_count);
+    var importance = 0.0;
+    if ((params.buddhabrot_flags & BUDDHABROT_IMPORTANCE) > 0 && cell_count > 0) {
+        importance = params.buddhabrot_importance;
+    }
+    if (random_f32(&rng) < importance) {
+        let cell = buddhabrot_state.cells[min(u32(random_f32(&rng) * f32(cell_count)), cell_count - 1)];
+        sample = Complex(
+            -radius + (f32(cell % BUDDHABROT_CELLS) + random_f32(&rng)) * cell_size,
+            -radius + (f32(cell / BUDDHABROT_CELLS) + random_f32(&rng)) * cell_size
+        );
+        sample_cell = cell;
+    } else {
+        sample = Complex((random_f32(&rng) * 2.0 - 1.0) * radius, (random_f32(&rng) * 2.0 - 1.0) * radius);
+        let cell_x = min(u32((sample.re + radius) / cell_size), BUDDHABROT_CELLS - 1);
+        let cell_y = min(u32((sample.im + radius) / cell_size), BUDDHABROT_CELLS - 1);
+        sample_cell = cell_y * BUDDHABROT_CELLS + cell_x;
+    }
+
+    // Samples come uniformly from the square and, with the chance of the importance, uniformly
+    // from the boundary cells. Dividing by the density of that mixture relative to the uniform
+    // one keeps the image the same as without the importance sampling, only less noisy
+    var relative_density = 1.0 - importance;
+    if (importance > 0.0 && buddhabrot_state.boundary[sample_cell] > 0) {
+        relative_density += importance * f32(BUDDHABROT_CELLS * BUDDHABROT_CELLS) / f32(cell_count);
+    }
+    // Rounded up or down at random, so the fixed point weight is right on average
+    let scaled_weight = BUDDHABROT_WEIGHT_SCALE / relative_density;
+    var weight = u32(scaled_weight);
+    if (random_f32(&rng) < fract(scaled_weight)) {
+        weight++;
+    }
+    if (weight == 0) {
+        return;
+    }
+
+    let start = buddhabrot_orbit_start(sample);
+    let escaped_at = buddhabrot_escape(start[0], start[1], buddhabrot_max_limit());
+    let anti = (params.buddhabrot_flags & BUDDHABROT_ANTI) > 0;
+
+    var channels: u32 = 1;
+    if ((params.buddhabrot_flags & BUDDHABROT_NEBULA) > 0) {
+        channels = 3;
+    }
+
+    // Buddhabrot plots orbits that escape within the channel limit, anti-Buddhabrot the rest
+    var plotted = vec3<bool>(false, false, false);
+    var trace_length: u32 = 0;
+    for (var k: u32 = 0; k < channels; k++) {
+        let limit = params.buddhabrot_limits[k];
+        let escaped = escaped_at >= 0 && u32(escaped_at) < limit;
+        plotted[k] = escaped != anti;
+        if (plotted[k]) {
+            if (anti) {
+                trace_length = max(trace_length, limit);
+            } else {
+                trace_length = max(trace_length, u32(escaped_at));
+            }
+        }
+    }
+
+    var z = start[0];
+    for (var i: u32 = 0; i < trace_length; i++) {
+        z = buddhabrot_step(z, start[1]);
+        let pixel = buddhabrot_pixel(z);
+        if (pixel < 0) {
+            continue;
+        }
+        for (var k: u32 = 0; k < channels; k++) {
+            if (plotted[k] && (!anti || i < params.buddhabrot_limits[k])) {
+                atomicAdd(&density[u32(pixel) * 3 + k], weight);
+            }
+        }
+    }
+}
+
+var<workgroup> workgroup_max_density: array<atomic<u32>, 3>;
+
+@compute @workgroup_size(256)
+fn cs_buddhabrot_max(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_index) local_index: u32
+) {
+    if (id.x < params.buddhabrot_width * params.buddhabrot_height) {
+        for (var k: u32 = 0; k < 3; k++) {
+            atomicMax(&workgroup_max_density[k], atomicLoad(&density[id.x * 3 + k]));
+        }
+    }
+    workgroupBarrier();
+
+    if (local_index == 0) {
+        for (var k: u32 = 0; k < 3; k++) {
+            atomicMax(&buddhabrot_state.max_density[k], atomicLoad(&workgroup_max_density[k]));
+        }
+    }
+}
+
+fn buddhabrot_channel(pixel: u32, k: u32) -> f32 {
+    let max_density = max(atomicLoad(&buddhabrot_state.max_density[k]), 1u);
+    let value = f32(atomicLoad(&density[pixel * 3 + k])) / f32(max_density);
+    return pow(value, params.buddhabrot_gamma);
+}
+
+// Tone-maps the accumulated density relative to the densest pixel
+fn buddhabrot_color(uv: vec2f) -> vec4f {
+    let x = min(u32(uv.x * f32(params.buddhabrot_width)), params.buddhabrot_width - 1);
+    let y = min(u32((1.0 - uv.y) * f32(params.buddhabrot_height)), params.buddhabrot_height - 1);
+    let pixel = y * params.buddhabrot_width + x;
+
+    if ((params.buddhabrot_flags & BUDDHABROT_NEBULA) > 0) {
+        let colors = vec3f(
+            buddhabrot_channel(pixel, 0),
+            buddhabrot_channel(pixel, 1),
+            buddhabrot_channel(pixel, 2)
+        );
+        return vec4f(colors, 1.0);
+    }
+
+    let value = buddhabrot_channel(pixel, 0);
+    if ((params.color_scheme & HSV_SCHEME) > 0) {
+        let colors = vec3f(value, params.hsv_saturation, params.hsv_brightness * value);
+        return vec4f(hsv_rgb(colors), 1.0);
+    }
+    return vec4f(value, value * params.rgb_green, value * params.rgb_blue, 1.0);
+}
+
+// Both fractals fit inside this sphere around the origin, rays that miss it are background
+const RAYMARCH_BOUNDING_RADIUS: f32 = 2.0;
+// Orbits farther than this from the origin are considered escaped
+const RAYMARCH_BAILOUT: f32 = 4.0;
+const SHADOW_STEPS: u32 = 48;
+// Higher values give sharper shadows
+const SHADOW_SOFTNESS: f32 = 12.0;
+const AMBIENT_OCCLUSION_SAMPLES: u32 = 5;
+
+struct DistanceEstimate {
+    distance: f32,
+    trap: f32, // smallest squared distance of the orbit to the origin
+    iterations: u32,
+}
+
+// Power-n Mandelbulb, the spherical coordinates analogue of z^n + c with c = p
+fn mandelbulb_distance(p: vec3f) -> DistanceEstimate {
+    let power = params.pow;
+    var z = p;
+    var dr = 1.0;
+    var r = length(z);
+    var trap = dot(z, z);
+    var i: u32 = 0;
+    while (i < params.raymarch_iterations && r < RAYMARCH_BAILOUT) {
+        let theta = acos(clamp(z.z / max(r, 1e-12), -1.0, 1.0)) * power;
+        let phi = atan2(z.y, z.x) * power;
+        // Running derivative of |z| for the distance estimate
+        dr = pow(r, power - 1.0) * abs(power) * dr + 1.0;
+        z = pow(r, power) * vec3f(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta)) + p;
+        r = length(z);
+        trap = min(trap, dot(z, z));
+        i++;
+    }
+    return DistanceEstimate(0.5 * log(max(r, 1e-12)) * r / dr, trap, i);
+}
+
+fn quaternion_mul(a: vec4f, b: vec4f) -> vec4f {
+    return vec4f(a.x * b.x - dot(a.yzw, b.yzw), a.x * b.yzw + b.x * a.yzw + cross(a.yzw, b.yzw));
+}
+
+fn quaternion_sqr(q: vec4f) -> vec4f {
+    return vec4f(q.x * q.x - dot(q.yzw, q.yzw), 2.0 * q.x * q.yzw);
+}
+
+// Julia set of q^2 + c over quaternions, sliced at w = 0
+fn quaternion_julia_distance(p: vec3f) -> DistanceEstimate {
+    var q = vec4f(p, 0.0);
+    var dq = vec4f(1.0, 0.0, 0.0, 0.0);
+    var trap = dot(q, q);
+    var i: u32 = 0;
+    while (i < params.raymarch_iterations && dot(q, q) < RAYMARCH_BAILOUT * RAYMARCH_BAILOUT) {
+        dq = 2.0 * quaternion_mul(q, dq);
+        q = quaternion_sqr(q) + params.quaternion_c;
+        trap = min(trap, dot(q, q));
+        i++;
+    }
+    let r = length(q);
+    return DistanceEstimate(0.5 * r * log(max(r, 1e-12)) / max(length(dq), 1e-12), trap, i);
+}
+
+fn fractal_distance(p: vec3f) -> DistanceEstimate {
+    if ((params.fractal_3d & QUATERNION_JULIA_FRACTAL_3D) > 0) {
+        return quaternion_julia_distance(p);
+    }
+    return mandelbulb_distance(p);
+}
+
+// Sphere tracing: steps along the ray by the estimated distance, which never overshoots the surface.
+// Returns the distance to the hit along the ray, -1 on a miss
+fn raymarch(origin: vec3f, direction: vec3f) -> f32 {
+    let b = dot(origin, direction);
+    let h = b * b - dot(origin, origin) + RAYMARCH_BOUNDING_RADIUS * RAYMARCH_BOUNDING_RADIUS;
+    if (h < 0.0) {
+        return -1.0;
+    }
+
+    var t = max(-b - sqrt(h), 0.0);
+    let t_max = -b + sqrt(h);
+    for (var i: u32 = 0; i < params.raymarch_steps; i++) {
+        let distance = fractal_distance(origin + direction * t).distance;
+        if (distance < surface_epsilon(t)) {
+            return t;
+        }
+        t += distance;
+        if (t > t_max) {
+            return -1.0;
+        }
+    }
+    return -1.0;
+}
+
+// Grows with the distance to keep the detail roughly pixel sized
+fn surface_epsilon(t: f32) -> f32 {
+    return max(0.0005 * t, 1e-5);
+}
+
+fn fractal_normal(p: vec3f, h: f32) -> vec3f {
+    let k = vec2f(1.0, -1.0);
+    return normalize(
+        k.xyy * fractal_distance(p + k.xyy * h).distance
+            + k.yyx * fractal_distance(p + k.yyx * h).distance
+            + k.yxy * fractal_distance(p + k.yxy * h).distance
+            + k.xxx * fractal_distance(p + k.xxx * h).distance
+    );
+}
+
+// Penumbra from how closely the ray towards the light passes by the fractal
+fn soft_shadow(origin: vec3f, direction: vec3f) -> f32 {
+    var result = 1.0;
+    var t = 0.01;
+    for (var i: u32 = 0; i < SHADOW_STEPS; i++) {
+        let distance = fractal_distance(origin + direction * t).distance;
+        result = min(result, SHADOW_SOFTNESS * distance / t);
+        t += clamp(distance, 0.005, 0.2);
+        if (result < 0.001 || t > 2.0 * RAYMARCH_BOUNDING_RADIUS) {
+            break;
+        }
+    }
+    return clamp(result, 0.0, 1.0);
+}
+
+// Compares the distance to the fractal at a few points along the normal with the distance travelled
+fn ambient_occlusion(p: vec3f, normal: vec3f) -> f32 {
+    var occlusion = 0.0;
+    var weight = 1.0;
+    for (var i: u32 = 1; i <= AMBIENT_OCCLUSION_SAMPLES; i++) {
+        let h = 0.01 + 0.05 * f32(i);
+        occlusion += (h - fractal_distance(p + normal * h).distance) * weight;
+        weight *= 0.6;
+    }
+    return clamp(1.0 - 2.0 * occlusion, 0.0, 1.0);
+}
+
+fn raymarch_surface_color(estimate: DistanceEstimate) -> vec3f {
+    var value: f32;
+    if ((params.raymarch_flags & RAYMARCH_ORBIT_TRAP) > 0) {
+        value = clamp(sqrt(estimate.trap), 0.0, 1.0);
+    } else {
+        value = f32(estimate.iterations) / f32(max(params.raymarch_iterations, 1u));
+    }
+
+    if ((params.color_scheme & HSV_SCHEME) > 0) {
+        return hsv_rgb(vec3f(value, params.hsv_saturation, params.hsv_brightness));
+    }
+    return vec3f(value, params.rgb_green, params.rgb_blue);
+}
+
+// Orbit camera looking at the origin, the aspect ratio matches the 3:2 view of the plane
+fn raymarch_color(uv: vec2f) -> vec4f {
+    let to_camera = vec3f(
+        cos(params.camera_pitch) * sin(params.camera_yaw),
+        sin(params.camera_pitch),
+        cos(params.camera_pitch) * cos(params.camera_yaw)
+    );
+    let origin = to_camera * params.camera_distance;
+    let forward = -to_camera;
+    let right = normalize(cross(forward, vec3f(0.0, 1.0, 0.0)));
+    let up = cross(right, forward);
+
+    let half_height = tan(0.5 * params.camera_fov);
+    let x = (uv.x - 0.5) * 2.0 * half_height * 1.5;
+    let y = (uv.y - 0.5) * 2.0 * half_height;
+    let direction = normalize(forward + right * x + up * y);
+
+    let t = raymarch(origin, direction);
+    if (t < 0.0) {
+        return vec4f(vec3f(0.02, 0.02, 0.05) + vec3f(0.08, 0.08, 0.12) * uv.y, 1.0);
+    }
+
+    let p = origin + direction * t;
+    let epsilon = surface_epsilon(t);
+    let normal = fractal_normal(p, epsilon);
+    let light = normalize(vec3f(0.6, 0.8, 0.4));
+
+    var shadow = 1.0;
+    if ((params.raymarch_flags & RAYMARCH_SOFT_SHADOWS) > 0) {
+        shadow = soft_shadow(p + normal * 2.0 * epsilon, light);
+    }
+    var occlusion = 1.0;
+    if ((params.raymarch_flags & RAYMARCH_AMBIENT_OCCLUSION) > 0) {
+        occlusion = ambient_occlusion(p, normal);
+    }
+
+    let diffuse = max(dot(normal, light), 0.0) * shadow;
+    let specular = pow(max(dot(reflect(direction, normal), light), 0.0), 32.0) * shadow;
+    let base = raymarch_surface_color(fractal_distance(p));
+    let colors = base * (0.2 * occlusion + 0.8 * diffuse) + vec3f(0.3 * specular);
+    return vec4f(colors, 1.0);
+}
@@ -0,0 +1,214 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+const ROOT_FINDER_MAX_ITER: usize = 500;
+const ROOT_FINDER_TOLERANCE: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let d = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+impl From<[f32; 2]> for Complex {
+    fn from(value: [f32; 2]) -> Self {
+        Self::new(value[0] as f64, value[1] as f64)
+    }
+}
+
+impl From<Complex> for [f32; 2] {
+    fn from(value: Complex) -> Self {
+        [value.re as f32, value.im as f32]
+    }
+}
+
+/// Expands `(z - r_0)(z - r_1)...` into coefficients ordered from the highest degree
+pub fn coefficients_from_roots(roots: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let mut coefficients = vec![Complex::new(1.0, 0.0)];
+
+    for &root in roots {
+        let root = Complex::from(root);
+        let mut next = coefficients.clone();
+        next.push(Complex::new(0.0, 0.0));
+        for (i, &coefficient) in coefficients.iter().enumerate() {
+            next[i + 1] = next[i + 1] - coefficient * root;
+        }
+        coefficients = next;
+    }
+
+    coefficients.into_iter().map(Into::into).collect()
+}
+
+/// Finds all roots of a polynomial with coefficients ordered from the highest degree
+/// using the Durand-Kerner method. Leading zero coefficients are ignored
+pub fn roots_from_coefficients(coefficients: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let coefficients = coefficients
+        .iter()
+        .map(|&c| Complex::from(c))
+        .skip_while(|c| c.norm_sqr() == 0.0)
+        .collect::<Vec<_>>();
+
+    let Some((&leading, rest)) = coefficients.split_first() else {
+        return vec![];
+    };
+    let monic = rest.iter().map(|&c| c / leading).collect::<Vec<_>>();
+    let degree = monic.len();
+
+    let evaluate = |z: Complex| {
+        monic
+            .iter()
+            .fold(Complex::new(1.0, 0.0), |acc, &c| acc * z + c)
+    };
+
+    // Standard starting points: powers of a number that is neither real nor a root of unity
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots = Vec::with_capacity(degree);
+    let mut current = Complex::new(1.0, 0.0);
+    for _ in 0..degree {
+        roots.push(current);
+        current = current * seed;
+    }
+
+    for _ in 0..ROOT_FINDER_MAX_ITER {
+        let mut max_change: f64 = 0.0;
+        for i in 0..degree {
+            let mut denominator = Complex::new(1.0, 0.0);
+            for j in 0..degree {
+                if i != j {
+                    denominator = denominator * (roots[i] - roots[j]);
+                }
+            }
+            let change = evaluate(roots[i]) / denominator;
+            if change.re.is_finite() && change.im.is_finite() {
+                roots[i] = roots[i] - change;
+                max_change = max_change.max(change.norm_sqr());
+            }
+        }
+        if max_change < ROOT_FINDER_TOLERANCE * ROOT_FINDER_TOLERANCE {
+            break;
+        }
+    }
+
+    roots.into_iter().map(Into::into).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every root has a distinct found root within `tolerance`, in any order
+    fn assert_same_roots(found: &[[f32; 2]], expected: &[[f32; 2]], tolerance: f32) {
+        assert_eq!(found.len(), expected.len(), "{found:?}");
+        let mut unmatched = found.to_vec();
+        for root in expected {
+            let distance = |r: &[f32; 2]| (r[0] - root[0]).hypot(r[1] - root[1]);
+            let (index, closest) = unmatched
+                .iter()
+                .enumerate()
+                .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+                .unwrap();
+            assert!(
+                distance(closest) < tolerance,
+                "{root:?} not found in {found:?}"
+            );
+            unmatched.remove(index);
+        }
+    }
+
+    #[test]
+    fn expands_roots_of_unity() {
+        let roots = [
+            [1.0, 0.0],
+            [-0.5, 3f32.sqrt() / 2.0],
+            [-0.5, -3f32.sqrt() / 2.0],
+        ];
+        let coefficients = coefficients_from_roots(&roots);
+        let expected = [[1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [-1.0, 0.0]];
+        for (c, e) in coefficients.iter().zip(expected) {
+            assert!(
+                (c[0] - e[0]).abs() < 1e-6 && (c[1] - e[1]).abs() < 1e-6,
+                "{c:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn roots_survive_the_round_trip() {
+        let roots = [
+            [1.0, 0.0],
+            [-0.3, 1.2],
+            [0.5, -0.7],
+            [-2.0, 0.1],
+            [0.0, 0.0],
+        ];
+        let found = roots_from_coefficients(&coefficients_from_roots(&roots));
+        assert_same_roots(&found, &roots, 1e-4);
+    }
+
+    #[test]
+    fn finds_repeated_roots() {
+        // Durand-Kerner converges only linearly to a multiple root, so it is less precise
+        let roots = [[1.0, 0.0], [1.0, 0.0], [-1.0, 0.5]];
+        let found = roots_from_coefficients(&coefficients_from_roots(&roots));
+        assert_same_roots(&found, &roots, 1e-2);
+    }
+
+    #[test]
+    fn ignores_leading_zeros() {
+        let roots = [[2.0, 0.0], [0.0, -1.0]];
+        let mut coefficients = vec![[0.0, 0.0], [0.0, 0.0]];
+        coefficients.extend(coefficients_from_roots(&roots));
+        assert_same_roots(&roots_from_coefficients(&coefficients), &roots, 1e-4);
+
+        assert!(roots_from_coefficients(&[[0.0, 0.0], [0.0, 0.0]]).is_empty());
+        assert!(roots_from_coefficients(&[[3.0, 0.0]]).is_empty());
+    }
+}
@@ -1,28 +1,87 @@
+use crate::formula::MAX_FORMULA_PARAMS;
 use bitflags::bitflags;
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
+/// Highest degree of the polynomial used by [`FractalType::NEWTON`]
+pub const MAX_NEWTON_ROOTS: usize = 16;
+
+/// Longest sequence of formulas used by [`FractalType::HYBRID`]
+pub const MAX_HYBRID_STEPS: usize = 8;
+
+/// Longest A/B sequence of [`RenderMode::LYAPUNOV`], one bit per letter
+pub const MAX_LYAPUNOV_SEQUENCE: usize = 32;
+
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct Uniforms {
-    pub center: [f32; 4],        // 2 points, 16
-    pub initial_value: [f32; 4], // 2 points, 16
-    pub max_iter: u32,           // 4
-    pub zoom: f32,               // 4
-    pub rgb_green: f32,          // 4
-    pub rgb_blue: f32,           // 4
-    pub color_scheme: u32,       // 4
-    pub hsv_saturation: f32,     // 4
-    pub hsv_brightness: f32,     // 4
-    pub show_axis: u32,          // 4
-    pub escape_threshold: f32,   // 4
-    pub fractal_type: u32,       // 4
-    pub pow: u32,                // 4
+    pub center: [f32; 4],                                   // 2 points, 16
+    pub initial_value: [f32; 4],                            // 2 points, 16
+    pub max_iter: u32,                                      // 4
+    pub zoom: f32,                                          // 4
+    pub rgb_green: f32,                                     // 4
+    pub rgb_blue: f32,                                      // 4
+    pub color_scheme: u32,                                  // 4
+    pub hsv_saturation: f32,                                // 4
+    pub hsv_brightness: f32,                                // 4
+    pub show_axis: u32,                                     // 4
+    pub escape_threshold: f32,                              // 4
+    pub fractal_type: u32,                                  // 4
+    pub pow: f32,                                           // 4
+    pub newton_root_count: u32,                             // 4
+    pub newton_nova: u32,                                   // 4
+    pub newton_tolerance: f32,                              // 4
+    pub magnet_tolerance: f32,                              // 4
+    pub hybrid_step_count: u32,                             // 4
+    pub newton_relaxation: [f32; 4],                        // 2 points, 16
+    pub newton_roots: [[f32; 4]; MAX_NEWTON_ROOTS],         // 2 points each, 16 * MAX_NEWTON_ROOTS
+    pub phoenix_p: [f32; 4],                                // 2 points, 16
+    pub formula_params: [[f32; 4]; MAX_FORMULA_PARAMS / 2], // 2 parameters each, 16 * 4
+    pub hybrid_steps: [[u32; 4]; MAX_HYBRID_STEPS / 4],     // encoded by HybridStep::encode, 16 * 2
+    pub render_mode: u32,                                   // 4
+    pub lyapunov_sequence: u32,                             // 4
+    pub lyapunov_sequence_length: u32,                      // 4
+    pub lyapunov_warmup: u32,                               // 4
+    pub lyapunov_iterations: u32,                           // 4
+    pub buddhabrot_width: u32,                              // 4
+    pub buddhabrot_height: u32,                             // 4
+    pub buddhabrot_seed: u32,                               // 4
+    pub buddhabrot_flags: u32,                              // 4
+    pub buddhabrot_importance: f32,                         // 4
+    pub buddhabrot_gamma: f32,                              // 4
     pub pad: [u8; 4],
+    pub buddhabrot_limits: [u32; 4], // 3 channels, 16
+    pub quaternion_c: [f32; 4],      // 4 quaternion components, 16
+    pub camera_yaw: f32,             // 4
+    pub camera_pitch: f32,           // 4
+    pub camera_distance: f32,        // 4
+    pub camera_fov: f32,             // 4
+    pub fractal_3d: u32,             // 4
+    pub raymarch_flags: u32,         // 4
+    pub raymarch_steps: u32,         // 4
+    pub raymarch_iterations: u32,    // 4
+    pub domain_flags: u32,           // 4
+    pub domain_contour_base: f32,    // 4
+    pub domain_grid_spacing: f32,    // 4
+    pub bailout_test: u32,           // 4
+    pub convergence_epsilon: f32,    // 4
+    pub periodicity_flags: u32,      // 4
+    pub periodicity_epsilon: f32,    // 4
+    pub cardioid_check: u32,         // 4
+    pub auto_iterations: u32,        // 4
+    pub pad_2: [u8; 12],
+    /// Origin of the render target in the image and the image size, zero for the whole image
+    pub image_rect: [u32; 4], // 16
+    /// Size of the tile of the raw data compute pass, zero otherwise
+    pub raw_data_width: u32, // 4
+    pub raw_data_height: u32, // 4
+    pub palette_offset: f32,  // 4
+    pub pad_3: [u8; 4],
 }
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct FractalColorScheme: u32 {
         const RGB = 1;
         const HSV = 2;
@@ -30,10 +89,166 @@ bitflags! {
 }
 
 bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct RenderMode: u32 {
+        const ESCAPE_TIME = 1;
+        const LYAPUNOV = 2;
+        const BUDDHABROT = 4;
+        const RAYMARCH_3D = 8;
+        const DOMAIN_COLORING = 16;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct DomainColoringFlags: u32 {
+        /// Brightness steps where |f(z)| crosses a power of the contour base
+        const MODULUS_CONTOURS = 1;
+        /// Images of the lines where Re f(z) or Im f(z) is a multiple of the grid spacing
+        const GRID = 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct Fractal3dType: u32 {
+        const MANDELBULB = 1;
+        const QUATERNION_JULIA = 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct RaymarchFlags: u32 {
+        const SOFT_SHADOWS = 1;
+        const AMBIENT_OCCLUSION = 2;
+        /// Color by the closest approach of the orbit to the origin instead of the iteration count
+        const ORBIT_TRAP = 4;
+    }
+}
+
+bitflags! {
+    /// Condition that stops the iteration, the radius R is the square root of the escape threshold
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct BailoutTest: u32 {
+        const NORM = 1;
+        const REAL = 2;
+        const IMAGINARY = 4;
+        const MANHATTAN = 8;
+        const MAX_NORM = 16;
+        const PRODUCT = 32;
+        /// Stops when successive values get closer than the convergence epsilon
+        const CONVERGENCE = 64;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct PeriodicityFlags: u32 {
+        /// Stops interior orbits once they return close to an earlier point
+        const CHECK = 1;
+        /// Colors interior points by the period of the detected cycle instead of black
+        const INTERIOR_COLORING = 2;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub struct BuddhabrotFlags: u32 {
+        const ANTI = 1;
+        const NEBULA = 2;
+        const IMPORTANCE = 4;
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
     pub struct FractalType: u32 {
         const MANDELBROT = 1;
         const JULIA = 2;
+        const BURNING_SHIP = 1 << 2;
+        const TRICORN = 1 << 3;
+        const CELTIC = 1 << 4;
+        const BUFFALO = 1 << 5;
+        const PERPENDICULAR_MANDELBROT = 1 << 6;
+        const PERPENDICULAR_BURNING_SHIP = 1 << 7;
+        const HEART = 1 << 8;
+        const NEWTON = 1 << 9;
+        const PHOENIX = 1 << 10;
+        const MAGNET_1 = 1 << 11;
+        const MAGNET_2 = 1 << 12;
+        const CUSTOM = 1 << 13;
+        const HYBRID = 1 << 14;
+    }
+}
+
+impl FractalType {
+    /// Flags that choose what varies over the plane: c (Mandelbrot) or z0 (Julia)
+    pub const MODES: Self = Self::MANDELBROT.union(Self::JULIA);
+
+    /// Formula variants, each combinable with any of [`FractalType::MODES`].
+    /// An empty variant means the classic `z^pow + c`
+    pub const VARIANTS: [Self; 13] = [
+        Self::BURNING_SHIP,
+        Self::TRICORN,
+        Self::CELTIC,
+        Self::BUFFALO,
+        Self::PERPENDICULAR_MANDELBROT,
+        Self::PERPENDICULAR_BURNING_SHIP,
+        Self::HEART,
+        Self::NEWTON,
+        Self::PHOENIX,
+        Self::MAGNET_1,
+        Self::MAGNET_2,
+        Self::CUSTOM,
+        Self::HYBRID,
+    ];
+
+    /// Variants that can be a step of [`FractalType::HYBRID`]
+    pub const HYBRID_STEPS: [Self; 9] = [
+        Self::empty(),
+        Self::BURNING_SHIP,
+        Self::TRICORN,
+        Self::CELTIC,
+        Self::BUFFALO,
+        Self::PERPENDICULAR_MANDELBROT,
+        Self::PERPENDICULAR_BURNING_SHIP,
+        Self::HEART,
+        Self::CUSTOM,
+    ];
+
+    pub fn mode(self) -> Self {
+        self.intersection(Self::MODES)
+    }
+
+    pub fn variant(self) -> Self {
+        self.difference(Self::MODES)
+    }
+
+    pub fn with_mode(self, mode: Self) -> Self {
+        self.variant().union(mode.mode())
+    }
+
+    pub fn with_variant(self, variant: Self) -> Self {
+        self.mode().union(variant.variant())
+    }
+}
+
+/// One formula of a hybrid sequence, applied `repeats` iterations in a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HybridStep {
+    pub fractal_type: FractalType,
+    pub repeats: u32,
+}
+
+impl HybridStep {
+    pub const MAX_REPEATS: u32 = u16::MAX as u32;
+
+    /// Packs the variant into the low 16 bits and the repeat count into the high 16 bits
+    pub fn encode(&self) -> u32 {
+        let variant = self.fractal_type.variant().bits() & 0xFFFF;
+        let repeats = self.repeats.clamp(1, Self::MAX_REPEATS);
+        variant | (repeats << 16)
     }
 }
 
@@ -47,6 +262,126 @@ impl Display for FractalType {
         if self.contains(Self::MANDELBROT) {
             parts.push("Мандельброт");
         }
+        if self.contains(Self::BURNING_SHIP) {
+            parts.push("Горящий корабль");
+        }
+        if self.contains(Self::TRICORN) {
+            parts.push("Трикорн");
+        }
+        if self.contains(Self::CELTIC) {
+            parts.push("Кельтский");
+        }
+        if self.contains(Self::BUFFALO) {
+            parts.push("Буйвол");
+        }
+        if self.contains(Self::PERPENDICULAR_MANDELBROT) {
+            parts.push("Перпендикулярный Мандельброт");
+        }
+        if self.contains(Self::PERPENDICULAR_BURNING_SHIP) {
+            parts.push("Перпендикулярный горящий корабль");
+        }
+        if self.contains(Self::HEART) {
+            parts.push("Сердце");
+        }
+        if self.contains(Self::NEWTON) {
+            parts.push("Ньютон");
+        }
+        if self.contains(Self::PHOENIX) {
+            parts.push("Феникс");
+        }
+        if self.contains(Self::MAGNET_1) {
+            parts.push("Магнит I");
+        }
+        if self.contains(Self::MAGNET_2) {
+            parts.push("Магнит II");
+        }
+        if self.contains(Self::CUSTOM) {
+            parts.push("Своя формула");
+        }
+        if self.contains(Self::HYBRID) {
+            parts.push("Гибрид");
+        }
+
+        if parts.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+impl Display for RenderMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if self.contains(Self::ESCAPE_TIME) {
+            parts.push("Время убегания");
+        }
+        if self.contains(Self::LYAPUNOV) {
+            parts.push("Ляпунов");
+        }
+        if self.contains(Self::BUDDHABROT) {
+            parts.push("Буддаброт");
+        }
+        if self.contains(Self::RAYMARCH_3D) {
+            parts.push("3D");
+        }
+        if self.contains(Self::DOMAIN_COLORING) {
+            parts.push("Раскраска f(z)");
+        }
+
+        if parts.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+impl Display for BailoutTest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if self.contains(Self::NORM) {
+            parts.push("|z|² > R²");
+        }
+        if self.contains(Self::REAL) {
+            parts.push("|Re z| > R");
+        }
+        if self.contains(Self::IMAGINARY) {
+            parts.push("|Im z| > R");
+        }
+        if self.contains(Self::MANHATTAN) {
+            parts.push("|Re z| + |Im z| > R");
+        }
+        if self.contains(Self::MAX_NORM) {
+            parts.push("max(|Re z|, |Im z|) > R");
+        }
+        if self.contains(Self::PRODUCT) {
+            parts.push("|Re z · Im z| > R²");
+        }
+        if self.contains(Self::CONVERGENCE) {
+            parts.push("|zₙ − zₙ₋₁| < ε");
+        }
+
+        if parts.is_empty() {
+            write!(f, "(none)")
+        } else {
+            write!(f, "{}", parts.join(" | "))
+        }
+    }
+}
+
+impl Display for Fractal3dType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+
+        if self.contains(Self::MANDELBULB) {
+            parts.push("Мандельбульб");
+        }
+        if self.contains(Self::QUATERNION_JULIA) {
+            parts.push("Кватернионная Джулиа");
+        }
 
         if parts.is_empty() {
             write!(f, "(none)")
@@ -55,6 +390,7 @@ impl Display for FractalType {
         }
     }
 }
+
 impl Display for FractalColorScheme {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut parts = vec![];
@@ -1,7 +1,59 @@
-use crate::uniforms::{FractalColorScheme, FractalType};
+use crate::animation::{Keyframe, LoopKind};
+use crate::formula::{DEFAULT_DOMAIN_FUNCTION, DEFAULT_FORMULA, MAX_FORMULA_PARAMS};
+use crate::uniforms::{
+    BailoutTest, BuddhabrotFlags, DomainColoringFlags, Fractal3dType, FractalColorScheme,
+    FractalType, HybridStep, MAX_HYBRID_STEPS, MAX_LYAPUNOV_SEQUENCE, MAX_NEWTON_ROOTS,
+    PeriodicityFlags, RaymarchFlags, RenderMode, Uniforms,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
 
+/// Width and height of the plane shown at zoom 1, see `plane_point` in the shader
+pub const PLANE_SIZE: [f32; 2] = [3.0, 2.0];
+
+pub const MAX_ITERATIONS: u32 = 10_000_000;
+
+/// Keeps the orbit camera from flipping over the poles
+pub const MAX_CAMERA_PITCH: f32 = 1.55;
+
+/// The classic ranges of r for the Lyapunov mode, where the A/B sequences show their structure
+pub const LYAPUNOV_RANGES: [[f32; 2]; 2] = [[2.0, 4.0], [2.0, 4.0]];
+
+/// Fields that do not change the image, left out of the parameters embedded into images
+const WINDOW_FIELDS: [&str; 21] = [
+    "auto_iterations",
+    "show_settings",
+    "show_bookmarks",
+    "animate_pow",
+    "pow_animation_from",
+    "pow_animation_to",
+    "pow_animation_speed",
+    "newton_show_roots",
+    "buddhabrot_samples_per_frame",
+    "screenshot_custom_size",
+    "screenshot_size",
+    "screenshot_supersampling",
+    "screenshot_folder",
+    "keyframes",
+    "animation_fps",
+    "animation_size",
+    "loop_kind",
+    "loop_orbit_radius",
+    "loop_duration",
+    "loop_frames",
+    "loop_size",
+];
+
+/// Everything the user can change, saved between sessions and as JSON parameter files.
+/// Missing fields keep their defaults
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UserSettings {
     pub max_iter: u32,
+    /// Derive `max_iter` from the zoom and the share of pixels that hit the limit
+    pub auto_iterations: bool,
     pub center_x: f32,
     pub center_y: f32,
     pub initial_value_x: f32,
@@ -12,17 +64,90 @@ pub struct UserSettings {
     pub rgb_blue: f32,
     pub hsv_saturation: f32,
     pub hsv_brightness: f32,
+    /// Shift of the escape time colors along the palette, wraps around at 1
+    pub palette_offset: f32,
     pub show_settings: bool,
+    pub show_bookmarks: bool,
     pub show_axis: bool,
-    pub pow: u32,
+    pub pow: f32,
+    pub animate_pow: bool,
+    pub pow_animation_from: f32,
+    pub pow_animation_to: f32,
+    pub pow_animation_speed: f32,
     pub escape_threshold: f32,
+    pub bailout_test: BailoutTest,
+    pub convergence_epsilon: f32,
+    pub periodicity_flags: PeriodicityFlags,
+    pub periodicity_epsilon: f32,
+    pub cardioid_check: bool,
     pub fractal_type: FractalType,
+    pub newton_roots: Vec<[f32; 2]>,
+    pub newton_relaxation_x: f32,
+    pub newton_relaxation_y: f32,
+    pub newton_nova: bool,
+    pub newton_tolerance: f32,
+    pub newton_show_roots: bool,
+    pub phoenix_p_x: f32,
+    pub phoenix_p_y: f32,
+    pub magnet_tolerance: f32,
+    pub formula: String,
+    pub formula_params: [[f32; 2]; MAX_FORMULA_PARAMS],
+    pub hybrid_steps: Vec<HybridStep>,
+    pub render_mode: RenderMode,
+    pub lyapunov_sequence: String,
+    pub lyapunov_warmup: u32,
+    pub lyapunov_iterations: u32,
+    pub buddhabrot_flags: BuddhabrotFlags,
+    pub buddhabrot_limits: [u32; 3],
+    pub buddhabrot_importance: f32,
+    pub buddhabrot_gamma: f32,
+    pub buddhabrot_samples_per_frame: u32,
+    pub fractal_3d: Fractal3dType,
+    pub quaternion_c: [f32; 4],
+    /// Orbit camera around the origin, angles in radians
+    pub camera_yaw: f32,
+    pub camera_pitch: f32,
+    pub camera_distance: f32,
+    /// Vertical field of view in degrees
+    pub camera_fov: f32,
+    pub raymarch_flags: RaymarchFlags,
+    pub raymarch_steps: u32,
+    pub raymarch_iterations: u32,
+    pub domain_function: String,
+    pub domain_flags: DomainColoringFlags,
+    pub domain_contour_base: f32,
+    pub domain_grid_spacing: f32,
+    /// Screenshots are rendered at the size of the view unless a resolution is chosen
+    pub screenshot_custom_size: bool,
+    pub screenshot_size: [u32; 2],
+    pub screenshot_supersampling: u32,
+    /// Folder of the quick screenshots, empty for the working directory
+    pub screenshot_folder: String,
+    /// Views of the animation in playback order
+    pub keyframes: Vec<Keyframe>,
+    pub animation_fps: u32,
+    /// Size of the exported frames in pixels
+    pub animation_size: [u32; 2],
+    pub loop_kind: LoopKind,
+    /// Radius of the circle of [`LoopKind::JULIA_ORBIT`]
+    pub loop_orbit_radius: f32,
+    /// Seconds of one pass of the loop
+    pub loop_duration: f32,
+    pub loop_frames: u32,
+    pub loop_size: [u32; 2],
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UserSettings {
     pub fn new() -> Self {
         Self {
             max_iter: 125,
+            auto_iterations: false,
             zoom: 0.75,
             center_x: -0.33,
             center_y: 0.0,
@@ -33,11 +158,393 @@ impl UserSettings {
             rgb_blue: 0.8,
             hsv_saturation: 1.0,
             hsv_brightness: 1.0,
+            palette_offset: 0.0,
             show_settings: true,
+            show_bookmarks: false,
             show_axis: false,
-            pow: 2,
+            pow: 2.0,
+            animate_pow: false,
+            pow_animation_from: 2.0,
+            pow_animation_to: 5.0,
+            pow_animation_speed: 0.25,
             escape_threshold: 4.0,
+            bailout_test: BailoutTest::NORM,
+            convergence_epsilon: 0.0001,
+            periodicity_flags: PeriodicityFlags::empty(),
+            periodicity_epsilon: 0.000_001,
+            cardioid_check: true,
             fractal_type: FractalType::MANDELBROT,
+            newton_roots: Self::default_newton_roots(),
+            newton_relaxation_x: 1.0,
+            newton_relaxation_y: 0.0,
+            newton_nova: false,
+            newton_tolerance: 0.001,
+            newton_show_roots: true,
+            phoenix_p_x: -0.5,
+            phoenix_p_y: 0.0,
+            magnet_tolerance: 0.001,
+            formula: DEFAULT_FORMULA.to_string(),
+            formula_params: [[0.0; 2]; MAX_FORMULA_PARAMS],
+            hybrid_steps: vec![
+                HybridStep {
+                    fractal_type: FractalType::empty(),
+                    repeats: 2,
+                },
+                HybridStep {
+                    fractal_type: FractalType::BURNING_SHIP,
+                    repeats: 1,
+                },
+            ],
+            render_mode: RenderMode::ESCAPE_TIME,
+            lyapunov_sequence: "AB".to_string(),
+            lyapunov_warmup: 50,
+            lyapunov_iterations: 200,
+            buddhabrot_flags: BuddhabrotFlags::IMPORTANCE,
+            buddhabrot_limits: [2_000, 200, 20],
+            buddhabrot_importance: 0.8,
+            buddhabrot_gamma: 0.5,
+            buddhabrot_samples_per_frame: 100_000,
+            fractal_3d: Fractal3dType::MANDELBULB,
+            quaternion_c: [-0.2, 0.6, 0.2, 0.2],
+            camera_yaw: 0.6,
+            camera_pitch: 0.4,
+            camera_distance: 2.8,
+            camera_fov: 45.0,
+            raymarch_flags: RaymarchFlags::SOFT_SHADOWS | RaymarchFlags::AMBIENT_OCCLUSION,
+            raymarch_steps: 192,
+            raymarch_iterations: 12,
+            domain_function: DEFAULT_DOMAIN_FUNCTION.to_string(),
+            domain_flags: DomainColoringFlags::MODULUS_CONTOURS,
+            domain_contour_base: 2.0,
+            domain_grid_spacing: 0.5,
+            screenshot_custom_size: false,
+            screenshot_size: [3600, 2400],
+            screenshot_supersampling: 1,
+            screenshot_folder: String::new(),
+            keyframes: vec![],
+            animation_fps: 30,
+            animation_size: [1200, 800],
+            loop_kind: LoopKind::PALETTE_CYCLE,
+            loop_orbit_radius: 0.05,
+            loop_duration: 3.0,
+            loop_frames: 36,
+            loop_size: [480, 320],
+        }
+    }
+
+    /// JSON of the fields that affect the rendered image
+    pub fn image_parameters(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("Settings are always serializable");
+        if let Value::Object(fields) = &mut value {
+            fields.retain(|name, _| !WINDOW_FIELDS.contains(&name.as_str()));
+        }
+        value.to_string()
+    }
+
+    /// Copy of the settings showing the view of [`Self::image_parameters`].
+    /// The window fields are kept and the automatic iterations are turned off,
+    /// so that the iteration limit stays as it was in the image. Values out of the ranges
+    /// of the settings window are an error
+    pub fn with_image_parameters(&self, parameters: &str) -> Result<Self, serde_json::Error> {
+        let Value::Object(parameters) = serde_json::from_str(parameters)? else {
+            return Err(serde::de::Error::custom("parameters must be a JSON object"));
+        };
+        let mut value = serde_json::to_value(self)?;
+        if let Value::Object(fields) = &mut value {
+            fields.extend(
+                parameters
+                    .into_iter()
+                    .filter(|(name, _)| !WINDOW_FIELDS.contains(&name.as_str())),
+            );
+        }
+
+        let mut settings: Self = serde_json::from_value(value)?;
+        settings.check_ranges().map_err(serde::de::Error::custom)?;
+        settings.auto_iterations = false;
+        Ok(settings)
+    }
+
+    /// Checks the image fields against the ranges of the settings window. Parameters of images,
+    /// bookmarks and locations come from outside and may hold values the shaders cannot handle
+    fn check_ranges(&self) -> Result<(), String> {
+        if !(self.zoom > 0.0 && self.zoom.is_finite()) {
+            return Err(format!("zoom = {} must be positive", self.zoom));
+        }
+        let points = [
+            [self.center_x, self.center_y],
+            [self.initial_value_x, self.initial_value_y],
+        ];
+        let finite = |values: &[[f32; 2]]| values.iter().flatten().all(|value| value.is_finite());
+        if !finite(&points)
+            || !finite(&self.newton_roots)
+            || !finite(&self.formula_params)
+            || !self.quaternion_c.iter().all(|value| value.is_finite())
+            || !self.camera_yaw.is_finite()
+        {
+            return Err("coordinates must be finite".to_string());
+        }
+
+        check_range("max_iter", self.max_iter, 0..=MAX_ITERATIONS)?;
+        check_range("rgb_green", self.rgb_green, 0.0..=1.0)?;
+        check_range("rgb_blue", self.rgb_blue, 0.0..=1.0)?;
+        check_range("hsv_saturation", self.hsv_saturation, 0.0..=1.0)?;
+        check_range("hsv_brightness", self.hsv_brightness, 0.0..=1.0)?;
+        check_range("palette_offset", self.palette_offset, 0.0..=1.0)?;
+        check_range("pow", self.pow, -255.0..=255.0)?;
+        check_range("escape_threshold", self.escape_threshold, 1.0..=1e30)?;
+        check_range("convergence_epsilon", self.convergence_epsilon, 1e-9..=0.5)?;
+        check_range(
+            "periodicity_epsilon",
+            self.periodicity_epsilon,
+            1e-12..=0.01,
+        )?;
+        check_range(
+            "newton_roots",
+            self.newton_roots.len(),
+            1..=MAX_NEWTON_ROOTS,
+        )?;
+        check_range("newton_relaxation_x", self.newton_relaxation_x, -3.0..=3.0)?;
+        check_range("newton_relaxation_y", self.newton_relaxation_y, -3.0..=3.0)?;
+        check_range("newton_tolerance", self.newton_tolerance, 0.000_001..=0.5)?;
+        check_range("phoenix_p_x", self.phoenix_p_x, -3.0..=3.0)?;
+        check_range("phoenix_p_y", self.phoenix_p_y, -3.0..=3.0)?;
+        check_range("magnet_tolerance", self.magnet_tolerance, 0.000_001..=0.5)?;
+        check_range(
+            "hybrid_steps",
+            self.hybrid_steps.len(),
+            1..=MAX_HYBRID_STEPS,
+        )?;
+        for step in &self.hybrid_steps {
+            check_range("repeats", step.repeats, 1..=HybridStep::MAX_REPEATS)?;
+        }
+        check_range("lyapunov_warmup", self.lyapunov_warmup, 0..=10_000)?;
+        check_range("lyapunov_iterations", self.lyapunov_iterations, 1..=10_000)?;
+        for limit in self.buddhabrot_limits {
+            check_range("buddhabrot_limits", limit, 1..=100_000)?;
+        }
+        check_range(
+            "buddhabrot_importance",
+            self.buddhabrot_importance,
+            0.0..=1.0,
+        )?;
+        check_range("buddhabrot_gamma", self.buddhabrot_gamma, 0.1..=2.0)?;
+        check_range(
+            "camera_pitch",
+            self.camera_pitch,
+            -MAX_CAMERA_PITCH..=MAX_CAMERA_PITCH,
+        )?;
+        check_range("camera_distance", self.camera_distance, 0.1..=20.0)?;
+        check_range("camera_fov", self.camera_fov, 10.0..=120.0)?;
+        check_range("raymarch_steps", self.raymarch_steps, 16..=2_000)?;
+        check_range("raymarch_iterations", self.raymarch_iterations, 1..=100)?;
+        check_range("domain_contour_base", self.domain_contour_base, 1.1..=10.0)?;
+        check_range(
+            "domain_grid_spacing",
+            self.domain_grid_spacing,
+            0.01..=100.0,
+        )
+    }
+
+    /// Settings saved by an earlier session. Fields that are missing, unknown or no longer valid,
+    /// e.g. after an update, get their defaults instead of discarding everything else
+    pub fn restore(saved: &str) -> Self {
+        let defaults = Self::new();
+        let (Ok(Value::Object(saved)), Ok(Value::Object(mut fields))) =
+            (serde_json::from_str(saved), serde_json::to_value(&defaults))
+        else {
+            return defaults;
+        };
+
+        for (name, value) in saved {
+            let Some(default) = fields.insert(name.clone(), value) else {
+                fields.remove(&name);
+                continue;
+            };
+            if serde_json::from_value::<Self>(Value::Object(fields.clone())).is_err() {
+                log::warn!("Saved setting {name} is invalid, using the default");
+                fields.insert(name, default);
+            }
+        }
+
+        serde_json::from_value(Value::Object(fields)).unwrap_or(defaults)
+    }
+
+    pub fn uniforms(&self) -> Uniforms {
+        let mut newton_roots = [[0.0; 4]; MAX_NEWTON_ROOTS];
+        for (target, root) in newton_roots.iter_mut().zip(&self.newton_roots) {
+            *target = [root[0], root[1], 0.0, 0.0];
+        }
+
+        let mut formula_params = [[0.0; 4]; MAX_FORMULA_PARAMS / 2];
+        for (target, pair) in formula_params
+            .iter_mut()
+            .zip(self.formula_params.chunks_exact(2))
+        {
+            *target = [pair[0][0], pair[0][1], pair[1][0], pair[1][1]];
+        }
+
+        let mut hybrid_steps = [[0; 4]; MAX_HYBRID_STEPS / 4];
+        for (i, step) in self.hybrid_steps.iter().take(MAX_HYBRID_STEPS).enumerate() {
+            hybrid_steps[i / 4][i % 4] = step.encode();
+        }
+
+        let (lyapunov_sequence, lyapunov_sequence_length) = self.lyapunov_sequence_bits();
+
+        // Without Nebulabrot a single channel is accumulated up to the common iteration limit
+        let buddhabrot_limits = if self.buddhabrot_flags.contains(BuddhabrotFlags::NEBULA) {
+            let [red, green, blue] = self.buddhabrot_limits;
+            [red, green, blue, 0]
+        } else {
+            [self.max_iter; 4]
+        };
+
+        Uniforms {
+            max_iter: self.max_iter,
+            zoom: self.zoom,
+            center: [self.center_x, self.center_y, 0.0, 0.0],
+            rgb_green: self.rgb_green,
+            rgb_blue: self.rgb_blue,
+            color_scheme: self.color_scheme.bits(),
+            hsv_saturation: self.hsv_saturation,
+            hsv_brightness: self.hsv_brightness,
+            show_axis: self.show_axis as u8 as u32,
+            escape_threshold: self.escape_threshold,
+            initial_value: [self.initial_value_x, self.initial_value_y, 0.0, 0.0],
+            fractal_type: self.fractal_type.bits(),
+            pow: self.pow,
+            newton_root_count: self.newton_roots.len().min(MAX_NEWTON_ROOTS) as u32,
+            newton_nova: self.newton_nova as u8 as u32,
+            newton_tolerance: self.newton_tolerance,
+            magnet_tolerance: self.magnet_tolerance,
+            hybrid_step_count: self.hybrid_steps.len().min(MAX_HYBRID_STEPS) as u32,
+            newton_relaxation: [self.newton_relaxation_x, self.newton_relaxation_y, 0.0, 0.0],
+            newton_roots,
+            phoenix_p: [self.phoenix_p_x, self.phoenix_p_y, 0.0, 0.0],
+            formula_params,
+            hybrid_steps,
+            render_mode: self.render_mode.bits(),
+            lyapunov_sequence,
+            lyapunov_sequence_length,
+            lyapunov_warmup: self.lyapunov_warmup,
+            lyapunov_iterations: self.lyapunov_iterations,
+            buddhabrot_width: 1,
+            buddhabrot_height: 1,
+            buddhabrot_seed: 0,
+            buddhabrot_flags: self.buddhabrot_flags.bits(),
+            buddhabrot_importance: self.buddhabrot_importance,
+            buddhabrot_gamma: self.buddhabrot_gamma,
+            pad: [0; 4],
+            buddhabrot_limits,
+            quaternion_c: self.quaternion_c,
+            camera_yaw: self.camera_yaw,
+            camera_pitch: self.camera_pitch,
+            camera_distance: self.camera_distance,
+            camera_fov: self.camera_fov.to_radians(),
+            fractal_3d: self.fractal_3d.bits(),
+            raymarch_flags: self.raymarch_flags.bits(),
+            raymarch_steps: self.raymarch_steps,
+            raymarch_iterations: self.raymarch_iterations,
+            domain_flags: self.domain_flags.bits(),
+            domain_contour_base: self.domain_contour_base,
+            domain_grid_spacing: self.domain_grid_spacing,
+            bailout_test: self.bailout_test.bits(),
+            convergence_epsilon: self.convergence_epsilon,
+            periodicity_flags: self.periodicity_flags.bits(),
+            periodicity_epsilon: self.periodicity_epsilon,
+            cardioid_check: self.cardioid_check as u8 as u32,
+            auto_iterations: (self.auto_iterations
+                && self.render_mode.contains(RenderMode::ESCAPE_TIME))
+                as u8 as u32,
+            pad_2: [0; 12],
+            image_rect: [0; 4],
+            raw_data_width: 0,
+            raw_data_height: 0,
+            palette_offset: self.palette_offset,
+            pad_3: [0; 4],
+        }
+    }
+
+    /// Encodes the A/B sequence as bits (B = 1), ignoring other characters.
+    /// An empty sequence falls back to `AB`
+    pub fn lyapunov_sequence_bits(&self) -> (u32, u32) {
+        let mut bits = 0;
+        let mut length = 0;
+        for letter in self
+            .lyapunov_sequence
+            .chars()
+            .filter(|letter| matches!(letter, 'A' | 'B'))
+            .take(MAX_LYAPUNOV_SEQUENCE)
+        {
+            if letter == 'B' {
+                bits |= 1 << length;
+            }
+            length += 1;
+        }
+
+        if length == 0 {
+            (0b10, 2)
+        } else {
+            (bits, length)
+        }
+    }
+
+    /// Ranges of r_A and r_B, i.e. the part of the plane in view
+    pub fn lyapunov_ranges(&self) -> [[f32; 2]; 2] {
+        let center = [self.center_x, self.center_y];
+        [0, 1].map(|i| {
+            let half = PLANE_SIZE[i] / 2.0 / self.zoom;
+            [center[i] - half, center[i] + half]
+        })
+    }
+
+    /// Centers the view on the ranges and zooms so that both fit, the aspect ratio of the view
+    /// widens one of them
+    pub fn show_lyapunov_ranges(&mut self, ranges: [[f32; 2]; 2]) {
+        let [a, b] = ranges;
+        self.center_x = (a[0] + a[1]) / 2.0;
+        self.center_y = (b[0] + b[1]) / 2.0;
+        let zoom_a = PLANE_SIZE[0] / (a[1] - a[0]).abs().max(f32::EPSILON);
+        let zoom_b = PLANE_SIZE[1] / (b[1] - b[0]).abs().max(f32::EPSILON);
+        self.zoom = zoom_a.min(zoom_b);
+    }
+
+    /// Switches the mode. The Lyapunov mode maps the view to values of r, so entering it shows
+    /// [`LYAPUNOV_RANGES`] and leaving it returns to the default view of the plane
+    pub fn switch_render_mode(&mut self, mode: RenderMode) {
+        let was_lyapunov = self.render_mode.contains(RenderMode::LYAPUNOV);
+        let is_lyapunov = mode.contains(RenderMode::LYAPUNOV);
+        self.render_mode = mode;
+
+        if is_lyapunov && !was_lyapunov {
+            self.show_lyapunov_ranges(LYAPUNOV_RANGES);
+        } else if was_lyapunov && !is_lyapunov {
+            let defaults = Self::new();
+            self.center_x = defaults.center_x;
+            self.center_y = defaults.center_y;
+            self.zoom = defaults.zoom;
         }
     }
+
+    /// Roots of `z^3 - 1`
+    pub fn default_newton_roots() -> Vec<[f32; 2]> {
+        let half_sqrt_3 = 3.0_f32.sqrt() / 2.0;
+        vec![[1.0, 0.0], [-0.5, half_sqrt_3], [-0.5, -half_sqrt_3]]
+    }
+}
+
+/// Error of [`UserSettings::check_ranges`] unless `value` lies within `range`. NaN never does
+fn check_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} = {value} is outside {}..={}",
+            range.start(),
+            range.end()
+        ))
+    }
 }
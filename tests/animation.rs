@@ -0,0 +1,83 @@
+use mandelbrot_gpu::animation::{self, Easing, Keyframe, LoopKind};
+use mandelbrot_gpu::user_settings::UserSettings;
+
+fn zoom_in() -> Vec<Keyframe> {
+    let mut settings = UserSettings::new();
+    let start = Keyframe::capture(&settings);
+
+    settings.center_x = -0.75;
+    settings.center_y = 0.1;
+    settings.zoom = 75.0;
+    settings.palette_offset = 0.5;
+    let mut end = Keyframe::capture(&settings);
+    end.duration = 2.0;
+    end.easing = Easing::LINEAR;
+
+    vec![start, end]
+}
+
+#[test]
+fn starts_and_ends_at_the_keyframes() {
+    let keyframes = zoom_in();
+    assert_eq!(animation::view_at(&keyframes, 0.0), Some(keyframes[0]));
+    assert_eq!(animation::view_at(&keyframes, 2.0), Some(keyframes[1]));
+    assert_eq!(animation::view_at(&keyframes, 5.0), Some(keyframes[1]));
+    assert_eq!(animation::view_at(&[], 1.0), None);
+}
+
+#[test]
+fn zooms_by_equal_factors() {
+    let keyframes = zoom_in();
+    let middle = animation::view_at(&keyframes, 1.0).unwrap();
+    assert!((middle.zoom - (0.75f32 * 75.0).sqrt()).abs() < 1e-3);
+    assert!((middle.palette_offset - 0.25).abs() < 1e-6);
+
+    // Most of the way in the plane is covered while the view is still wide
+    let share = (middle.center[0] - keyframes[0].center[0])
+        / (keyframes[1].center[0] - keyframes[0].center[0]);
+    assert!(share > 0.8 && share < 1.0, "{share}");
+}
+
+#[test]
+fn easing_keeps_the_ends() {
+    for easing in [
+        Easing::LINEAR,
+        Easing::EASE_IN,
+        Easing::EASE_OUT,
+        Easing::EASE_IN_OUT,
+    ] {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert_eq!(easing.apply(1.0), 1.0);
+        assert!(easing.apply(0.25) < easing.apply(0.75));
+    }
+}
+
+#[test]
+fn counts_both_ends_as_frames() {
+    let keyframes = zoom_in();
+    assert_eq!(animation::frame_count(&keyframes, 30), 61);
+    assert_eq!(animation::frame_count(&keyframes[..1], 30), 1);
+
+    let last = animation::frame_settings(&UserSettings::new(), &keyframes, 30, 60);
+    assert_eq!(last.zoom, 75.0);
+}
+
+#[test]
+fn loops_return_to_the_start() {
+    let mut settings = UserSettings::new();
+    settings.loop_kind = LoopKind::PALETTE_CYCLE | LoopKind::JULIA_ORBIT | LoopKind::POW_MORPH;
+    settings.loop_frames = 12;
+    settings.palette_offset = 0.3;
+
+    let first = animation::loop_frame_settings(&settings, 0);
+    let after_last = animation::loop_frame_settings(&settings, 12);
+    assert!((first.palette_offset - after_last.palette_offset).abs() < 1e-6);
+    assert!((first.initial_value_x - after_last.initial_value_x).abs() < 1e-6);
+    assert!((first.initial_value_y - after_last.initial_value_y).abs() < 1e-6);
+    assert!((first.pow - after_last.pow).abs() < 1e-5);
+
+    let middle = animation::loop_frame_settings(&settings, 6);
+    assert!((middle.palette_offset - 0.8).abs() < 1e-6);
+    assert!((middle.initial_value_x + settings.loop_orbit_radius).abs() < 1e-6);
+    assert_eq!(middle.pow, settings.pow_animation_to);
+}
@@ -0,0 +1,50 @@
+use mandelbrot_gpu::bookmarks::{
+    BOOKMARKS_VERSION, Bookmark, load_bookmarks, read_bookmarks, save_bookmarks, write_bookmarks,
+};
+use mandelbrot_gpu::user_settings::UserSettings;
+
+#[test]
+fn bookmarks_survive_the_file() {
+    let mut settings = UserSettings::new();
+    settings.center_x = -0.7435;
+    settings.center_y = 0.1314;
+    settings.zoom = 5000.0;
+    let bookmarks = vec![
+        Bookmark::capture("Долина морских коньков".to_string(), &settings),
+        Bookmark::capture("Начало".to_string(), &UserSettings::new()),
+    ];
+
+    let mut file = vec![];
+    write_bookmarks(&bookmarks, &mut file).unwrap();
+    let read = read_bookmarks(file.as_slice()).unwrap();
+    assert_eq!(read, bookmarks);
+
+    // The window stays as it is, only the view changes
+    let mut current = UserSettings::new();
+    current.show_settings = false;
+    let restored = read[0].settings(&current).unwrap();
+    assert_eq!(restored.zoom, 5000.0);
+    assert_eq!(restored.center_x, -0.7435);
+    assert!(!restored.show_settings);
+}
+
+#[test]
+fn newer_files_are_refused() {
+    let file = format!(
+        r#"{{"version": {}, "bookmarks": []}}"#,
+        BOOKMARKS_VERSION + 1
+    );
+    assert!(read_bookmarks(file.as_bytes()).is_err());
+}
+
+#[test]
+fn missing_file_has_no_bookmarks() {
+    let folder = std::env::temp_dir().join(format!("bookmarks_{}", std::process::id()));
+    let path = folder.join("nested").join("bookmarks.json");
+    assert_eq!(load_bookmarks(&path).unwrap(), vec![]);
+
+    let bookmarks = vec![Bookmark::capture("Вид".to_string(), &UserSettings::new())];
+    save_bookmarks(&bookmarks, &path).unwrap();
+    assert_eq!(load_bookmarks(&path).unwrap(), bookmarks);
+    std::fs::remove_dir_all(folder).unwrap();
+}
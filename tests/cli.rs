@@ -0,0 +1,204 @@
+use mandelbrot_gpu::cli::{
+    EXIT_JOB_FAILED, EXIT_NO_ADAPTER, EXIT_USAGE, Job, fractal_type, parse_command_line, read_jobs,
+    split_arguments,
+};
+use mandelbrot_gpu::uniforms::{FractalColorScheme, FractalType};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn args(line: &str) -> impl Iterator<Item = String> {
+    line.split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// File in a directory of its own, so tests running in parallel do not share files
+fn temp_file(test: &str, name: &str, contents: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("mandelbrot_render_{test}"));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn run(args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_mandelbrot_render"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn parses_the_options_of_a_job() {
+    let command_line = parse_command_line(args(
+        "-c -0.5,0.25 -z 4 -i 500 -f julia+burning-ship --color-scheme rgb -s 300x200 \
+         --supersampling 2 -o out.png",
+    ))
+    .unwrap();
+
+    assert!(!command_line.help);
+    assert_eq!(
+        command_line.job,
+        Job {
+            center: Some([-0.5, 0.25]),
+            zoom: Some(4.0),
+            iterations: Some(500),
+            fractal_type: Some(FractalType::JULIA | FractalType::BURNING_SHIP),
+            color_scheme: Some(FractalColorScheme::RGB),
+            size: Some([300, 200]),
+            supersampling: Some(2),
+            output: Some("out.png".into()),
+            ..Job::default()
+        }
+    );
+    assert_eq!(command_line.jobs().unwrap(), vec![command_line.job.clone()]);
+}
+
+#[test]
+fn rejects_invalid_options() {
+    for line in [
+        "--unknown",
+        "-o",
+        "-z many",
+        "-c 1",
+        "-s 0x200",
+        "--supersampling 9",
+        "--raw-output out.txt",
+        "--color-scheme grey",
+        "-f spiral",
+    ] {
+        assert!(parse_command_line(args(line)).is_err(), "{line}");
+    }
+
+    let command_line = parse_command_line(args("-z 2")).unwrap();
+    assert!(command_line.jobs().is_err());
+}
+
+#[test]
+fn fractal_type_takes_one_mode_and_one_variant() {
+    assert_eq!(
+        fractal_type("newton").unwrap(),
+        FractalType::MANDELBROT | FractalType::NEWTON
+    );
+    assert_eq!(fractal_type("julia").unwrap(), FractalType::JULIA);
+    assert_eq!(
+        fractal_type("tricorn+julia").unwrap(),
+        FractalType::JULIA | FractalType::TRICORN
+    );
+
+    assert!(fractal_type("mandelbrot+julia+newton").is_err());
+    assert!(fractal_type("julia+julia").is_err());
+    assert!(fractal_type("burning-ship+tricorn").is_err());
+}
+
+#[test]
+fn jobs_file_adds_to_the_command_line() {
+    let path = temp_file(
+        "jobs_file",
+        "jobs.txt",
+        "# Two zooms of the same view\n\
+         \n\
+         -z 2 -o first.png\n\
+         -z 8 -i 2000 --raw-output second.npy\n",
+    );
+    let base = parse_command_line(args("-c -0.75,0.1 -i 300")).unwrap().job;
+
+    let jobs = read_jobs(&path, &base).unwrap();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].center, Some([-0.75, 0.1]));
+    assert_eq!(jobs[0].zoom, Some(2.0));
+    assert_eq!(jobs[0].iterations, Some(300));
+    assert_eq!(jobs[0].output, Some("first.png".into()));
+    assert_eq!(jobs[1].center, Some([-0.75, 0.1]));
+    assert_eq!(jobs[1].iterations, Some(2000));
+    assert_eq!(jobs[1].raw_output, Some("second.npy".into()));
+}
+
+#[test]
+fn quotes_keep_spaces_in_arguments() {
+    assert_eq!(
+        split_arguments(r#"-o "my renders/deep zoom.png"  -p 'old view.json' -z 2"#).unwrap(),
+        [
+            "-o",
+            "my renders/deep zoom.png",
+            "-p",
+            "old view.json",
+            "-z",
+            "2"
+        ]
+    );
+    assert_eq!(
+        split_arguments(r#"-o "it's.png" ''"#).unwrap(),
+        ["-o", "it's.png", ""]
+    );
+    assert!(split_arguments(r#"-o "unclosed.png"#).is_err());
+
+    let path = temp_file("quotes", "jobs.txt", "-o \"deep zoom.png\" -z 2\n");
+    let jobs = read_jobs(&path, &Job::default()).unwrap();
+    assert_eq!(jobs[0].output, Some("deep zoom.png".into()));
+}
+
+#[test]
+fn jobs_file_errors_name_the_line() {
+    let path = temp_file("jobs_errors", "jobs.txt", "-o first.png\n\n-z 2\n");
+    let error = read_jobs(&path, &Job::default()).unwrap_err().to_string();
+    assert!(error.ends_with("jobs.txt:3: missing --output"), "{error}");
+
+    let path = temp_file("jobs_errors", "unknown.txt", "-o first.png --fast\n");
+    let error = read_jobs(&path, &Job::default()).unwrap_err().to_string();
+    assert!(
+        error.ends_with("unknown.txt:1: unknown option --fast"),
+        "{error}"
+    );
+
+    assert!(read_jobs(Path::new("missing/jobs.txt"), &Job::default()).is_err());
+}
+
+#[test]
+fn job_settings_apply_the_options_to_the_parameter_file() {
+    let path = temp_file(
+        "job_settings",
+        "params.json",
+        r#"{"zoom": 16.0, "max_iter": 900, "auto_iterations": true}"#,
+    );
+    let job = Job {
+        params: Some(path),
+        iterations: Some(100),
+        ..Job::default()
+    };
+
+    let settings = job.settings().unwrap();
+    assert_eq!(settings.zoom, 16.0);
+    assert_eq!(settings.max_iter, 100);
+    assert!(!settings.auto_iterations);
+}
+
+#[test]
+fn exit_codes() {
+    let (code, _) = run(&["--help"]);
+    assert_eq!(code, Some(0));
+
+    let (code, stderr) = run(&["--unknown"]);
+    assert_eq!(code, Some(EXIT_USAGE.into()), "{stderr}");
+
+    let (code, stderr) = run(&["-z", "2"]);
+    assert_eq!(code, Some(EXIT_USAGE.into()), "{stderr}");
+
+    let jobs = temp_file("exit_codes", "jobs.txt", "-z 2\n");
+    let (code, stderr) = run(&["--jobs", jobs.to_str().unwrap()]);
+    assert_eq!(code, Some(EXIT_USAGE.into()), "{stderr}");
+
+    // Fails after the renderer is created, without an adapter the exit code says so instead
+    let output = jobs.with_file_name("out.png");
+    let (code, stderr) = run(&["--params", "missing.json", "-o", output.to_str().unwrap()]);
+    if code == Some(EXIT_NO_ADAPTER.into()) && std::env::var_os("SKIP_GPU_TESTS").is_some() {
+        return;
+    }
+    assert_eq!(code, Some(EXIT_JOB_FAILED.into()), "{stderr}");
+    assert!(stderr.contains("missing.json"), "{stderr}");
+}
@@ -0,0 +1,524 @@
+//! Renders through whatever adapter is available, a software one included.
+//! Fails without any adapter, unless `SKIP_GPU_TESTS` is set on machines known to have none.
+
+use mandelbrot_gpu::fractal_renderer::{FractalRenderer, Image, RawData, RendererError};
+use mandelbrot_gpu::image_export::{
+    LoopExport, PngExport, read_parameters, render_png, render_raw_data, save_raw_data, write_apng,
+    write_gif, write_npy, write_png,
+};
+use mandelbrot_gpu::uniforms::{BuddhabrotFlags, FractalColorScheme, FractalType, RenderMode};
+use mandelbrot_gpu::user_settings::UserSettings;
+
+/// Opt-out for machines without any graphics adapter, other errors still fail
+const SKIP_VARIABLE: &str = "SKIP_GPU_TESTS";
+
+fn renderer() -> Option<FractalRenderer> {
+    match FractalRenderer::new() {
+        Ok(renderer) => Some(renderer),
+        Err(error @ RendererError::NoAdapter(_)) => {
+            skip(error);
+            None
+        }
+        Err(error) => panic!("{error}"),
+    }
+}
+
+/// Panics unless the tests may be skipped
+fn skip(error: impl std::fmt::Display) {
+    if std::env::var_os(SKIP_VARIABLE).is_none() {
+        panic!(
+            "{error}, set {SKIP_VARIABLE}=1 to skip the GPU tests on machines without an adapter"
+        );
+    }
+    eprintln!("skipped: {error}");
+}
+
+#[test]
+fn renders_default_view() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = UserSettings::new();
+    // 150 pixels make rows that need padding in the readback buffer
+    let image = renderer
+        .render_to_image(&settings.uniforms(), 150, 100)
+        .unwrap();
+
+    assert_eq!((image.width, image.height), (150, 100));
+    assert_eq!(image.pixels.len(), 150 * 100 * 4);
+    let pixel = |x: usize, y: usize| &image.pixels[(y * 150 + x) * 4..][..4];
+    assert_eq!(
+        pixel(75, 50),
+        [0, 0, 0, 255],
+        "the center lies inside the set"
+    );
+    assert_ne!(pixel(0, 0)[..3], [0, 0, 0], "the corner escapes");
+}
+
+#[test]
+fn axes_are_opaque_white() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let mut settings = UserSettings::new();
+    let without_axes = renderer
+        .render_to_image(&settings.uniforms(), 150, 100)
+        .unwrap();
+    settings.show_axis = true;
+    let with_axes = renderer
+        .render_to_image(&settings.uniforms(), 150, 100)
+        .unwrap();
+
+    let axis_pixels = with_axes
+        .pixels
+        .chunks_exact(4)
+        .zip(without_axes.pixels.chunks_exact(4))
+        .filter(|(with, without)| with != without)
+        .map(|(with, _)| with)
+        .collect::<Vec<_>>();
+    assert!(!axis_pixels.is_empty());
+    assert!(axis_pixels.iter().all(|pixel| *pixel == [255; 4]));
+}
+
+#[test]
+fn rejects_empty_image() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    assert!(
+        renderer
+            .render_to_image(&UserSettings::new().uniforms(), 0, 100)
+            .is_err()
+    );
+}
+
+/// A detailed view, where any offset between the tiles would change pixels
+fn seahorse_valley() -> UserSettings {
+    let mut settings = UserSettings::new();
+    settings.center_x = -0.75;
+    settings.center_y = 0.1;
+    settings.zoom = 20.0;
+    settings.max_iter = 300;
+    settings.show_axis = true;
+    settings
+}
+
+#[test]
+fn tiles_match_single_pass() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let uniforms = seahorse_valley().uniforms();
+    let single_pass = renderer.render_to_image(&uniforms, 301, 203).unwrap();
+
+    // Uneven tiles leave narrow tiles at the right and bottom edges
+    renderer.tile_size = 64;
+    let tiled = renderer.render_to_image(&uniforms, 301, 203).unwrap();
+
+    assert!(
+        single_pass.pixels == tiled.pixels,
+        "tiles must meet without seams"
+    );
+}
+
+#[test]
+fn streamed_png_matches_single_pass() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = seahorse_valley();
+    let single_pass = renderer
+        .render_to_image(&settings.uniforms(), 302, 202)
+        .unwrap()
+        .downsample(2);
+
+    renderer.tile_size = 50;
+    let mut png = vec![];
+    render_png(&mut renderer, &settings, 151, 101, 2, &mut png).unwrap();
+
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height), (151, 101));
+    assert!(
+        single_pass.pixels == pixels,
+        "strips must meet without seams"
+    );
+}
+
+#[test]
+fn png_export_renders_wide_images_in_low_strips() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = seahorse_valley();
+    let single_pass = renderer
+        .render_to_image(&settings.uniforms(), 302, 202)
+        .unwrap()
+        .downsample(2);
+
+    // 302 samples per row make strips of 8 rows, about one tile of 50 x 50
+    renderer.tile_size = 50;
+    let path = std::env::temp_dir().join("mandelbrot_gpu_png_export.png");
+    let mut export = PngExport::create(&mut renderer, &settings, 151, 101, 2, &path).unwrap();
+    let mut strips = 0;
+    while !export.is_done() {
+        let progress = export.progress();
+        export.render_strip(&mut renderer).unwrap();
+        assert!(export.progress() > progress);
+        strips += 1;
+    }
+    export.finish().unwrap();
+    assert_eq!(strips, 202usize.div_ceil(8));
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut reader = png::Decoder::new(std::io::BufReader::new(file))
+        .read_info()
+        .unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        single_pass.pixels == pixels,
+        "strips must meet without seams"
+    );
+}
+
+#[test]
+fn png_keeps_parameters() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let mut settings = seahorse_valley();
+    settings.fractal_type = FractalType::JULIA | FractalType::BURNING_SHIP;
+    settings.formula = "z^3 + c".to_string();
+    let mut png = vec![];
+    render_png(&mut renderer, &settings, 30, 20, 1, &mut png).unwrap();
+
+    let parameters = read_parameters(png.as_slice()).unwrap().unwrap();
+    let restored = UserSettings::new()
+        .with_image_parameters(&parameters)
+        .unwrap();
+    assert_eq!(restored.formula, settings.formula);
+    assert!(
+        bytemuck::bytes_of(&restored.uniforms()) == bytemuck::bytes_of(&settings.uniforms()),
+        "the restored view must render the same image"
+    );
+}
+
+#[test]
+fn foreign_png_has_no_parameters() {
+    let image = Image {
+        width: 2,
+        height: 2,
+        pixels: vec![255; 16],
+    };
+    let mut png = vec![];
+    write_png(&image, &mut png).unwrap();
+
+    assert!(read_parameters(png.as_slice()).unwrap().is_none());
+    assert!(read_parameters(&b"not a png"[..]).is_err());
+}
+
+#[test]
+fn raw_data_matches_image() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = seahorse_valley();
+    let image = renderer
+        .render_to_image(&settings.uniforms(), 301, 203)
+        .unwrap();
+    let raw = render_raw_data(&mut renderer, &settings, 301, 203).unwrap();
+
+    renderer.tile_size = 64;
+    let tiled = render_raw_data(&mut renderer, &settings, 301, 203).unwrap();
+    assert!(raw.values == tiled.values, "tiles must meet without seams");
+
+    let iterations: Vec<f32> = raw.channel(0).collect();
+    let distances: Vec<f32> = raw.channel(2).collect();
+    assert!(iterations.contains(&-1.0) && iterations.iter().any(|&time| time > 0.0));
+    let mut colored_inside = 0;
+    for (index, pixel) in image.pixels.chunks_exact(4).enumerate() {
+        if iterations[index] == -1.0 {
+            colored_inside += (pixel[..3] != [0, 0, 0]) as usize;
+            assert_eq!(distances[index], 0.0);
+        } else {
+            assert!(
+                distances[index] > 0.0,
+                "escaped orbits estimate the distance"
+            );
+        }
+    }
+    // The compute and the fragment shader may round a few orbits on the boundary differently
+    assert!(
+        colored_inside < iterations.len() / 1000,
+        "{colored_inside} inside pixels are not black"
+    );
+}
+
+#[test]
+fn raw_data_needs_escape_time() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let mut settings = UserSettings::new();
+    settings.render_mode = RenderMode::LYAPUNOV;
+    assert!(render_raw_data(&mut renderer, &settings, 30, 20).is_err());
+}
+
+#[test]
+fn distance_estimate_follows_the_complex_derivative() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    // -2.1 lies 0.1 left of the tip of the set. Its orbit changes sign, where the moduli of
+    // the derivative alone would add up to an estimate of about 0.05
+    let mut settings = UserSettings::new();
+    settings.center_x = -2.1;
+    settings.center_y = 0.0;
+    settings.zoom = 10_000.0;
+    settings.escape_threshold = 1e4;
+    let raw = render_raw_data(&mut renderer, &settings, 3, 3).unwrap();
+
+    let distance = raw.channel(2).nth(4).unwrap();
+    assert!((distance - 0.097).abs() < 0.005, "{distance}");
+}
+
+#[test]
+fn variants_have_no_distance_estimate() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    for fractal_type in [
+        FractalType::MANDELBROT | FractalType::BURNING_SHIP,
+        FractalType::JULIA | FractalType::BURNING_SHIP,
+    ] {
+        let mut settings = UserSettings::new();
+        settings.fractal_type = fractal_type;
+        let raw = render_raw_data(&mut renderer, &settings, 30, 20).unwrap();
+
+        let mut escaped = 0;
+        for (iterations, distance) in raw.channel(0).zip(raw.channel(2)) {
+            if iterations >= 0.0 {
+                assert_eq!(distance, -1.0, "{fractal_type}");
+                escaped += 1;
+            }
+        }
+        assert!(escaped > 0, "{fractal_type}");
+    }
+}
+
+#[test]
+fn raw_data_files_are_written_in_strips() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = seahorse_valley();
+    let raw = render_raw_data(&mut renderer, &settings, 61, 43).unwrap();
+    renderer.tile_size = 16;
+    let directory = std::env::temp_dir().join("mandelbrot_gpu_raw_strips");
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let path = directory.join("raw.npy");
+    assert_eq!(
+        save_raw_data(&mut renderer, &settings, 61, 43, &path).unwrap(),
+        std::slice::from_ref(&path)
+    );
+    let mut npy = vec![];
+    write_npy(&raw, &mut npy).unwrap();
+    assert!(std::fs::read(&path).unwrap() == npy);
+
+    let path = directory.join("raw.exr");
+    save_raw_data(&mut renderer, &settings, 61, 43, &path).unwrap();
+    let image = exr::prelude::read_all_flat_layers_from_file(&path).unwrap();
+    let channels = &image.layer_data[0].channel_data.list;
+    for (channel, name) in RawData::CHANNELS.iter().enumerate() {
+        let samples = &channels
+            .iter()
+            .find(|samples| samples.name == **name)
+            .unwrap()
+            .sample_data;
+        let values: Vec<f32> = samples.values_as_f32().collect();
+        assert!(values == raw.channel(channel).collect::<Vec<_>>(), "{name}");
+    }
+
+    let path = directory.join("raw.png");
+    let paths = save_raw_data(&mut renderer, &settings, 61, 43, &path).unwrap();
+    assert_eq!(paths.len(), RawData::CHANNELS.len());
+    for path in paths {
+        let decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut samples = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut samples).unwrap();
+        assert_eq!((frame.width, frame.height), (61, 43), "{}", path.display());
+    }
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn huge_raw_data_is_an_error() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = UserSettings::new();
+    let size = 1 << 31;
+    assert!(matches!(
+        renderer.render_raw_data(&settings.uniforms(), size, size),
+        Err(RendererError::OutOfMemory { .. })
+    ));
+}
+
+#[test]
+fn npy_header_is_aligned() {
+    let raw = RawData {
+        width: 30,
+        height: 20,
+        values: vec![0.0; 30 * 20 * RawData::CHANNELS.len()],
+    };
+    let mut npy = vec![];
+    write_npy(&raw, &mut npy).unwrap();
+
+    assert!(npy.starts_with(b"\x93NUMPY\x01\x00"));
+    let header_size = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    let header = std::str::from_utf8(&npy[10..10 + header_size]).unwrap();
+    assert!(header.contains("'shape': (20, 30, 5)"), "{header}");
+    assert!(header.ends_with('\n'));
+    assert_eq!((10 + header_size) % 64, 0);
+    assert_eq!(npy.len(), 10 + header_size + 20 * 30 * 5 * 4);
+}
+
+fn small_loop() -> UserSettings {
+    let mut settings = UserSettings::new();
+    settings.loop_frames = 3;
+    settings.loop_size = [30, 20];
+    settings
+}
+
+#[test]
+fn apng_loops_every_frame() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let mut apng = vec![];
+    write_apng(&mut renderer, &small_loop(), &mut apng).unwrap();
+
+    let reader = png::Decoder::new(apng.as_slice()).read_info().unwrap();
+    let animation = reader.info().animation_control().unwrap();
+    assert_eq!((animation.num_frames, animation.num_plays), (3, 0));
+}
+
+#[test]
+fn gif_loops_every_frame() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let mut gif = vec![];
+    write_gif(&mut renderer, &small_loop(), &mut gif).unwrap();
+
+    let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+    assert_eq!((decoder.width(), decoder.height()), (30, 20));
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!(frame.delay, 100);
+        frames += 1;
+    }
+    assert_eq!(frames, 3);
+}
+
+#[test]
+fn loop_export_matches_the_whole_loop() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    let settings = small_loop();
+    let mut whole = vec![];
+    write_apng(&mut renderer, &settings, &mut whole).unwrap();
+
+    let mut apng = vec![];
+    let mut export = LoopExport::apng(&settings, &mut apng).unwrap();
+    assert_eq!((export.next_frame(), export.frame_count()), (0, 3));
+    while !export.is_done() {
+        export.render_frame(&mut renderer).unwrap();
+    }
+    assert_eq!(export.next_frame(), 3);
+    export.finish().unwrap();
+    assert!(apng == whole, "frame by frame must write the same file");
+}
+
+fn buddhabrot() -> UserSettings {
+    let mut settings = UserSettings::new();
+    settings.render_mode = RenderMode::BUDDHABROT;
+    settings.max_iter = 100;
+    settings.auto_iterations = false;
+    // Linear gray levels, so pixels compare as densities
+    settings.color_scheme = FractalColorScheme::RGB;
+    settings.rgb_green = 1.0;
+    settings.rgb_blue = 1.0;
+    settings.buddhabrot_gamma = 1.0;
+    settings
+}
+
+#[test]
+fn buddhabrot_fits_the_storage_limit() {
+    // A device with a small storage binding limit, like the default limits of a window
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let adapter = match pollster::block_on(instance.request_adapter(&Default::default())) {
+        Ok(adapter) => adapter,
+        Err(error) => {
+            skip(error);
+            return;
+        }
+    };
+    let limits = wgpu::Limits {
+        max_storage_buffer_binding_size: 1 << 20,
+        ..adapter.limits()
+    };
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        required_limits: limits,
+        ..Default::default()
+    }))
+    .unwrap();
+    let mut renderer = FractalRenderer::from_device(device, queue, adapter.get_info());
+    renderer.buddhabrot_samples = 1 << 16;
+
+    // 12 bytes of density per pixel would need 1.4 MiB
+    let image = renderer
+        .render_to_image(&buddhabrot().uniforms(), 400, 300)
+        .unwrap();
+    assert!(image.pixels.chunks_exact(4).any(|pixel| pixel[0] > 0));
+}
+
+#[test]
+fn importance_sampling_keeps_the_density() {
+    let Some(mut renderer) = renderer() else {
+        return;
+    };
+    renderer.buddhabrot_samples = 1 << 20;
+    let (width, height) = (60, 40);
+    // Share of the whole brightness in each 10×10 block
+    let mut blocks = |importance: f32| {
+        let mut settings = buddhabrot();
+        settings.buddhabrot_flags = BuddhabrotFlags::IMPORTANCE;
+        settings.buddhabrot_importance = importance;
+        let image = renderer
+            .render_to_image(&settings.uniforms(), width, height)
+            .unwrap();
+        let mut blocks = [0.0; 24];
+        for (index, pixel) in image.pixels.chunks_exact(4).enumerate() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            blocks[(y / 10 * 6 + x / 10) as usize] += pixel[0] as f64;
+        }
+        let total: f64 = blocks.iter().sum();
+        blocks.iter().map(|block| block / total).collect::<Vec<_>>()
+    };
+
+    let uniform = blocks(0.0);
+    let importance = blocks(0.8);
+    for (uniform, importance) in uniform.iter().zip(&importance) {
+        assert!(
+            (uniform - importance).abs() < 0.01 + 0.15 * uniform,
+            "{uniform} != {importance}"
+        );
+    }
+}
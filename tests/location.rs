@@ -0,0 +1,83 @@
+use mandelbrot_gpu::location::{LocationError, decode_location, encode_location};
+use mandelbrot_gpu::uniforms::FractalType;
+use mandelbrot_gpu::user_settings::UserSettings;
+
+fn shared_view() -> UserSettings {
+    let mut settings = UserSettings::new();
+    settings.center_x = -0.7435;
+    settings.center_y = 0.1314;
+    settings.zoom = 5000.0;
+    settings.max_iter = 2500;
+    settings.fractal_type = FractalType::JULIA;
+    settings
+}
+
+#[test]
+fn location_restores_the_view() {
+    let settings = shared_view();
+    let location = encode_location(&settings);
+    assert!(location.starts_with("mandelbrot:1:"), "{location}");
+    assert!(location.len() < 200, "{location}");
+
+    let mut current = UserSettings::new();
+    current.show_settings = false;
+    current.zoom = 2.0;
+    let restored = decode_location(&format!("  {location}\n"), &current).unwrap();
+    assert_eq!(restored.image_parameters(), {
+        let mut expected = settings.clone();
+        expected.auto_iterations = false;
+        expected.image_parameters()
+    });
+    assert!(!restored.show_settings);
+}
+
+#[test]
+fn cut_off_locations_are_refused() {
+    let location = encode_location(&shared_view());
+    let data_start = "mandelbrot:1:".len();
+    for length in data_start..location.len() {
+        let result = decode_location(&location[..length], &UserSettings::new()).map(|_| ());
+        assert!(
+            matches!(result, Err(LocationError::Truncated)),
+            "{length}: {result:?}"
+        );
+    }
+
+    let error = decode_location(&location[..data_start + 5], &UserSettings::new()).err();
+    assert_eq!(error.unwrap().to_string(), "место обрезано");
+}
+
+#[test]
+fn damaged_and_foreign_strings_are_refused() {
+    let location = encode_location(&shared_view());
+    let mut damaged = location.clone().into_bytes();
+    let middle = damaged.len() - 10;
+    damaged[middle] = if damaged[middle] == b'A' { b'B' } else { b'A' };
+    let damaged = String::from_utf8(damaged).unwrap();
+    assert!(decode_location(&damaged, &UserSettings::new()).is_err());
+
+    let settings = UserSettings::new();
+    assert!(matches!(
+        decode_location("-0.74 0.13 5000", &settings),
+        Err(LocationError::NotLocation)
+    ));
+    assert!(matches!(
+        decode_location(&location.replacen(":1:", ":0:", 1), &settings),
+        Err(LocationError::Outdated(0))
+    ));
+    assert!(matches!(
+        decode_location(&location.replacen(":1:", ":2:", 1), &settings),
+        Err(LocationError::Newer(2))
+    ));
+}
+
+#[test]
+fn out_of_range_locations_are_refused() {
+    let mut settings = shared_view();
+    settings.camera_pitch = 3.0;
+    let result = decode_location(&encode_location(&settings), &UserSettings::new()).map(|_| ());
+    assert!(
+        matches!(result, Err(LocationError::Parameters(_))),
+        "{result:?}"
+    );
+}
@@ -0,0 +1,84 @@
+use mandelbrot_gpu::uniforms::{FractalColorScheme, FractalType, RenderMode};
+use mandelbrot_gpu::user_settings::UserSettings;
+
+#[test]
+fn restores_saved_settings() {
+    let mut settings = UserSettings::new();
+    settings.zoom = 42.0;
+    settings.color_scheme = FractalColorScheme::RGB;
+    settings.fractal_type = FractalType::JULIA | FractalType::BURNING_SHIP;
+    settings.show_settings = false;
+
+    let restored = UserSettings::restore(&serde_json::to_string(&settings).unwrap());
+    assert_eq!(restored.zoom, 42.0);
+    assert_eq!(restored.color_scheme, FractalColorScheme::RGB);
+    assert_eq!(restored.fractal_type, settings.fractal_type);
+    assert!(!restored.show_settings);
+}
+
+#[test]
+fn old_settings_keep_what_still_fits() {
+    let defaults = UserSettings::new();
+    // Written by a version with a removed field and a field whose type has changed since
+    let restored = UserSettings::restore(
+        r#"{"zoom": 3.5, "removed_setting": true, "max_iter": "many", "color_scheme": "RGB"}"#,
+    );
+
+    assert_eq!(restored.zoom, 3.5);
+    assert_eq!(restored.color_scheme, FractalColorScheme::RGB);
+    assert_eq!(restored.max_iter, defaults.max_iter);
+    assert_eq!(restored.center_x, defaults.center_x);
+}
+
+#[test]
+fn unreadable_settings_give_defaults() {
+    let restored = UserSettings::restore("not json");
+    assert_eq!(restored.zoom, UserSettings::new().zoom);
+}
+
+#[test]
+fn out_of_range_parameters_are_refused() {
+    let settings = UserSettings::new();
+    for parameters in [
+        r#"{"camera_pitch": 2.0}"#,
+        r#"{"zoom": 0.0}"#,
+        r#"{"zoom": -3.0}"#,
+        r#"{"newton_roots": []}"#,
+        r#"{"max_iter": 4000000000}"#,
+        r#"{"escape_threshold": 0.5}"#,
+        r#"{"hybrid_steps": []}"#,
+    ] {
+        let error = settings.with_image_parameters(parameters).map(|_| ());
+        assert!(error.is_err(), "{parameters}");
+    }
+
+    let restored = settings
+        .with_image_parameters(r#"{"camera_pitch": -1.5, "zoom": 1e6}"#)
+        .unwrap();
+    assert_eq!([restored.camera_pitch, restored.zoom], [-1.5, 1e6]);
+}
+
+#[test]
+fn lyapunov_mode_shows_values_of_r() {
+    let mut settings = UserSettings::new();
+    settings.switch_render_mode(RenderMode::LYAPUNOV);
+    let [a, b] = settings.lyapunov_ranges();
+    // The view is wider than high, so r_A gets the wider range
+    assert!(a[0] <= 2.0 && a[1] >= 4.0, "{a:?}");
+    assert_eq!(b, [2.0, 4.0]);
+
+    settings.show_lyapunov_ranges([[3.0, 3.9], [3.4, 3.5]]);
+    let [a, b] = settings.lyapunov_ranges();
+    assert!(
+        (a[0] - 3.0).abs() < 1e-5 && (a[1] - 3.9).abs() < 1e-5,
+        "{a:?}"
+    );
+    assert!(b[0] <= 3.4 && b[1] >= 3.5, "{b:?}");
+
+    settings.switch_render_mode(RenderMode::ESCAPE_TIME);
+    let defaults = UserSettings::new();
+    assert_eq!(
+        [settings.center_x, settings.center_y, settings.zoom],
+        [defaults.center_x, defaults.center_y, defaults.zoom]
+    );
+}